@@ -0,0 +1,335 @@
+//! Direct Sound audio, playing [`Sample`]s mixed in software, see [`Mixer`].
+//!
+//! The GBA has two Direct Sound channels, each fed by a small FIFO of signed
+//! 8 bits samples. A timer sets the sample rate: each time it overflows, the
+//! channels play the next sample of their FIFO. When a FIFO is half empty,
+//! it requests a DMA transfer to refill it.
+//!
+//! This driver plays FIFO A on the left speaker and FIFO B on the right one,
+//! refilled by [`dma::Channel::_1`] and [`dma::Channel::_2`], at the
+//! [`SAMPLE_RATE`] set by [`timer::Id::_0`]. Enable it with
+//! [`ConsoleState::enable_audio`], then play sounds with [`Mixer::play`]
+//! on [`ConsoleState::audio`]:
+//!
+//! ```ignore
+//! const SHOOT: Sample = hal::sample!("../sounds/shoot.pcm", 8192);
+//!
+//! if console.audio.is_enabled() || console.enable_audio() {
+//!     let pan = Params { pan: -32, ..Params::DEFAULT };
+//!     console.audio.play(SHOOT, pan);
+//! }
+//! ```
+//!
+//! # Implementation
+//!
+//! Exactly [`BUFFER_LEN`] samples are played each frame. Each channel has
+//! two buffers of [`BUFFER_LEN`] samples: one is played while the other
+//! is mixed. [`crate::exec::full_game`] swaps them at the start of vblank,
+//! and mixes the next ones at the end of the frame, after the `*_draw` call.
+//! Up to [`MAX_VOICES`] samples can play at the same time.
+//!
+//! If a frame takes longer than a vblank, the DMA plays past the end of the
+//! buffer until the next swap, causing an audible glitch.
+//!
+//! For cheaper, chiptune sounds, see the [`psg`] channels.
+use core::cell::UnsafeCell;
+
+use const_default::ConstDefault;
+use volmatrix::{
+    dma::{self, Channel, Control, Step, Timing, Unit},
+    rw::VolAddress,
+};
+
+use crate::{profile::FRAME_CYCLES, timer};
+
+#[cfg(doc)]
+use crate::exec::ConsoleState;
+
+pub mod psg;
+mod voice;
+
+pub use voice::{Params, Sample, BASE_PITCH, MAX_PAN, MAX_SAMPLE_LEN, MAX_SAMPLE_RATE, MAX_VOLUME};
+
+#[doc(hidden)]
+pub use include_const_aligned as align;
+
+/// How many samples are played each frame.
+pub const BUFFER_LEN: usize = 304;
+/// CPU cycles between two samples.
+const SAMPLE_CYCLES: u16 = 924;
+/// How many samples are played each second, about 18157 Hz.
+pub const SAMPLE_RATE: u32 = (1 << 24) / SAMPLE_CYCLES as u32;
+/// How many [`Sample`]s can play at the same time.
+pub const MAX_VOICES: usize = 8;
+
+const _: () = assert!(
+    BUFFER_LEN as u32 * SAMPLE_CYCLES as u32 == FRAME_CYCLES,
+    "Exactly BUFFER_LEN samples must be played each frame",
+);
+
+// SAFETY: all of the following are valid GBA memory-mapped registers
+// of the given size.
+const SOUNDCNT_H: VolAddress<u16> = unsafe { VolAddress::new(0x0400_0082) };
+const SOUNDCNT_X: VolAddress<u16> = unsafe { VolAddress::new(0x0400_0084) };
+const FIFO_A: usize = 0x0400_00A0;
+const FIFO_B: usize = 0x0400_00A4;
+
+const MASTER_ENABLE: u16 = 1 << 7;
+/// Full volume FIFO A on the left speaker, and FIFO B on the right one,
+/// both using timer 0, resetting both FIFOs.
+const DIRECT_SOUND: u16 = 1 << 2 | 1 << 3 | 1 << 9 | 1 << 11 | 1 << 12 | 1 << 15;
+/// All `SOUNDCNT_H` bits controlling Direct Sound.
+const DIRECT_SOUND_MASK: u16 = 0xFF0C;
+
+/// The FIFO DMA requests transfer 4 words, ignoring the transfer count.
+const FIFO_DMA: Control = Control::new(Unit::Word, Timing::Special)
+    .with_dest(Step::Fixed)
+    .with_repeat(true);
+
+#[repr(C, align(4))]
+struct Buffers {
+    /// The samples played by each channel, see [module-level doc](self).
+    left: [[i8; BUFFER_LEN]; 2],
+    right: [[i8; BUFFER_LEN]; 2],
+    /// Voices are added here before clamping them to `left` and `right`.
+    mix_left: [i16; BUFFER_LEN],
+    mix_right: [i16; BUFFER_LEN],
+}
+struct Shared(UnsafeCell<Buffers>);
+// SAFETY: the buffers are only accessed by the single `Mixer` in
+// `ConsoleState`, and the buffer read by DMA is never written to.
+unsafe impl Sync for Shared {}
+
+static BUFFERS: Shared = Shared(UnsafeCell::new(Buffers {
+    left: [[0; BUFFER_LEN]; 2],
+    right: [[0; BUFFER_LEN]; 2],
+    mix_left: [0; BUFFER_LEN],
+    mix_right: [0; BUFFER_LEN],
+}));
+
+/// A voice played with [`Mixer::play`].
+///
+/// Once the voice stops, its id is invalid, and methods using it do nothing.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct VoiceId {
+    index: u8,
+    generation: u8,
+}
+
+/// The software mixer, accessible as [`ConsoleState::audio`].
+///
+/// See [module-level doc](self) for details.
+pub struct Mixer {
+    voices: [Option<voice::Voice>; MAX_VOICES],
+    next_generation: u8,
+    /// The buffer currently played.
+    front: usize,
+    /// The sample rate timer, `None` when audio is disabled.
+    timer: Option<timer::Slot>,
+}
+impl ConstDefault for Mixer {
+    const DEFAULT: Self = Self {
+        voices: [None; MAX_VOICES],
+        next_generation: 0,
+        front: 0,
+        timer: None,
+    };
+}
+impl Mixer {
+    /// Whether audio is enabled, see [`ConsoleState::enable_audio`].
+    #[must_use]
+    pub const fn is_enabled(&self) -> bool {
+        self.timer.is_some()
+    }
+    /// Start playing `sample` with `params`, returns `None` if audio is
+    /// disabled, or if [`MAX_VOICES`] samples are already playing.
+    // allow: there are less than 256 voices.
+    #[allow(clippy::cast_possible_truncation)]
+    pub fn play(&mut self, sample: Sample, params: Params) -> Option<VoiceId> {
+        if !self.is_enabled() {
+            return None;
+        }
+        let index = self.voices.iter().position(Option::is_none)?;
+        let generation = self.next_generation;
+        self.next_generation = generation.wrapping_add(1);
+        self.voices[index] = Some(voice::Voice::new(sample, params, generation));
+        Some(VoiceId { index: index as u8, generation })
+    }
+    fn voice_mut(&mut self, id: VoiceId) -> Option<&mut voice::Voice> {
+        let voice = self.voices[usize::from(id.index)].as_mut()?;
+        (voice.generation == id.generation).then_some(voice)
+    }
+    /// Whether `voice` is still playing.
+    #[must_use]
+    pub fn is_playing(&self, voice: VoiceId) -> bool {
+        matches!(
+            &self.voices[usize::from(voice.index)],
+            Some(playing) if playing.generation == voice.generation,
+        )
+    }
+    /// The parameters of `voice`, `None` if it stopped.
+    #[must_use]
+    pub fn params(&self, voice: VoiceId) -> Option<Params> {
+        match &self.voices[usize::from(voice.index)] {
+            Some(playing) if playing.generation == voice.generation => Some(playing.params),
+            Some(_) | None => None,
+        }
+    }
+    /// Change the volume, panning and pitch of `voice`, starting with
+    /// the next frame.
+    pub fn set_params(&mut self, voice: VoiceId, params: Params) {
+        if let Some(voice) = self.voice_mut(voice) {
+            voice.set_params(params);
+        }
+    }
+    /// Stop playing `voice`.
+    pub fn stop(&mut self, voice: VoiceId) {
+        if self.voice_mut(voice).is_some() {
+            self.voices[usize::from(voice.index)] = None;
+        }
+    }
+    /// Stop playing all voices.
+    pub fn stop_all(&mut self) {
+        self.voices = [None; MAX_VOICES];
+    }
+    /// Start the Direct Sound channels, the timer and the DMAs.
+    pub(crate) fn enable(&mut self, mut timer: timer::Slot) {
+        with_buffers(|buffers| {
+            buffers.left = [[0; BUFFER_LEN]; 2];
+            buffers.right = [[0; BUFFER_LEN]; 2];
+        });
+        // Sound registers can only be written to when sound is enabled.
+        SOUNDCNT_X.write(MASTER_ENABLE);
+        SOUNDCNT_H.write(SOUNDCNT_H.read() & !DIRECT_SOUND_MASK | DIRECT_SOUND);
+        self.front = 0;
+        self.start_dma();
+        timer.set_reload(0_u16.wrapping_sub(SAMPLE_CYCLES));
+        timer.start(timer::Prescaler::Cycles1);
+        self.timer = Some(timer);
+    }
+    /// Stop all voices and the Direct Sound channels, returns the timer
+    /// to free.
+    pub(crate) fn disable(&mut self) -> Option<timer::Slot> {
+        let timer = self.timer.take()?;
+        self.stop_all();
+        Channel::_1.stop();
+        Channel::_2.stop();
+        SOUNDCNT_H.write(SOUNDCNT_H.read() & !DIRECT_SOUND_MASK);
+        Some(timer)
+    }
+    fn start_dma(&self) {
+        let buffers = BUFFERS.0.get();
+        // SAFETY: only the addresses are taken, no reference is created.
+        let (left, right) = unsafe {
+            let left = &(*buffers).left[self.front];
+            let right = &(*buffers).right[self.front];
+            (left.as_ptr() as usize, right.as_ptr() as usize)
+        };
+        // SAFETY: the buffers are static, 4 bytes aligned, and are not
+        // written to while played, DMA stops reading them on the next swap.
+        unsafe {
+            Channel::_1.start(left, FIFO_A, 4, FIFO_DMA);
+            Channel::_2.start(right, FIFO_B, 4, FIFO_DMA);
+        }
+    }
+    /// Play the buffers mixed during the last frame, should be called at
+    /// the start of vblank.
+    pub(crate) fn swap_buffers(&mut self) {
+        if self.is_enabled() {
+            self.front = 1 - self.front;
+            self.start_dma();
+        }
+    }
+    /// Mix all voices into the buffers not being played.
+    // allow: the mix is clamped to the i8 range before casting.
+    #[allow(clippy::cast_possible_truncation)]
+    pub(crate) fn mix(&mut self) {
+        if !self.is_enabled() {
+            return;
+        }
+        let back = 1 - self.front;
+        let voices = &mut self.voices;
+        with_buffers(|buffers| {
+            buffers.mix_left = [0; BUFFER_LEN];
+            buffers.mix_right = [0; BUFFER_LEN];
+            for slot in voices.iter_mut() {
+                let playing = match slot {
+                    Some(voice) => voice.mix(&mut buffers.mix_left, &mut buffers.mix_right),
+                    None => continue,
+                };
+                if !playing {
+                    *slot = None;
+                }
+            }
+            let clamp = |mixed: &i16| (mixed >> 4).clamp(-128, 127) as i8;
+            let left = buffers.left[back].iter_mut().zip(&buffers.mix_left);
+            left.for_each(|(sample, mixed)| *sample = clamp(mixed));
+            let right = buffers.right[back].iter_mut().zip(&buffers.mix_right);
+            right.for_each(|(sample, mixed)| *sample = clamp(mixed));
+        });
+    }
+}
+
+/// Access the [`BUFFERS`], only from the `Mixer` in `ConsoleState`.
+fn with_buffers<R>(f: impl FnOnce(&mut Buffers) -> R) -> R {
+    // SAFETY: only the `Mixer` in `ConsoleState` calls this, and never
+    // reentrantly. The buffers read by DMA are not written to while played.
+    f(unsafe { &mut *BUFFERS.0.get() })
+}
+
+/// Define a [`Sample`] from a file of signed 8 bits PCM samples.
+///
+/// ```ignore
+/// const SHOOT: hal::audio::Sample = hal::sample!("../sounds/shoot.pcm", 8192);
+/// ```
+///
+/// Where the first argument is the path to the file, relative to the file
+/// using the macro, like [`include_bytes!`], and the second one the sample
+/// rate in Hz.
+#[macro_export]
+macro_rules! sample {
+    ($file:literal, $rate:expr $(,)?) => {
+        $crate::audio::Sample::new(
+            $crate::audio::align::include_const_aligned!(4, $file),
+            $rate,
+        )
+    };
+}
+
+#[cfg(all(test, feature = "host"))]
+mod test {
+    use const_default::ConstDefault;
+
+    use super::{Params, Sample, BUFFERS, BUFFER_LEN, MAX_PAN, SAMPLE_RATE};
+    use crate::{exec::ConsoleState, host::Console};
+
+    static DATA: [u8; 100] = [40; 100];
+
+    #[test]
+    fn panned_voice_mixed_once() {
+        let _console = Console::new();
+        let mut console = ConsoleState::DEFAULT;
+        assert!(console.enable_audio());
+        let sample = Sample::new(&DATA, SAMPLE_RATE);
+        let left = Params { pan: -MAX_PAN, ..Params::DEFAULT };
+        let voice = console.audio.play(sample, left).unwrap();
+        let octave_up = Params { pitch: 512, ..Params::DEFAULT };
+        let high_voice = console.audio.play(sample, octave_up).unwrap();
+        console.audio.mix();
+
+        // SAFETY: no other `Mixer` is accessing the buffers.
+        let buffers = unsafe { &*BUFFERS.0.get() };
+        // The back buffer is mixed while the first one is played.
+        let (left, right) = (&buffers.left[1], &buffers.right[1]);
+        assert_eq!(&left[..50], &[80; 50]);
+        assert_eq!(&left[50..100], &[40; 50]);
+        assert_eq!(&right[..50], &[40; 50]);
+        assert_eq!(&left[100..], &[0; BUFFER_LEN - 100]);
+        assert_eq!(&right[50..], &[0; BUFFER_LEN - 50]);
+        assert!(!console.audio.is_playing(voice));
+        assert!(!console.audio.is_playing(high_voice));
+
+        console.disable_audio();
+        assert!(!console.audio.is_enabled());
+    }
+}
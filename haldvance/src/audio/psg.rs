@@ -0,0 +1,697 @@
+//! The Game Boy sound channels, and sound effects played on them, see [`Psg`].
+//!
+//! Besides Direct Sound, the GBA has the four Programmable Sound Generator
+//! (PSG) channels of the Game Boy:
+//!
+//! | Channel | Handle | Sound |
+//! |:-------:|:------:|:-----:|
+//! | [`Channel::Square1`] | [`Square1Handle`] | Square wave, with a frequency [`Sweep`] |
+//! | [`Channel::Square2`] | [`Square2Handle`] | Square wave |
+//! | [`Channel::Wave`] | [`WaveHandle`] | Repeats a [`WaveTable`] of 32 4 bits samples |
+//! | [`Channel::Noise`] | [`NoiseHandle`] | Pseudo-random noise |
+//!
+//! The hardware generates their sound, so unlike [`super::Mixer`] voices,
+//! they cost no CPU time. Set their registers through the handles, or play
+//! an [`Effect`], a const table of register writes applied over a few frames:
+//!
+//! ```ignore
+//! const BLIP: Effect = Effect::new(Channel::Square2, &[
+//!     Step::new(Write::Envelope(Envelope::new(12, Direction::Decrease, 2))),
+//!     Step::new(Write::Frequency(Frequency::from_hz(880).restart())).with_wait(4),
+//!     Step::new(Write::Frequency(Frequency::from_hz(1320))).with_wait(8),
+//! ]);
+//!
+//! console.psg.enable();
+//! console.psg.play(BLIP);
+//! ```
+//!
+//! Effects are updated at the start of vblank by [`crate::exec::full_game`].
+//! The channel keeps playing after the last step, so effects should end with
+//! a decreasing [`Envelope`] or a length, see [`Frequency::with_length`].
+use const_default::ConstDefault;
+use volmatrix::rw::{VolAddress, VolBlock};
+
+// SAFETY: all of the following are valid GBA memory-mapped registers
+// of the given size.
+const SOUND1CNT_L: VolAddress<u16> = unsafe { VolAddress::new(0x0400_0060) };
+const SOUND1CNT_H: VolAddress<u16> = unsafe { VolAddress::new(0x0400_0062) };
+const SOUND1CNT_X: VolAddress<u16> = unsafe { VolAddress::new(0x0400_0064) };
+const SOUND2CNT_L: VolAddress<u16> = unsafe { VolAddress::new(0x0400_0068) };
+const SOUND2CNT_H: VolAddress<u16> = unsafe { VolAddress::new(0x0400_006C) };
+const SOUND3CNT_L: VolAddress<u16> = unsafe { VolAddress::new(0x0400_0070) };
+const SOUND3CNT_H: VolAddress<u16> = unsafe { VolAddress::new(0x0400_0072) };
+const SOUND3CNT_X: VolAddress<u16> = unsafe { VolAddress::new(0x0400_0074) };
+const SOUND4CNT_L: VolAddress<u16> = unsafe { VolAddress::new(0x0400_0078) };
+const SOUND4CNT_H: VolAddress<u16> = unsafe { VolAddress::new(0x0400_007C) };
+const SOUNDCNT_L: VolAddress<u16> = unsafe { VolAddress::new(0x0400_0080) };
+const SOUNDCNT_H: VolAddress<u16> = unsafe { VolAddress::new(0x0400_0082) };
+const SOUNDCNT_X: VolAddress<u16> = unsafe { VolAddress::new(0x0400_0084) };
+const WAVE_RAM: VolBlock<u8, 16> = unsafe { VolBlock::new(0x0400_0090) };
+
+const MASTER_ENABLE: u16 = 1 << 7;
+/// Both speakers at full volume, with no channel playing on them.
+const FULL_VOLUME: u16 = 0x0077;
+/// The PSG volume bits of `SOUNDCNT_H`, the others control Direct Sound.
+const PSG_VOLUME_MASK: u16 = 0b11;
+const PSG_VOLUME_FULL: u16 = 0b10;
+const LEFT_SPEAKER: u16 = 1 << 12;
+const RIGHT_SPEAKER: u16 = 1 << 8;
+/// Start playing the channel, in [`Frequency`] and [`NoiseFrequency`].
+const RESTART: u16 = 1 << 15;
+/// Stop playing once the length expires, in [`Frequency`] and [`NoiseFrequency`].
+const LENGTH_ENABLE: u16 = 1 << 14;
+const WAVE_PLAY: u16 = 1 << 7;
+const WAVE_BANK: u16 = 1 << 6;
+
+/// One of the PSG channels, see [module-level doc](self).
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+#[repr(u8)]
+pub enum Channel {
+    Square1 = 0,
+    Square2 = 1,
+    Wave = 2,
+    Noise = 3,
+}
+
+/// Whether a [`Sweep`] or [`Envelope`] goes up or down.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Direction {
+    Increase,
+    Decrease,
+}
+
+/// Proportion of the time a square wave is high.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+#[repr(u16)]
+pub enum Duty {
+    /// 12.5%, a thin sound.
+    Eighth = 0,
+    Quarter = 1,
+    /// 50%, the fullest sound.
+    Half = 2,
+    /// Sounds the same as [`Duty::Quarter`].
+    ThreeQuarters = 3,
+}
+
+/// How the [`Channel::Square1`] frequency changes over time.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct Sweep(u16);
+impl Sweep {
+    /// Keep the frequency constant.
+    pub const OFF: Self = Self(1 << 3);
+
+    /// Every `time` 128th of a second, the frequency rate changes by
+    /// itself shifted right by `shift`, in `direction`.
+    ///
+    /// An increasing sweep stops the channel once the rate overflows.
+    ///
+    /// # Panics
+    ///
+    /// If `shift` or `time` are 8 or more.
+    #[must_use]
+    pub const fn new(shift: u8, time: u8, direction: Direction) -> Self {
+        assert!(
+            shift < 8 && time < 8,
+            "Sweep shift and time must be less than 8"
+        );
+        let decrease = matches!(direction, Direction::Decrease) as u16;
+        Self(shift as u16 | decrease << 3 | (time as u16) << 4)
+    }
+}
+
+/// The volume envelope of [`Channel::Square1`], [`Channel::Square2`] and
+/// [`Channel::Noise`], with the square duty cycle and the sound length.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct Envelope(u16);
+impl Envelope {
+    /// Start at `volume`, from `0` to `15`, then change it by one every
+    /// `step` 64th of a second in `direction`. With a `step` of `0`, the
+    /// volume stays constant.
+    ///
+    /// The duty cycle is [`Duty::Half`].
+    ///
+    /// # Panics
+    ///
+    /// If `volume` is 16 or more, or `step` 8 or more.
+    #[must_use]
+    pub const fn new(volume: u8, direction: Direction, step: u8) -> Self {
+        assert!(volume < 16, "Envelope volume must be less than 16");
+        assert!(step < 8, "Envelope step must be less than 8");
+        let increase = matches!(direction, Direction::Increase) as u16;
+        let duty = (Duty::Half as u16) << 6;
+        Self(duty | (step as u16) << 8 | increase << 11 | (volume as u16) << 12)
+    }
+    /// Set the duty cycle, ignored by [`Channel::Noise`].
+    #[must_use]
+    pub const fn with_duty(self, duty: Duty) -> Self {
+        Self(self.0 & !(0b11 << 6) | (duty as u16) << 6)
+    }
+    /// Play for `(64 - length) / 256` seconds, when the frequency is set
+    /// [`Frequency::with_length`].
+    ///
+    /// # Panics
+    ///
+    /// If `length` is 64 or more.
+    #[must_use]
+    pub const fn with_length(self, length: u8) -> Self {
+        assert!(length < 64, "Envelope length must be less than 64");
+        Self(self.0 & !0x3F | length as u16)
+    }
+}
+
+/// The frequency of [`Channel::Square1`], [`Channel::Square2`] and
+/// [`Channel::Wave`].
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct Frequency(u16);
+impl Frequency {
+    /// The raw frequency `rate`, less than 2048.
+    ///
+    /// Square channels play at `131072 / (2048 - rate)` Hz. The wave
+    /// channel plays its whole [`WaveTable`] at `65536 / (2048 - rate)` Hz,
+    /// one octave lower.
+    ///
+    /// # Panics
+    ///
+    /// If `rate` is 2048 or more.
+    #[must_use]
+    pub const fn new(rate: u16) -> Self {
+        assert!(rate < 2048, "Frequency rate must be less than 2048");
+        Self(rate)
+    }
+    /// A square channel playing at `hz`, from 64 to 131072.
+    ///
+    /// # Panics
+    ///
+    /// If `hz` is outside of this range.
+    // allow: the rate is checked to fit in 11 bits.
+    #[allow(clippy::cast_possible_truncation)]
+    #[must_use]
+    pub const fn from_hz(hz: u32) -> Self {
+        assert!(
+            64 <= hz && hz <= 131_072,
+            "Frequency must be between 64 and 131072 Hz"
+        );
+        Self::new((2048 - 131_072 / hz) as u16)
+    }
+    /// (Re)start the sound, resetting its envelope and length.
+    ///
+    /// Without this, only the frequency of the current sound changes.
+    #[must_use]
+    pub const fn restart(self) -> Self {
+        Self(self.0 | RESTART)
+    }
+    /// Stop the sound once its length expires, see [`Envelope::with_length`]
+    /// and [`WaveVolume::with_length`].
+    #[must_use]
+    pub const fn with_length(self) -> Self {
+        Self(self.0 | LENGTH_ENABLE)
+    }
+}
+
+/// The shift register width of [`NoiseFrequency`].
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Width {
+    /// 15 bits, a hiss.
+    Long,
+    /// 7 bits, a more regular, metallic sound.
+    Short,
+}
+
+/// The frequency and kind of [`Channel::Noise`].
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct NoiseFrequency(u16);
+impl NoiseFrequency {
+    /// Noise at `524288 / ratio / 2^(shift + 1)` Hz, where a `ratio` of
+    /// `0` counts as `0.5`. Higher frequencies sound softer.
+    ///
+    /// # Panics
+    ///
+    /// If `ratio` is 8 or more, or `shift` 16 or more.
+    #[must_use]
+    pub const fn new(ratio: u8, shift: u8, width: Width) -> Self {
+        assert!(ratio < 8, "Noise ratio must be less than 8");
+        assert!(shift < 16, "Noise shift must be less than 16");
+        let short = matches!(width, Width::Short) as u16;
+        Self(ratio as u16 | short << 3 | (shift as u16) << 4)
+    }
+    /// (Re)start the noise, resetting its envelope and length.
+    #[must_use]
+    pub const fn restart(self) -> Self {
+        Self(self.0 | RESTART)
+    }
+    /// Stop the noise once its length expires, see [`Envelope::with_length`].
+    #[must_use]
+    pub const fn with_length(self) -> Self {
+        Self(self.0 | LENGTH_ENABLE)
+    }
+}
+
+/// The volume and length of [`Channel::Wave`].
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct WaveVolume(u16);
+impl WaveVolume {
+    pub const MUTE: Self = Self(0);
+    pub const QUARTER: Self = Self(3 << 13);
+    pub const HALF: Self = Self(2 << 13);
+    pub const THREE_QUARTERS: Self = Self(1 << 15);
+    pub const FULL: Self = Self(1 << 13);
+
+    /// Play for `(256 - length) / 256` seconds, when the frequency is set
+    /// [`Frequency::with_length`].
+    #[must_use]
+    pub const fn with_length(self, length: u8) -> Self {
+        Self(self.0 & !0xFF | length as u16)
+    }
+}
+
+/// The 32 4 bits samples repeatedly played by [`Channel::Wave`].
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct WaveTable([u8; 16]);
+impl WaveTable {
+    /// A table of `samples`, each from `0` to `15`.
+    ///
+    /// # Panics
+    ///
+    /// If any sample is 16 or more.
+    #[must_use]
+    pub const fn new(samples: [u8; 32]) -> Self {
+        let mut packed = [0; 16];
+        let mut i = 0;
+        while i < 16 {
+            let (high, low) = (samples[2 * i], samples[2 * i + 1]);
+            assert!(high < 16 && low < 16, "Wave samples must be less than 16");
+            // The high nibble plays first.
+            packed[i] = high << 4 | low;
+            i += 1;
+        }
+        Self(packed)
+    }
+}
+
+/// [`Channel::Square1`], see [`Psg::square1`].
+#[derive(Debug)]
+pub struct Square1Handle(());
+impl Square1Handle {
+    pub fn set_sweep(&mut self, sweep: Sweep) {
+        SOUND1CNT_L.write(sweep.0);
+    }
+    pub fn set_envelope(&mut self, envelope: Envelope) {
+        SOUND1CNT_H.write(envelope.0);
+    }
+    pub fn set_frequency(&mut self, frequency: Frequency) {
+        SOUND1CNT_X.write(frequency.0);
+    }
+}
+
+/// [`Channel::Square2`], see [`Psg::square2`].
+#[derive(Debug)]
+pub struct Square2Handle(());
+impl Square2Handle {
+    pub fn set_envelope(&mut self, envelope: Envelope) {
+        SOUND2CNT_L.write(envelope.0);
+    }
+    pub fn set_frequency(&mut self, frequency: Frequency) {
+        SOUND2CNT_H.write(frequency.0);
+    }
+}
+
+/// [`Channel::Wave`], see [`Psg::wave`].
+#[derive(Debug)]
+pub struct WaveHandle {
+    /// The [`WAVE_BANK`] played, the other one is accessible in `WAVE_RAM`.
+    bank: u16,
+    playing: bool,
+}
+impl WaveHandle {
+    /// Play `table`, from the next sample on.
+    ///
+    /// There are two wave RAM banks: `table` is written to the one not
+    /// being played, which then replaces the current one.
+    pub fn set_table(&mut self, table: &WaveTable) {
+        for (ram, sample) in WAVE_RAM.iter().zip(table.0) {
+            ram.write(sample);
+        }
+        self.bank ^= WAVE_BANK;
+        self.playing = true;
+        SOUND3CNT_L.write(WAVE_PLAY | self.bank);
+    }
+    pub fn set_volume(&mut self, volume: WaveVolume) {
+        SOUND3CNT_H.write(volume.0);
+    }
+    /// Set the frequency, restarting the channel if stopped with
+    /// [`Self::stop`] and `frequency` is [`Frequency::restart`].
+    pub fn set_frequency(&mut self, frequency: Frequency) {
+        if !self.playing && frequency.0 & RESTART != 0 {
+            self.playing = true;
+            SOUND3CNT_L.write(WAVE_PLAY | self.bank);
+        }
+        SOUND3CNT_X.write(frequency.0);
+    }
+    /// Stop playing.
+    pub fn stop(&mut self) {
+        self.playing = false;
+        SOUND3CNT_L.write(self.bank);
+    }
+}
+
+/// [`Channel::Noise`], see [`Psg::noise`].
+#[derive(Debug)]
+pub struct NoiseHandle(());
+impl NoiseHandle {
+    pub fn set_envelope(&mut self, envelope: Envelope) {
+        SOUND4CNT_L.write(envelope.0);
+    }
+    pub fn set_frequency(&mut self, frequency: NoiseFrequency) {
+        SOUND4CNT_H.write(frequency.0);
+    }
+}
+
+/// A register write in an [`Effect`].
+#[derive(Clone, Copy, Debug)]
+pub enum Write {
+    /// Only for [`Channel::Square1`].
+    Sweep(Sweep),
+    /// For all channels but [`Channel::Wave`].
+    Envelope(Envelope),
+    /// For all channels but [`Channel::Noise`].
+    Frequency(Frequency),
+    /// Only for [`Channel::Noise`].
+    NoiseFrequency(NoiseFrequency),
+    /// Only for [`Channel::Wave`].
+    WaveVolume(WaveVolume),
+    /// Only for [`Channel::Wave`].
+    WaveTable(&'static WaveTable),
+}
+impl Write {
+    const fn is_valid_for(&self, channel: Channel) -> bool {
+        use Channel::{Noise, Square1, Square2, Wave};
+        matches!(
+            (self, channel),
+            (Self::Sweep(_), Square1)
+                | (Self::Envelope(_), Square1 | Square2 | Noise)
+                | (Self::Frequency(_), Square1 | Square2 | Wave)
+                | (Self::NoiseFrequency(_), Noise)
+                | (Self::WaveVolume(_) | Self::WaveTable(_), Wave)
+        )
+    }
+}
+
+/// A [`Write`] in an [`Effect`], and how long to wait before the next one.
+#[derive(Clone, Copy, Debug)]
+pub struct Step {
+    write: Write,
+    wait: u8,
+}
+impl Step {
+    /// Apply `write`, and the next step on the same frame.
+    #[must_use]
+    pub const fn new(write: Write) -> Self {
+        Self { write, wait: 0 }
+    }
+    /// Wait `frames` after this step before applying the next one.
+    #[must_use]
+    pub const fn with_wait(self, frames: u8) -> Self {
+        Self { wait: frames, ..self }
+    }
+}
+
+/// A sound effect played on a PSG channel with [`Psg::play`].
+///
+/// See [module-level doc](self) for an example.
+#[derive(Clone, Copy, Debug)]
+pub struct Effect {
+    channel: Channel,
+    steps: &'static [Step],
+}
+impl Effect {
+    /// An effect applying `steps` to `channel`, in order.
+    ///
+    /// # Panics
+    ///
+    /// If a [`Write`] of `steps` doesn't apply to `channel`.
+    #[must_use]
+    pub const fn new(channel: Channel, steps: &'static [Step]) -> Self {
+        let mut i = 0;
+        while i < steps.len() {
+            assert!(
+                steps[i].write.is_valid_for(channel),
+                "Write invalid for the channel"
+            );
+            i += 1;
+        }
+        Self { channel, steps }
+    }
+    #[must_use]
+    pub const fn channel(&self) -> Channel {
+        self.channel
+    }
+}
+
+/// An [`Effect`] being played.
+#[derive(Clone, Copy)]
+struct Playing {
+    steps: &'static [Step],
+    /// Index in `steps` of the next step.
+    next: usize,
+    /// Frames to wait before applying the next step.
+    wait: u8,
+}
+
+/// The PSG channels, accessible as [`ConsoleState::psg`].
+///
+/// See [module-level doc](self) for details.
+///
+/// [`ConsoleState::psg`]: crate::exec::ConsoleState::psg
+pub struct Psg {
+    enabled: bool,
+    square1: Square1Handle,
+    square2: Square2Handle,
+    wave: WaveHandle,
+    noise: NoiseHandle,
+    /// The effect played on each [`Channel`].
+    effects: [Option<Playing>; 4],
+}
+impl ConstDefault for Psg {
+    const DEFAULT: Self = Self {
+        enabled: false,
+        square1: Square1Handle(()),
+        square2: Square2Handle(()),
+        wave: WaveHandle { bank: 0, playing: false },
+        noise: NoiseHandle(()),
+        effects: [None; 4],
+    };
+}
+impl Psg {
+    /// Turn on sound, and play all channels on both speakers at full volume.
+    ///
+    /// Does nothing if already enabled. This doesn't affect Direct Sound.
+    pub fn enable(&mut self) {
+        if self.enabled {
+            return;
+        }
+        // Sound registers can only be written to when sound is enabled.
+        SOUNDCNT_X.write(MASTER_ENABLE);
+        SOUNDCNT_L.write(FULL_VOLUME | (LEFT_SPEAKER | RIGHT_SPEAKER) * 0b1111);
+        SOUNDCNT_H.write(SOUNDCNT_H.read() & !PSG_VOLUME_MASK | PSG_VOLUME_FULL);
+        self.square1.set_sweep(Sweep::OFF);
+        self.enabled = true;
+    }
+    /// Stop all channels and effects, without turning off Direct Sound.
+    pub fn disable(&mut self) {
+        if !self.enabled {
+            return;
+        }
+        for channel in [
+            Channel::Square1,
+            Channel::Square2,
+            Channel::Wave,
+            Channel::Noise,
+        ] {
+            self.stop(channel);
+        }
+        SOUNDCNT_L.write(0);
+        self.enabled = false;
+    }
+    #[must_use]
+    pub const fn is_enabled(&self) -> bool {
+        self.enabled
+    }
+    /// Play `channel` on the `left` and `right` speakers.
+    pub fn set_pan(&mut self, channel: Channel, left: bool, right: bool) {
+        if !self.enabled {
+            return;
+        }
+        let shift = channel as u16;
+        let mut output = SOUNDCNT_L.read() & !((LEFT_SPEAKER | RIGHT_SPEAKER) << shift);
+        if left {
+            output |= LEFT_SPEAKER << shift;
+        }
+        if right {
+            output |= RIGHT_SPEAKER << shift;
+        }
+        SOUNDCNT_L.write(output);
+    }
+    /// Start `effect` at the next vblank, replacing any effect on its channel.
+    ///
+    /// Returns `false` if the PSG is disabled.
+    pub fn play(&mut self, effect: Effect) -> bool {
+        if self.enabled {
+            let playing = Playing { steps: effect.steps, next: 0, wait: 0 };
+            self.effects[effect.channel as usize] = Some(playing);
+        }
+        self.enabled
+    }
+    /// Whether an effect is still being played on `channel`.
+    ///
+    /// This is `true` until the last step's wait is over.
+    #[must_use]
+    pub const fn is_playing(&self, channel: Channel) -> bool {
+        self.effects[channel as usize].is_some()
+    }
+    /// Stop the effect on `channel`, and silence it.
+    pub fn stop(&mut self, channel: Channel) {
+        self.effects[channel as usize] = None;
+        if !self.enabled {
+            return;
+        }
+        // A channel with an initial volume of 0 is turned off on restart.
+        let silent = Envelope::new(0, Direction::Decrease, 0);
+        match channel {
+            Channel::Square1 => {
+                self.square1.set_envelope(silent);
+                self.square1.set_frequency(Frequency::new(0).restart());
+            }
+            Channel::Square2 => {
+                self.square2.set_envelope(silent);
+                self.square2.set_frequency(Frequency::new(0).restart());
+            }
+            Channel::Wave => self.wave.stop(),
+            Channel::Noise => {
+                self.noise.set_envelope(silent);
+                self.noise
+                    .set_frequency(NoiseFrequency::new(0, 0, Width::Long).restart());
+            }
+        }
+    }
+    /// Control [`Channel::Square1`] directly, stopping its effect.
+    ///
+    /// Returns `None` if the PSG is disabled.
+    pub fn square1(&mut self) -> Option<&mut Square1Handle> {
+        self.effects[Channel::Square1 as usize] = None;
+        self.enabled.then_some(&mut self.square1)
+    }
+    /// Control [`Channel::Square2`] directly, stopping its effect.
+    ///
+    /// Returns `None` if the PSG is disabled.
+    pub fn square2(&mut self) -> Option<&mut Square2Handle> {
+        self.effects[Channel::Square2 as usize] = None;
+        self.enabled.then_some(&mut self.square2)
+    }
+    /// Control [`Channel::Wave`] directly, stopping its effect.
+    ///
+    /// Returns `None` if the PSG is disabled.
+    pub fn wave(&mut self) -> Option<&mut WaveHandle> {
+        self.effects[Channel::Wave as usize] = None;
+        self.enabled.then_some(&mut self.wave)
+    }
+    /// Control [`Channel::Noise`] directly, stopping its effect.
+    ///
+    /// Returns `None` if the PSG is disabled.
+    pub fn noise(&mut self) -> Option<&mut NoiseHandle> {
+        self.effects[Channel::Noise as usize] = None;
+        self.enabled.then_some(&mut self.noise)
+    }
+    /// Apply `write` to `channel`, [`Effect::new`] checked it applies.
+    fn apply(&mut self, channel: Channel, write: Write) {
+        match (channel, write) {
+            (_, Write::Sweep(sweep)) => self.square1.set_sweep(sweep),
+            (Channel::Square1, Write::Envelope(envelope)) => self.square1.set_envelope(envelope),
+            (Channel::Square2, Write::Envelope(envelope)) => self.square2.set_envelope(envelope),
+            (_, Write::Envelope(envelope)) => self.noise.set_envelope(envelope),
+            (Channel::Square1, Write::Frequency(frequency)) => {
+                self.square1.set_frequency(frequency);
+            }
+            (Channel::Square2, Write::Frequency(frequency)) => {
+                self.square2.set_frequency(frequency);
+            }
+            (_, Write::Frequency(frequency)) => self.wave.set_frequency(frequency),
+            (_, Write::NoiseFrequency(frequency)) => self.noise.set_frequency(frequency),
+            (_, Write::WaveVolume(volume)) => self.wave.set_volume(volume),
+            (_, Write::WaveTable(table)) => self.wave.set_table(table),
+        }
+    }
+    /// Apply the effect steps due this frame, should be called at the
+    /// start of vblank.
+    pub(crate) fn update(&mut self) {
+        if !self.enabled {
+            return;
+        }
+        for channel in [
+            Channel::Square1,
+            Channel::Square2,
+            Channel::Wave,
+            Channel::Noise,
+        ] {
+            let mut playing = match self.effects[channel as usize] {
+                Some(playing) => playing,
+                None => continue,
+            };
+            if playing.wait > 0 {
+                playing.wait -= 1;
+            } else {
+                if playing.next == playing.steps.len() {
+                    self.effects[channel as usize] = None;
+                    continue;
+                }
+                while let Some(step) = playing.steps.get(playing.next) {
+                    self.apply(channel, step.write);
+                    playing.next += 1;
+                    if step.wait > 0 {
+                        playing.wait = step.wait - 1;
+                        break;
+                    }
+                }
+            }
+            self.effects[channel as usize] = Some(playing);
+        }
+    }
+}
+
+#[cfg(all(test, feature = "host"))]
+mod test {
+    use const_default::ConstDefault;
+
+    use super::{
+        Channel, Direction, Effect, Envelope, Frequency, Psg, Step, Write, SOUND2CNT_H, SOUND2CNT_L,
+    };
+    use crate::host::Console;
+
+    const BLIP: Effect = Effect::new(
+        Channel::Square2,
+        &[
+            Step::new(Write::Envelope(Envelope::new(12, Direction::Decrease, 2))),
+            Step::new(Write::Frequency(Frequency::new(1800).restart())).with_wait(2),
+            Step::new(Write::Frequency(Frequency::new(1900))).with_wait(1),
+        ],
+    );
+
+    #[test]
+    fn effect_steps_follow_waits() {
+        let _console = Console::new();
+        let mut psg = Psg::DEFAULT;
+        assert!(!psg.play(BLIP));
+        psg.enable();
+        assert!(psg.play(BLIP));
+        psg.update();
+        assert_eq!(SOUND2CNT_L.read() >> 12, 12);
+        assert_eq!(SOUND2CNT_H.read(), 1800 | 1 << 15);
+        psg.update();
+        assert_eq!(SOUND2CNT_H.read(), 1800 | 1 << 15);
+        psg.update();
+        assert_eq!(SOUND2CNT_H.read(), 1900);
+        assert!(psg.is_playing(Channel::Square2));
+        psg.update();
+        assert!(!psg.is_playing(Channel::Square2));
+        assert!(!psg.is_playing(Channel::Square1));
+    }
+}
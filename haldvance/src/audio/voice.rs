@@ -0,0 +1,158 @@
+//! Sound samples and how they are mixed, see [`Sample`] and [`Params`].
+use const_default::ConstDefault;
+
+use super::{BUFFER_LEN, SAMPLE_RATE};
+
+/// Voice positions are fixed point numbers with this many fraction bits.
+const FRACTION_BITS: u32 = 12;
+
+/// The longest [`Sample`], in samples.
+pub const MAX_SAMPLE_LEN: usize = 1 << 19;
+/// The highest [`Sample`] rate, in Hz.
+pub const MAX_SAMPLE_RATE: u32 = 1 << 16;
+/// The loudest [`Params::volume`].
+pub const MAX_VOLUME: u8 = 64;
+/// The [`Params::pan`] playing only on the right speaker, its negation
+/// plays only on the left speaker.
+pub const MAX_PAN: i8 = 64;
+/// The [`Params::pitch`] playing a [`Sample`] at its own rate.
+pub const BASE_PITCH: u16 = 256;
+
+/// Signed 8 bits PCM sound data, played with [`super::Mixer::play`].
+///
+/// Define samples with [`crate::sample!`].
+#[derive(Clone, Copy, Debug)]
+pub struct Sample {
+    data: &'static [u8],
+    /// Sample rate of `data`, in Hz.
+    rate: u32,
+    /// Where to restart once the end is reached, `None` to stop playing.
+    loop_start: Option<usize>,
+}
+impl Sample {
+    /// A sample playing `data` at `rate` Hz, prefer [`crate::sample!`].
+    ///
+    /// Each byte of `data` is a signed 8 bits sample.
+    ///
+    /// # Panics
+    ///
+    /// If `data` is longer than [`MAX_SAMPLE_LEN`], or `rate` is higher
+    /// than [`MAX_SAMPLE_RATE`].
+    #[must_use]
+    pub const fn new(data: &'static [u8], rate: u32) -> Self {
+        assert!(data.len() <= MAX_SAMPLE_LEN, "Sample is too long");
+        assert!(rate <= MAX_SAMPLE_RATE, "Sample rate is too high");
+        Self { data, rate, loop_start: None }
+    }
+    /// Restart playing from `start` once the end is reached, rather than
+    /// stopping, for music and engine hums.
+    ///
+    /// # Panics
+    ///
+    /// If `start` is not within the sample.
+    #[must_use]
+    pub const fn looping(self, start: usize) -> Self {
+        assert!(
+            start < self.data.len(),
+            "Loop start must be within the sample"
+        );
+        Self { loop_start: Some(start), ..self }
+    }
+}
+
+/// How a [`Sample`] is played, see [`super::Mixer::play`].
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct Params {
+    /// From `0` (silent) to [`MAX_VOLUME`].
+    pub volume: u8,
+    /// From `-MAX_PAN` (left speaker only) to [`MAX_PAN`] (right speaker
+    /// only), `0` plays on both speakers at full volume.
+    pub pan: i8,
+    /// Playback speed in 1/256th, [`BASE_PITCH`] plays the sample at its
+    /// rate, twice as much plays it one octave higher, and twice as fast.
+    pub pitch: u16,
+}
+impl ConstDefault for Params {
+    const DEFAULT: Self = Self {
+        volume: MAX_VOLUME,
+        pan: 0,
+        pitch: BASE_PITCH,
+    };
+}
+
+/// A [`Sample`] being played.
+#[derive(Clone, Copy)]
+pub(super) struct Voice {
+    sample: Sample,
+    pub(super) params: Params,
+    /// Distinguishes voices successively played in the same slot.
+    pub(super) generation: u8,
+    /// Position in the sample, in fixed point with [`FRACTION_BITS`].
+    position: u32,
+    /// How much `position` advances per mixed sample.
+    step: u32,
+    left_gain: i16,
+    right_gain: i16,
+}
+impl Voice {
+    pub(super) fn new(sample: Sample, params: Params, generation: u8) -> Self {
+        let mut voice = Self {
+            sample,
+            params,
+            generation,
+            position: 0,
+            step: 0,
+            left_gain: 0,
+            right_gain: 0,
+        };
+        voice.set_params(params);
+        voice
+    }
+    // allow: with MAX_SAMPLE_RATE and the highest pitch, the step is less
+    // than 1 << 22.
+    #[allow(clippy::cast_possible_truncation)]
+    pub(super) fn set_params(&mut self, params: Params) {
+        let Params { volume, pan, pitch } = params;
+        let volume = i16::from(volume.min(MAX_VOLUME));
+        let pan = i16::from(pan.clamp(-MAX_PAN, MAX_PAN));
+        let max_pan = i16::from(MAX_PAN);
+        self.left_gain = volume * (max_pan - pan).min(max_pan) / max_pan;
+        self.right_gain = volume * (max_pan + pan).min(max_pan) / max_pan;
+        let speed = (u64::from(self.sample.rate) * u64::from(pitch)) << FRACTION_BITS;
+        self.step = (speed / (u64::from(BASE_PITCH) * u64::from(SAMPLE_RATE))) as u32;
+        self.params = params;
+    }
+    /// Add [`BUFFER_LEN`] samples of this voice to `left` and `right`,
+    /// returns `false` if the sample ended.
+    ///
+    /// Samples are added with a quarter of their value, so that all voices
+    /// can be added without overflowing.
+    // allow: samples are at most MAX_SAMPLE_LEN long, and bytes are
+    // reinterpreted as signed on purpose.
+    #[allow(clippy::cast_possible_truncation, clippy::cast_possible_wrap)]
+    #[cfg_attr(not(feature = "host"), link_section = ".iwram.audio_mix")]
+    pub(super) fn mix(
+        &mut self,
+        left: &mut [i16; BUFFER_LEN],
+        right: &mut [i16; BUFFER_LEN],
+    ) -> bool {
+        let data = self.sample.data;
+        let end = (data.len() as u32) << FRACTION_BITS;
+        for (left, right) in left.iter_mut().zip(right.iter_mut()) {
+            if self.position >= end {
+                match self.sample.loop_start {
+                    Some(start) => {
+                        let start = (start as u32) << FRACTION_BITS;
+                        self.position = start + (self.position - end) % (end - start);
+                    }
+                    None => return false,
+                }
+            }
+            let value = i16::from(data[(self.position >> FRACTION_BITS) as usize] as i8);
+            *left += (value * self.left_gain) >> 2;
+            *right += (value * self.right_gain) >> 2;
+            self.position += self.step;
+        }
+        true
+    }
+}
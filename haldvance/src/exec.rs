@@ -5,14 +5,23 @@
 use core::{marker::PhantomData, mem};
 
 use const_default::ConstDefault;
-use gba::mmio_addresses::VCOUNT;
+use utils::Bitset8;
 
 use crate::{
-    input::{Input, KEYINPUT},
+    audio,
+    input::{replay, Input, KeyGroup, KEYINPUT},
+    interrupt, planckrand, power, profile, timer,
     video::{self, mode, object},
 };
 
-pub use crate::planckrand::{RandBitsIter, Rng};
+#[cfg(feature = "host")]
+pub(crate) use crate::host::wait_vblank;
+
+mod crash;
+pub mod reset;
+pub mod scene;
+
+pub use crate::planckrand::{RandBitsIter, Rng, Streams, Weighted};
 
 pub enum EnterMode<T: ?Sized, F, G, H>
 where
@@ -64,22 +73,6 @@ enum ControlModes {
     Affine(video::Control<mode::Affine>),
 }
 
-/// Performs a busy loop until vertical blank starts.
-///
-/// This is very inefficient, and please keep following the lessons until we
-/// cover how interrupts work!
-fn spin_until_vblank() {
-    while VCOUNT.read() < 160 {}
-}
-
-/// Performs a busy loop until vertical draw starts.
-///
-/// This is very inefficient, and please keep following the lessons until we
-/// cover how interrupts work!
-fn spin_until_vdraw() {
-    while VCOUNT.read() >= 160 {}
-}
-
 // TODO: input latency is sooooo bad. What's the deal?
 /// Global console state.
 #[derive(ConstDefault)]
@@ -88,11 +81,42 @@ pub struct ConsoleState {
     pub frame: usize,
     /// The button state
     pub input: Input,
+    /// Whether `input` is read from hardware, recorded or replayed.
+    ///
+    /// See [`replay`] for details.
+    pub(crate) input_source: replay::Source,
     /// The object allocation state.
     pub(crate) objects: object::Allocator,
-    /// A random number generator.
-    /// Just set this with [`Rng::new`] to seed it.
-    pub rng: Rng,
+    /// Video commands to execute at the next vblank.
+    ///
+    /// See [`video::command`] for details.
+    pub commands: video::command::Buffer,
+    /// The reserved hardware timers.
+    pub(crate) timers: Bitset8,
+    /// Per-line video effects, updated at the next vblank.
+    ///
+    /// See [`video::scanline`] for details.
+    pub scanline: video::scanline::Effects,
+    /// CPU time profiler, disabled by default.
+    ///
+    /// See [`profile`] for details.
+    pub profiler: profile::Profiler,
+    /// Sound effects mixer, disabled by default, see [`Self::enable_audio`].
+    ///
+    /// See [`audio`] for details.
+    pub audio: audio::Mixer,
+    /// The Game Boy sound channels and their effects, disabled by default.
+    ///
+    /// See [`audio::psg`] for details.
+    pub psg: audio::psg::Psg,
+    /// Random number generators, one for gameplay and one for cosmetics.
+    /// Just set this with [`Streams::new`] to seed it, or see
+    /// [`Self::seed_from_entropy`].
+    pub rng: Streams,
+    /// The keys triggering a soft reset, A+B+Start+Select by default.
+    ///
+    /// See [`reset`] for details.
+    pub soft_reset: reset::Trigger,
 }
 impl ConsoleState {
     /// Run `f` once every `frequency` frame, with given `offset`.
@@ -117,6 +141,151 @@ impl ConsoleState {
     pub fn free_object(&mut self, slot: object::Slot) {
         self.objects.free(slot);
     }
+    /// Reserve the hardware timer `id`.
+    /// Returns `None` if it is already reserved.
+    ///
+    /// Make sure to call [`Self::free_timer`] before dropping a [`timer::Slot`],
+    /// otherwise, the timer will forever be leaked.
+    #[must_use]
+    pub fn reserve_timer(&mut self, id: timer::Id) -> Option<timer::Slot> {
+        let already_taken = self.timers.reserve(id as u32);
+        // SAFETY: `id` wasn't reserved before, so there is no other slot for it.
+        (!already_taken).then(|| unsafe { timer::Slot::new_unchecked(id) })
+    }
+    /// Enable [`Self::profiler`], using timers 2 and 3.
+    ///
+    /// Returns `false` if the timers are already reserved.
+    pub fn enable_profiler(&mut self) -> bool {
+        if self.profiler.is_enabled() {
+            return true;
+        }
+        let low = match self.reserve_timer(timer::Id::_2) {
+            Some(low) => low,
+            None => return false,
+        };
+        let high = match self.reserve_timer(timer::Id::_3) {
+            Some(high) => high,
+            None => {
+                self.free_timer(low);
+                return false;
+            }
+        };
+        self.profiler.enable(low, high);
+        true
+    }
+    /// Disable [`Self::profiler`], freeing the timers it used.
+    pub fn disable_profiler(&mut self) {
+        if let Some((low, high)) = self.profiler.disable() {
+            self.free_timer(low);
+            self.free_timer(high);
+        }
+    }
+    /// Enable [`Self::audio`], using timer 0 and DMA channels 1 and 2.
+    ///
+    /// Returns `false` if timer 0 is already reserved.
+    pub fn enable_audio(&mut self) -> bool {
+        if self.audio.is_enabled() {
+            return true;
+        }
+        match self.reserve_timer(timer::Id::_0) {
+            Some(timer) => self.audio.enable(timer),
+            None => return false,
+        }
+        true
+    }
+    /// Disable [`Self::audio`], stopping all voices and freeing the timer
+    /// it used.
+    pub fn disable_audio(&mut self) {
+        if let Some(timer) = self.audio.disable() {
+            self.free_timer(timer);
+        }
+    }
+    /// Seed [`Self::rng`] from the frame count and the timer counters,
+    /// returns the seed.
+    ///
+    /// The time the player takes to press a key is the only source of
+    /// randomness on the GBA, so call this when the player presses a key,
+    /// for example Start on the title screen. Timers that are running, such
+    /// as timer 0 when [`Self::audio`] is enabled, add sub-frame precision.
+    ///
+    /// Returns `None` and leaves [`Self::rng`] untouched while recording or
+    /// replaying inputs, since the rng was seeded when starting, see [`replay`].
+    pub fn seed_from_entropy(&mut self) -> Option<u64> {
+        if self.is_replaying() || matches!(self.input_source, replay::Source::Record(_)) {
+            return None;
+        }
+        let seed = planckrand::mix_seed(&[self.frame as u64, timer::counters()]);
+        self.rng = Streams::new(seed);
+        Some(seed)
+    }
+    /// Start recording inputs in `buffer`, seeding [`Self::rng`] with `seed`
+    /// and resetting [`Self::input`].
+    ///
+    /// Stops any previous recording or replay, see [`replay`] for details.
+    pub fn start_recording(&mut self, buffer: &'static mut [replay::Run], seed: u64) {
+        self.rng = Streams::new(seed);
+        self.input.reset();
+        let recording = replay::Recording::new(buffer, seed, self.frame);
+        self.input_source = replay::Source::Record(recording);
+    }
+    /// Stop recording inputs, returns the recording, or `None` if
+    /// not recording.
+    pub fn stop_recording(&mut self) -> Option<replay::Recording> {
+        match mem::replace(&mut self.input_source, replay::Source::Hardware) {
+            replay::Source::Record(recording) => Some(recording),
+            source => {
+                self.input_source = source;
+                None
+            }
+        }
+    }
+    /// Replay `recording`, seeding [`Self::rng`] with the recording seed,
+    /// setting [`Self::frame`] back to when the recording started and
+    /// resetting [`Self::input`].
+    ///
+    /// Once all recorded inputs are replayed, inputs are read from the
+    /// hardware again. Stops any previous recording or replay.
+    pub fn start_replay(&mut self, recording: replay::Recording) {
+        self.rng = Streams::new(recording.seed());
+        self.frame = recording.start_frame();
+        self.input.reset();
+        self.input_source = replay::Source::Replay { recording, run: 0, frame: 0 };
+    }
+    /// Stop replaying inputs, returns the replayed recording, or `None`
+    /// if not replaying.
+    pub fn stop_replay(&mut self) -> Option<replay::Recording> {
+        match mem::replace(&mut self.input_source, replay::Source::Hardware) {
+            replay::Source::Replay { recording, .. } => Some(recording),
+            source => {
+                self.input_source = source;
+                None
+            }
+        }
+    }
+    /// Whether inputs are currently replayed from a recording.
+    #[must_use]
+    pub fn is_replaying(&self) -> bool {
+        self.input_source.is_replaying()
+    }
+    /// Turn off the screen and sound, and stop the CPU until all `wake`
+    /// keys are pressed.
+    ///
+    /// This blocks until the console wakes up and all `wake` keys are
+    /// released, see [`power`] for details.
+    ///
+    /// # Panics
+    ///
+    /// If `wake` is empty.
+    pub fn sleep(&mut self, wake: impl Into<KeyGroup>) {
+        power::sleep(wake.into());
+    }
+    // allow: `timer::Slot` is meant to not be Copy or Clone, see `Self::free_object`.
+    /// Stop and free a timer, consuming it.
+    #[allow(clippy::needless_pass_by_value)]
+    pub fn free_timer(&mut self, mut slot: timer::Slot) {
+        slot.reset();
+        self.timers.free(slot.id() as u32);
+    }
 }
 
 type GsF<T> = fn(&mut video::Control<mode::Text>, &mut T, &mut ConsoleState);
@@ -134,6 +303,15 @@ pub trait GameState {
     /// The game logic, updates the state based on input for current frame.
     fn logic(&mut self, console: &mut ConsoleState) -> Option<GameStateEnterMode<Self>>;
 
+    /// Called when the [`ConsoleState::soft_reset`] keys are pressed,
+    /// right before [`GameState::logic`], by default resets the console.
+    ///
+    /// This is the place to flush saves, see [`reset`] for details.
+    fn soft_reset(&mut self, console: &mut ConsoleState) -> reset::SoftReset {
+        let _ = console;
+        reset::SoftReset::Console
+    }
+
     /// Draw stuff in [`mode::Text`], text mode is the initial video mode.
     ///
     /// You must handle text mode, if only to setup a different mode you'll
@@ -161,6 +339,33 @@ pub trait GameState {
 /// For the `*_draw` family of functions, they will only be called
 /// if the video mode is the one provided in argument.
 ///
+/// When [`ConsoleState::profiler`] is enabled, the time spent in `logic`
+/// and `*_draw` are recorded as the `"logic"` and `"draw"` spans.
+///
+/// [`ConsoleState::input`] is read from the keypad at the start of the frame,
+/// or from a recording when replaying, see [`replay`]. When the
+/// [`ConsoleState::soft_reset`] keys are pressed, [`GameState::soft_reset`]
+/// is called before `logic`.
+///
+/// Changes to [`ConsoleState::scanline`] are applied at the start of vblank.
+/// When [`ConsoleState::audio`] is enabled, the sound mixed during the last
+/// frame starts playing at the start of vblank, and the sound for the next
+/// frame is mixed after the `*_draw` call, recorded as the `"audio"` span.
+/// [`ConsoleState::psg`] effects are updated at the start of vblank.
+/// Commands queued in [`ConsoleState::commands`] are executed right before
+/// the `*_draw` call, except object commands, which are applied to the RAM
+/// copy of OAM right after `logic`. That copy is uploaded to video memory at
+/// the start of vblank, before anything else, so that a long `*_draw` can't
+/// make the upload happen while the screen is drawn.
+///
+/// Between the `logic` and `*_draw` calls, the CPU is halted until the
+/// next vertical blank, using the [`interrupt::Source::VBlank`] interrupt.
+/// This also installs the master interrupt handler, so that handlers
+/// registered with [`interrupt::set_handler`] are called.
+///
+/// This is [`Runner::step`] in a loop, use [`Runner`] to control when
+/// frames run, for example in tests.
+///
 /// # Safety
 ///
 /// You must not have multiple concurrent instances of [`video::Control`]
@@ -170,45 +375,136 @@ pub trait GameState {
 /// it basically is a big ball of segfaults.
 ///
 /// You have been warned.
-pub unsafe fn full_game<Stt: GameState>(mut state: Stt) -> ! {
+pub unsafe fn full_game<Stt: GameState>(state: Stt) -> ! {
     // SAFETY: upheld by function safety invariants.
-    let mut video_control = ControlModes::Text(unsafe { video::Control::<mode::Text>::init() });
-    let mut console = ConsoleState::DEFAULT;
+    let mut runner = unsafe { Runner::new(state) };
     loop {
-        console.input.previous = mem::replace(&mut console.input.current, KEYINPUT.read());
+        runner.step();
+    }
+}
+
+/// Runs a [`GameState`] one frame at a time.
+///
+/// [`full_game`] runs a `Runner` forever, see its documentation for
+/// what happens each frame.
+///
+/// With the `host` feature, this can be used in tests on a regular
+/// computer, see [`crate::host`].
+pub struct Runner<Stt: GameState> {
+    state: Stt,
+    console: ConsoleState,
+    /// Only `None` while switching video modes.
+    video: Option<ControlModes>,
+}
+impl<Stt: GameState> Runner<Stt> {
+    /// Prepare to run `state`, installing the interrupt handler.
+    ///
+    /// # Safety
+    ///
+    /// There must be at most one `Runner` at a time, and you must not have
+    /// other instances of [`video::Control`] existing at the same time.
+    ///
+    /// With the `host` feature, a [`crate::host::Console`] must be alive
+    /// for as long as the `Runner` is used.
+    pub unsafe fn new(state: Stt) -> Self {
+        // SAFETY: upheld by function safety invariants.
+        let video = ControlModes::Text(unsafe { video::Control::<mode::Text>::init() });
+        // SAFETY: there is a single `Runner`, as upheld by function safety invariants.
+        unsafe { interrupt::init() };
+        Self {
+            state,
+            console: ConsoleState::DEFAULT,
+            video: Some(video),
+        }
+    }
+    #[must_use]
+    pub const fn state(&self) -> &Stt {
+        &self.state
+    }
+    pub fn state_mut(&mut self) -> &mut Stt {
+        &mut self.state
+    }
+    #[must_use]
+    pub const fn console(&self) -> &ConsoleState {
+        &self.console
+    }
+    pub fn console_mut(&mut self) -> &mut ConsoleState {
+        &mut self.console
+    }
+    /// Run a single frame, returns once drawn and audio mixed.
+    pub fn step(&mut self) {
+        let Self { state, console, video } = self;
+        let keys = console.input_source.keys(KEYINPUT.read());
+        console.input.update(keys);
         console.frame = console.frame.wrapping_add(1);
-        let mut enter_video_mode = state.logic(&mut console);
+        crash::set_frame(console.frame);
+        if console.soft_reset.triggered(console.input) {
+            match state.soft_reset(console) {
+                reset::SoftReset::Console => reset::console(),
+                reset::SoftReset::Ignore => {}
+            }
+        }
+        let logic_start = console.profiler.start();
+        let mut enter_video_mode = state.logic(console);
+        console.profiler.record("logic", logic_start);
 
-        spin_until_vblank();
-        video_control = match enter_video_mode.take() {
-            Some(mode) => mode.enter(video_control, &mut state, &mut console),
-            None => video_control,
-        };
-        match &mut video_control {
-            ControlModes::Text(video_control) => state.text_draw(&mut console, video_control),
-            ControlModes::Mixed(video_control) => state.mixed_draw(&mut console, video_control),
-            ControlModes::Affine(video_control) => state.affine_draw(&mut console, video_control),
+        let commands = &mut console.commands;
+        match video {
+            Some(ControlModes::Text(video_control)) => video_control.execute_objects(commands),
+            Some(ControlModes::Mixed(video_control)) => video_control.execute_objects(commands),
+            Some(ControlModes::Affine(video_control)) => video_control.execute_objects(commands),
+            None => {}
+        }
+        wait_vblank();
+        match video {
+            Some(ControlModes::Text(video_control)) => video_control.upload_objects(),
+            Some(ControlModes::Mixed(video_control)) => video_control.upload_objects(),
+            Some(ControlModes::Affine(video_control)) => video_control.upload_objects(),
+            None => {}
+        }
+        console.scanline.upload();
+        console.audio.swap_buffers();
+        console.psg.update();
+        if let Some(mode) = enter_video_mode.take() {
+            if let Some(current) = video.take() {
+                *video = Some(mode.enter(current, state, console));
+            }
         }
-        spin_until_vdraw();
+        let commands = &mut console.commands;
+        match video {
+            Some(ControlModes::Text(video_control)) => video_control.execute(commands),
+            Some(ControlModes::Mixed(video_control)) => video_control.execute(commands),
+            Some(ControlModes::Affine(video_control)) => video_control.execute(commands),
+            None => {}
+        }
+        let draw_start = console.profiler.start();
+        match video {
+            Some(ControlModes::Text(video_control)) => state.text_draw(console, video_control),
+            Some(ControlModes::Mixed(video_control)) => state.mixed_draw(console, video_control),
+            Some(ControlModes::Affine(video_control)) => state.affine_draw(console, video_control),
+            None => {}
+        }
+        console.profiler.record("draw", draw_start);
+        let audio_start = console.profiler.start();
+        console.audio.mix();
+        console.profiler.record("audio", audio_start);
+        console.profiler.end_frame();
     }
 }
 
-#[allow(clippy::missing_const_for_fn, unused_variables, clippy::empty_loop)]
+#[cfg(not(feature = "host"))]
+pub(crate) fn wait_vblank() {
+    crate::bios::VBlankIntrWait();
+}
+
+/// Show a crash screen, and halt until A+B+Start+Select reset the console.
+///
+/// Call this in your `#[panic_handler]`. The crash screen shows the panic
+/// message and location, the frame count and, with the `log-ring` feature,
+/// the last logged lines. The panic message is also logged as an error,
+/// unless the panic happened while logging, in which case neither the message
+/// nor the last logged lines are accessed again.
 pub fn panic_handler(info: &core::panic::PanicInfo) -> ! {
-    #[cfg(feature = "log")]
-    gba::fatal!("{}", info);
-    // SAFETY: resets the whole console
-    #[cfg(not(feature = "log"))]
-    unsafe {
-        use gba::{bios, mmio_types::ResetFlags};
-        bios::RegisterRamReset(
-            ResetFlags::new()
-                .with_vram(true)
-                .with_oam(true)
-                .with_sio(true)
-                .with_sound(true)
-                .with_all_other_io(true),
-        );
-        bios::SoftReset()
-    };
+    crate::error!("{info}");
+    crash::show(info)
 }
@@ -0,0 +1,191 @@
+//! The crash screen, shown by [`panic_handler`].
+//!
+//! On panic, the whole screen is replaced by white text on a blue
+//! background, showing the panic message and location, the frame at which
+//! the game crashed, and the last lines logged with [`crate::info!`] and
+//! friends (only with the `log-ring` feature). The console then halts, until
+//! the [`reset::STANDARD_KEYS`] are pressed to reset it.
+//!
+//! This doesn't depend on any of the game's video state: all registers
+//! used are reset, and a font is bundled with the HAL.
+use core::{
+    fmt::{self, Write},
+    sync::atomic::{AtomicUsize, Ordering},
+};
+
+use volmatrix::rw::{VolAddress, VolBlock};
+
+use crate::{exec::reset, input::KEYINPUT, log};
+
+#[cfg(doc)]
+use crate::exec::panic_handler;
+
+mod font;
+
+const COLUMNS: usize = 30;
+const ROWS: usize = 20;
+const MAP_WIDTH: usize = 32;
+
+const BACKGROUND: u16 = 0x5000;
+const TEXT: u16 = 0x7FFF;
+
+// SAFETY: all of the following are valid GBA memory-mapped registers
+// and memory regions of the given size.
+const DISPCNT: VolAddress<u16> = unsafe { VolAddress::new(0x0400_0000) };
+const BG0CNT: VolAddress<u16> = unsafe { VolAddress::new(0x0400_0008) };
+const BG0HOFS: VolAddress<u16> = unsafe { VolAddress::new(0x0400_0010) };
+const BG0VOFS: VolAddress<u16> = unsafe { VolAddress::new(0x0400_0012) };
+const MOSAIC: VolAddress<u16> = unsafe { VolAddress::new(0x0400_004C) };
+const BLDCNT: VolAddress<u16> = unsafe { VolAddress::new(0x0400_0050) };
+const DMA_CONTROLS: [VolAddress<u16>; 4] = unsafe {
+    [
+        VolAddress::new(0x0400_00BA),
+        VolAddress::new(0x0400_00C6),
+        VolAddress::new(0x0400_00D2),
+        VolAddress::new(0x0400_00DE),
+    ]
+};
+const IME: VolAddress<u16> = unsafe { VolAddress::new(0x0400_0208) };
+const PALETTE: VolBlock<u16, 2> = unsafe { VolBlock::new(0x0500_0000) };
+const TILES: VolBlock<u32, { font::GLYPHS.len() * 8 }> = unsafe { VolBlock::new(0x0600_0000) };
+/// Screen block 31, the last one.
+const MAP: VolBlock<u16, { MAP_WIDTH * MAP_WIDTH }> = unsafe { VolBlock::new(0x0600_F800) };
+
+const FORCED_BLANK: u16 = 1 << 7;
+const DISPLAY_BG0: u16 = 1 << 8;
+const BG0_SBB_31: u16 = 31 << 8;
+
+/// The last frame started by [`crate::exec::Runner::step`].
+static FRAME: AtomicUsize = AtomicUsize::new(0);
+
+pub(crate) fn set_frame(frame: usize) {
+    FRAME.store(frame, Ordering::Relaxed);
+}
+
+/// A 1bpp font row as a 4bpp tile row, using color 1 for set pixels.
+const fn tile_row(glyph_row: u8) -> u32 {
+    let mut row = 0;
+    let mut x = 0;
+    while x < 8 {
+        if glyph_row & (0x80 >> x) != 0 {
+            row |= 1 << (x * 4);
+        }
+        x += 1;
+    }
+    row
+}
+
+/// Writes text to the crash screen, wrapping long lines.
+struct Screen {
+    column: usize,
+    row: usize,
+    /// Text past this row is not shown.
+    last_row: usize,
+}
+impl Screen {
+    fn new_line(&mut self) {
+        self.column = 0;
+        self.row += 1;
+    }
+    fn put(&mut self, c: char) {
+        if c == '\n' {
+            self.new_line();
+            return;
+        }
+        if self.column == COLUMNS {
+            self.new_line();
+        }
+        if self.row > self.last_row {
+            return;
+        }
+        let glyph = match u8::try_from(c) {
+            Ok(c @ font::FIRST..=font::UNKNOWN) => c,
+            Ok(_) | Err(_) => font::UNKNOWN,
+        };
+        let tile = u16::from(glyph - font::FIRST);
+        MAP.index(self.row * MAP_WIDTH + self.column).write(tile);
+        self.column += 1;
+    }
+}
+impl Write for Screen {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        s.chars().for_each(|c| self.put(c));
+        Ok(())
+    }
+}
+
+/// Reset the video registers and memory used by the crash screen.
+fn setup_video() {
+    DISPCNT.write(FORCED_BLANK);
+    for control in DMA_CONTROLS {
+        control.write(0);
+    }
+    BG0CNT.write(BG0_SBB_31);
+    BG0HOFS.write(0);
+    BG0VOFS.write(0);
+    MOSAIC.write(0);
+    BLDCNT.write(0);
+    PALETTE.index(0).write(BACKGROUND);
+    PALETTE.index(1).write(TEXT);
+    for (i, glyph) in font::GLYPHS.iter().enumerate() {
+        for (y, glyph_row) in glyph.iter().enumerate() {
+            TILES.index(i * 8 + y).write(tile_row(*glyph_row));
+        }
+    }
+    let blank = u16::from(b' ' - font::FIRST);
+    MAP.iter().for_each(|entry| entry.write(blank));
+}
+
+/// Draw the crash screen for `info`, at `frame`.
+fn draw(frame: usize, info: &dyn fmt::Display) {
+    setup_video();
+    // Keep the last row for the reset instructions.
+    let mut screen = Screen { column: 0, row: 0, last_row: ROWS - 2 };
+    let _ = write!(screen, "YOU DED :(\nCrashed at frame {frame}\n\n{info}\n");
+    let mut has_logs = false;
+    log::for_each_recent(|line| {
+        if !has_logs {
+            let _ = screen.write_str("\nLast logs:\n");
+            has_logs = true;
+        }
+        let _ = writeln!(screen, "{line}");
+    });
+    let mut screen = Screen {
+        column: 0,
+        row: ROWS - 1,
+        last_row: ROWS - 1,
+    };
+    let _ = screen.write_str("A+B+Start+Select to reset");
+    DISPCNT.write(DISPLAY_BG0);
+}
+
+/// Show the crash screen for `info`, and halt.
+pub(crate) fn show(info: &core::panic::PanicInfo) -> ! {
+    IME.write(0);
+    draw(FRAME.load(Ordering::Relaxed), info);
+    // Interrupts are disabled, so `Halt` would never return, just spin.
+    loop {
+        if KEYINPUT.read().all_pressed(reset::STANDARD_KEYS) {
+            reset::console();
+        }
+    }
+}
+
+#[cfg(all(test, feature = "host"))]
+mod test {
+    use super::{draw, BACKGROUND, TEXT};
+    use crate::{host::Console, video::tile::Color};
+
+    #[test]
+    fn message_on_screen() {
+        let mut console = Console::new();
+        draw(42, &"boom");
+        let frame = console.render();
+        // The left side of the 'Y' of the first line.
+        assert_eq!(frame.pixel(0, 0), Color(BACKGROUND));
+        assert_eq!(frame.pixel(1, 0), Color(TEXT));
+        // The top of the 'b' of "boom", on the 4th line.
+        assert_eq!(frame.pixel(1, 3 * 8), Color(TEXT));
+        assert_eq!(frame.pixel(2, 3 * 8), Color(BACKGROUND));
+    }
+}
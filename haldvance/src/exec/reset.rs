@@ -0,0 +1,171 @@
+//! Soft reset, restarting the game with a key combination.
+//!
+//! Most GBA games reset when A+B+Start+Select are pressed together.
+//! [`crate::exec::full_game`] checks every frame for the keys set in
+//! [`ConsoleState::soft_reset`], and when they are pressed, calls
+//! [`GameState::soft_reset`] before the frame's `logic`.
+//!
+//! By default, this resets the whole console, like turning it off and on
+//! again. The game can flush saves in [`GameState::soft_reset`] before the
+//! reset, or handle the reset itself, for example by returning to the
+//! title screen with [`Scene::soft_reset`].
+//!
+//! [`Scene::soft_reset`]: crate::exec::scene::Scene::soft_reset
+use const_default::ConstDefault;
+
+use crate::input::{Input, Key, KeyGroup};
+
+#[cfg(doc)]
+use crate::exec::{ConsoleState, GameState};
+
+/// The standard soft reset keys, A+B+Start+Select.
+pub const STANDARD_KEYS: KeyGroup = KeyGroup::of(&[Key::A, Key::B, Key::Start, Key::Select]);
+
+/// What to do when the soft reset keys are pressed.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum SoftReset {
+    /// Reset the console.
+    Console,
+    /// Keep running, the game handled the soft reset.
+    Ignore,
+}
+
+/// The keys triggering a soft reset, see [module-level doc](self).
+///
+/// [`STANDARD_KEYS`] by default.
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub struct Trigger {
+    keys: Option<KeyGroup>,
+}
+impl ConstDefault for Trigger {
+    const DEFAULT: Self = Self { keys: Some(STANDARD_KEYS) };
+}
+impl Trigger {
+    /// Never trigger a soft reset.
+    pub const DISABLED: Self = Self { keys: None };
+
+    /// Trigger a soft reset when all `keys` are pressed.
+    ///
+    /// # Panics
+    ///
+    /// If `keys` is empty.
+    #[must_use]
+    pub const fn new(keys: KeyGroup) -> Self {
+        assert!(!keys.is_empty(), "Soft reset keys can't be empty");
+        Self { keys: Some(keys) }
+    }
+    /// The keys triggering a soft reset, `None` if disabled.
+    #[must_use]
+    pub const fn keys(self) -> Option<KeyGroup> {
+        self.keys
+    }
+    /// Whether all keys were pressed this frame, and were not all pressed
+    /// the previous frame.
+    pub(crate) const fn triggered(self, input: Input) -> bool {
+        match self.keys {
+            Some(keys) => input.current.all_pressed(keys) && !input.previous.all_pressed(keys),
+            None => false,
+        }
+    }
+}
+
+/// Reset the whole console, and restart the game from the beginning.
+#[cfg(not(feature = "host"))]
+pub fn console() -> ! {
+    use gba::{bios, mmio_types::ResetFlags};
+
+    // SAFETY: resets the whole console, nothing runs after this.
+    unsafe {
+        bios::RegisterRamReset(
+            ResetFlags::new()
+                .with_vram(true)
+                .with_oam(true)
+                .with_sio(true)
+                .with_sound(true)
+                .with_all_other_io(true),
+        );
+        bios::SoftReset()
+    }
+}
+
+/// Reset the whole console, and restart the game from the beginning.
+///
+/// With the `host` feature, this panics, since there is no game to restart.
+#[cfg(feature = "host")]
+pub fn console() -> ! {
+    panic!("The console was reset");
+}
+
+#[cfg(all(test, feature = "host"))]
+mod test {
+    use super::STANDARD_KEYS;
+    use crate::{
+        exec::{
+            scene::{Scene, Stack, Transition},
+            ConsoleState, Runner,
+        },
+        host::Console,
+        input::{Dir, Key},
+        video::mode,
+    };
+
+    #[derive(Clone, Copy, PartialEq, Eq, Debug)]
+    enum Screen {
+        Title,
+        Game,
+    }
+    impl Scene for Screen {
+        fn mode(&self) -> mode::Type {
+            mode::Type::Text
+        }
+        fn logic(&mut self, console: &mut ConsoleState) -> Transition<Self> {
+            match self {
+                Self::Title if console.input.just_pressed(Key::Dpad(Dir::Up)) => {
+                    Transition::Push(Self::Game)
+                }
+                Self::Title | Self::Game => Transition::Stay,
+            }
+        }
+        fn soft_reset(&mut self, _: &mut ConsoleState) -> Option<Transition<Self>> {
+            Some(Transition::Restart(Self::Title))
+        }
+    }
+
+    #[test]
+    fn return_to_title() {
+        let mut console = Console::new();
+        let stack = Stack::<Screen, 3>::new(Screen::Title);
+        // SAFETY: only Runner in this test, and the console is alive.
+        let mut runner = unsafe { Runner::new(stack) };
+        runner.step();
+        console.press(Key::Dpad(Dir::Up));
+        runner.step();
+        console.release_all();
+        runner.step();
+        assert_eq!(runner.state().top(), Some(&Screen::Game));
+        assert_eq!(runner.state().depth(), 2);
+
+        console.press(STANDARD_KEYS);
+        runner.step();
+        runner.step();
+        assert_eq!(runner.state().top(), Some(&Screen::Title));
+        assert_eq!(runner.state().depth(), 1);
+    }
+
+    #[test]
+    #[should_panic(expected = "The console was reset")]
+    fn full_stack_resets_console() {
+        let mut console = Console::new();
+        let stack = Stack::<Screen, 2>::new(Screen::Title);
+        // SAFETY: only Runner in this test, and the console is alive.
+        let mut runner = unsafe { Runner::new(stack) };
+        runner.step();
+        console.press(Key::Dpad(Dir::Up));
+        runner.step();
+        assert_eq!(runner.state().depth(), 2);
+
+        // No free slot for the restart.
+        console.press(STANDARD_KEYS);
+        runner.step();
+    }
+}
@@ -0,0 +1,328 @@
+//! Hierarchical game states, see [`Stack`].
+//!
+//! A game is often made of several screens, or "scenes": a title screen,
+//! a main menu, the game itself, a pause menu on top of the game etc.
+//! Each of those is a [`Scene`], and a [`Stack`] of scenes is a [`GameState`]
+//! that runs the top-most scene.
+//!
+//! A scene changes the stack by returning a [`Transition`] from
+//! [`Scene::logic`]. Transitions are completed at the next vblank, where
+//! the scenes that are removed from the stack get their [`Scene::exit`] method
+//! called, and the new top-most scene gets its `*_enter` method called, in the
+//! video mode it declared with [`Scene::mode`].
+//!
+//! The root scene of the stack is entered on the first frame, with [`Entry::Start`].
+use arrayvec::ArrayVec;
+
+use crate::{
+    exec::{reset::SoftReset, ConsoleState, EnterMode, GameState, GameStateEnterMode},
+    video::{self, mode, Mode},
+};
+
+/// What to do with the [`Stack`] after a [`Scene::logic`] call.
+pub enum Transition<S> {
+    /// Keep running the current scene.
+    Stay,
+    /// Put a new scene on top of the current one.
+    ///
+    /// The current scene is kept as-is, and will be resumed when the new
+    /// scene is popped.
+    Push(S),
+    /// Remove the current scene, resuming the one bellow it.
+    ///
+    /// Does nothing if the current scene is the root scene.
+    Pop,
+    /// Replace the current scene with a new one.
+    Replace(S),
+    /// Remove all scenes, and start over with a new root scene.
+    ///
+    /// Typically used to return to the title screen, see
+    /// [`Scene::soft_reset`].
+    Restart(S),
+}
+
+/// Why a `Scene::*_enter` method is called.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Entry {
+    /// The scene was just added to the stack.
+    Start,
+    /// The scene on top of this one was popped.
+    ///
+    /// Note that if the popped scene was in a different video mode, video
+    /// memory was probably overwritten, so you might need to redraw
+    /// everything.
+    Resume,
+}
+
+/// A single game screen, see [`Stack`].
+///
+/// Scenes only run when they are on top of the [`Stack`]. All methods but
+/// [`Scene::mode`], [`Scene::logic`] and [`Scene::soft_reset`] do nothing
+/// by default.
+pub trait Scene: Sized {
+    /// The video mode this scene runs in.
+    ///
+    /// Only the `*_enter` and `*_draw` methods for this mode will be called.
+    fn mode(&self) -> mode::Type;
+
+    /// The scene logic, see [`GameState::logic`].
+    fn logic(&mut self, console: &mut ConsoleState) -> Transition<Self>;
+
+    /// Called on the top scene when the soft reset keys are pressed, see
+    /// [`GameState::soft_reset`].
+    ///
+    /// Return `None` to reset the console (the default), or a transition
+    /// to apply instead, typically [`Transition::Restart`] with the title
+    /// screen to return to it. This is the place to flush saves.
+    ///
+    /// Like when returned from [`Scene::logic`], [`Transition::Restart`]
+    /// needs one free slot in the [`Stack`]. If the transition can't be
+    /// applied, because the stack is full or the root scene would be popped,
+    /// the console is reset instead.
+    fn soft_reset(&mut self, console: &mut ConsoleState) -> Option<Transition<Self>> {
+        let _ = console;
+        None
+    }
+
+    /// Setup video memory for this scene when it becomes the top of the stack.
+    fn text_enter(
+        &mut self,
+        entry: Entry,
+        console: &mut ConsoleState,
+        video: &mut video::Control<mode::Text>,
+    ) {
+        let _ = (entry, console, video);
+    }
+    /// Same as [`Scene::text_enter`] for [`mode::Mixed`].
+    fn mixed_enter(
+        &mut self,
+        entry: Entry,
+        console: &mut ConsoleState,
+        video: &mut video::Control<mode::Mixed>,
+    ) {
+        let _ = (entry, console, video);
+    }
+    /// Same as [`Scene::text_enter`] for [`mode::Affine`].
+    fn affine_enter(
+        &mut self,
+        entry: Entry,
+        console: &mut ConsoleState,
+        video: &mut video::Control<mode::Affine>,
+    ) {
+        let _ = (entry, console, video);
+    }
+
+    /// Clean up when the scene is removed from the stack.
+    ///
+    /// This is where you should free object slots with
+    /// [`ConsoleState::free_object`] and unload sprites with
+    /// [`video::Control::unload_sprite`].
+    ///
+    /// Note that `video` is already in the video mode of the next scene.
+    fn exit<M: Mode>(self, console: &mut ConsoleState, video: &mut video::Control<M>) {
+        let _ = (console, video);
+    }
+
+    /// See [`GameState::text_draw`].
+    fn text_draw(&mut self, console: &mut ConsoleState, video: &mut video::Control<mode::Text>) {
+        let _ = (console, video);
+    }
+    /// See [`GameState::mixed_draw`].
+    fn mixed_draw(&mut self, console: &mut ConsoleState, video: &mut video::Control<mode::Mixed>) {
+        let _ = (console, video);
+    }
+    /// See [`GameState::affine_draw`].
+    fn affine_draw(
+        &mut self,
+        console: &mut ConsoleState,
+        video: &mut video::Control<mode::Affine>,
+    ) {
+        let _ = (console, video);
+    }
+}
+
+/// A transition waiting for the next vblank to complete.
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Pending {
+    None,
+    /// The root scene needs to be entered.
+    Start,
+    /// The top scene was just pushed.
+    Push,
+    /// The top scene must be removed.
+    Pop,
+    /// The scene bellow the top one must be removed.
+    Replace,
+    /// All scenes bellow the top one must be removed.
+    Restart,
+}
+
+/// A stack of at most `N` [`Scene`]s, the top one being the running one.
+///
+/// `Stack` implements [`GameState`], so it can directly be passed to
+/// [`crate::exec::full_game`].
+///
+/// Note that [`Transition::Replace`] and [`Transition::Restart`] temporarily
+/// require one additional scene slot, so `N` should be one more than the maximum depth of your
+/// scene stack.
+///
+/// See [module-level doc](self) for details.
+pub struct Stack<S: Scene, const N: usize> {
+    scenes: ArrayVec<S, N>,
+    pending: Pending,
+}
+impl<S: Scene, const N: usize> Stack<S, N> {
+    /// Create a stack with `root` as only scene.
+    ///
+    /// # Panics
+    ///
+    /// If `N` is 0.
+    #[must_use]
+    pub fn new(root: S) -> Self {
+        let mut scenes = ArrayVec::new_const();
+        scenes.push(root);
+        Self { scenes, pending: Pending::Start }
+    }
+    /// The currently running scene.
+    #[must_use]
+    pub fn top(&self) -> Option<&S> {
+        self.scenes.last()
+    }
+    /// How many scenes are in the stack.
+    #[must_use]
+    pub fn depth(&self) -> usize {
+        self.scenes.len()
+    }
+
+    /// Start a transition, returns the mode of the scene to enter.
+    fn begin(&mut self, transition: Transition<S>) -> Option<mode::Type> {
+        let (pending, scene) = match transition {
+            Transition::Stay => return None,
+            Transition::Pop if self.scenes.len() < 2 => {
+                crate::error!("Attempted to pop the root scene, ignoring");
+                return None;
+            }
+            Transition::Pop => {
+                self.pending = Pending::Pop;
+                let bellow_top = self.scenes.len() - 2;
+                return self.scenes.get(bellow_top).map(S::mode);
+            }
+            Transition::Push(scene) => (Pending::Push, scene),
+            Transition::Replace(scene) => (Pending::Replace, scene),
+            Transition::Restart(scene) => (Pending::Restart, scene),
+        };
+        let mode = scene.mode();
+        if self.scenes.try_push(scene).is_err() {
+            crate::error!("Scene stack is full, ignoring transition");
+            return None;
+        }
+        self.pending = pending;
+        Some(mode)
+    }
+    /// Complete the pending transition, returns how the top scene should be entered.
+    fn complete<M: Mode>(
+        &mut self,
+        console: &mut ConsoleState,
+        video: &mut video::Control<M>,
+    ) -> Entry {
+        if self.pending == Pending::Restart {
+            // Exit scenes from the top-most one down to the root.
+            while self.scenes.len() > 1 {
+                let bellow_top = self.scenes.len() - 2;
+                if let Some(to_exit) = self.scenes.pop_at(bellow_top) {
+                    to_exit.exit(console, video);
+                }
+            }
+        }
+        let to_exit = match self.pending {
+            Pending::None | Pending::Start | Pending::Push | Pending::Restart => None,
+            Pending::Pop => self.scenes.pop(),
+            Pending::Replace => {
+                let bellow_top = self.scenes.len().wrapping_sub(2);
+                self.scenes.pop_at(bellow_top)
+            }
+        };
+        if let Some(to_exit) = to_exit {
+            to_exit.exit(console, video);
+        }
+        let entry = match self.pending {
+            Pending::Pop => Entry::Resume,
+            _ => Entry::Start,
+        };
+        self.pending = Pending::None;
+        entry
+    }
+}
+impl<S: Scene, const N: usize> GameState for Stack<S, N> {
+    fn logic(&mut self, console: &mut ConsoleState) -> Option<GameStateEnterMode<Self>> {
+        let mode = if self.pending == Pending::None {
+            let transition = self.scenes.last_mut()?.logic(console);
+            self.begin(transition)
+        } else {
+            // The root scene needs to be entered, or a soft reset restarted the stack.
+            self.top().map(S::mode)
+        }?;
+        let enter: GameStateEnterMode<Self> = match mode {
+            mode::Type::Text => EnterMode::Text(|video, stack, console| {
+                let entry = stack.complete(console, video);
+                if let Some(top) = stack.scenes.last_mut() {
+                    top.text_enter(entry, console, video);
+                }
+            }),
+            mode::Type::Mixed => EnterMode::Mixed(|video, stack, console| {
+                let entry = stack.complete(console, video);
+                if let Some(top) = stack.scenes.last_mut() {
+                    top.mixed_enter(entry, console, video);
+                }
+            }),
+            mode::Type::Affine => EnterMode::Affine(|video, stack, console| {
+                let entry = stack.complete(console, video);
+                if let Some(top) = stack.scenes.last_mut() {
+                    top.affine_enter(entry, console, video);
+                }
+            }),
+        };
+        Some(enter)
+    }
+
+    fn soft_reset(&mut self, console: &mut ConsoleState) -> SoftReset {
+        let transition = match self.scenes.last_mut() {
+            Some(top) => top.soft_reset(console),
+            None => None,
+        };
+        let transition = match transition {
+            Some(Transition::Stay) => return SoftReset::Ignore,
+            Some(transition) if self.pending == Pending::None => transition,
+            Some(_) => {
+                crate::error!("Soft reset during a scene transition, resetting the console");
+                return SoftReset::Console;
+            }
+            None => return SoftReset::Console,
+        };
+        // Entered in the next `logic` call, since `pending` is set.
+        match self.begin(transition) {
+            Some(_) => SoftReset::Ignore,
+            None => SoftReset::Console,
+        }
+    }
+
+    fn text_draw(&mut self, console: &mut ConsoleState, video: &mut video::Control<mode::Text>) {
+        if let Some(top) = self.scenes.last_mut() {
+            top.text_draw(console, video);
+        }
+    }
+    fn mixed_draw(&mut self, console: &mut ConsoleState, video: &mut video::Control<mode::Mixed>) {
+        if let Some(top) = self.scenes.last_mut() {
+            top.mixed_draw(console, video);
+        }
+    }
+    fn affine_draw(
+        &mut self,
+        console: &mut ConsoleState,
+        video: &mut video::Control<mode::Affine>,
+    ) {
+        if let Some(top) = self.scenes.last_mut() {
+            top.affine_draw(console, video);
+        }
+    }
+}
@@ -0,0 +1,419 @@
+//! Run the HAL on a regular computer, see [`Console`].
+//!
+//! Only available with the `host` feature, on x86_64 Linux.
+//!
+//! The HAL writes to hard-coded GBA memory addresses. With the `host`
+//! feature, those addresses are backed by regular memory mapped at the
+//! exact same addresses as on the GBA. All `VolAddress`, `VolBlock` and
+//! `VolMatrix` accesses, including DMA transfers, then read and write a
+//! simulated console. This lets you use [`video::Control`],
+//! [`crate::exec::Runner`] and everything else in ordinary `cargo test`s:
+//!
+//! ```sh
+//! cargo test --package haldvance --target x86_64-unknown-linux-gnu --features host
+//! ```
+//!
+//! Cartridge SRAM is simulated as plain memory, cleared like the rest of the
+//! console by [`Console::new`], so games can use [`crate::save::Sram`] in
+//! tests. Flash and EEPROM are not, use [`crate::save::File`] instead.
+//!
+//! Only memory is simulated: there is no timer counting, and interrupts only
+//! happen when [`Console::vblank`] is called. Waiting for vblank (in
+//! [`crate::exec::Runner::step`]) immediately calls [`Console::vblank`].
+//!
+//! The screen is only drawn when calling [`Console::render`], which returns
+//! a [`Frame`] that can be saved as a PNG image, or compared to a "golden"
+//! image with [`Frame::assert_golden`].
+//!
+//! [`video::Control`]: crate::video::Control
+use std::{
+    ffi::{c_int, c_long, c_void},
+    ptr, slice,
+    sync::{Mutex, MutexGuard, Once},
+};
+
+use crate::{input::KeyGroup, interrupt};
+
+mod render;
+
+pub use render::{Frame, HEIGHT, WIDTH};
+
+/// Memory regions of the GBA that are simulated, as `(address, size)`.
+const REGIONS: [(usize, usize); 8] = [
+    (EWRAM.0, EWRAM.1),
+    (IWRAM.0, IWRAM.1),
+    (IO.0, IO.1),
+    // mGBA debug registers, so that logging doesn't crash.
+    (0x04FF_F000, 0x1000),
+    (PALRAM.0, PALRAM.1),
+    (VRAM.0, VRAM.1),
+    (OAM.0, OAM.1),
+    (SRAM.0, SRAM.1),
+];
+const EWRAM: (usize, usize) = (0x0200_0000, 0x4_0000);
+const IWRAM: (usize, usize) = (0x0300_0000, 0x8000);
+const IO: (usize, usize) = (0x0400_0000, 0x1000);
+const PALRAM: (usize, usize) = (0x0500_0000, 0x400);
+const VRAM: (usize, usize) = (0x0600_0000, 0x1_8000);
+const OAM: (usize, usize) = (0x0700_0000, 0x400);
+const SRAM: (usize, usize) = (0x0E00_0000, 0x8000);
+
+const DISPCNT: usize = 0x0400_0000;
+const DISPSTAT: usize = 0x0400_0004;
+const VCOUNT: usize = 0x0400_0006;
+const KEYINPUT: usize = 0x0400_0130;
+const KEYCNT: usize = 0x0400_0132;
+const IE: usize = 0x0400_0200;
+const IF: usize = 0x0400_0202;
+const IME: usize = 0x0400_0208;
+/// `BG2PA`, `BG2PD`, `BG3PA` and `BG3PD`, set to `0x100` by the BIOS.
+const AFFINE_IDENTITY: [usize; 4] = [0x0400_0020, 0x0400_0026, 0x0400_0030, 0x0400_0036];
+const NO_KEYS: u16 = 0x03FF;
+const FORCED_BLANK: u16 = 1 << 7;
+const KEYCNT_IRQ: u16 = 1 << 14;
+const FIRST_VBLANK_LINE: u16 = 160;
+
+const PROT_READ: c_int = 0x1;
+const PROT_WRITE: c_int = 0x2;
+const MAP_PRIVATE: c_int = 0x02;
+const MAP_ANONYMOUS: c_int = 0x20;
+const MAP_FIXED_NOREPLACE: c_int = 0x10_0000;
+const PAGE_SIZE: usize = 0x1000;
+
+extern "C" {
+    fn mmap(
+        addr: *mut c_void,
+        len: usize,
+        prot: c_int,
+        flags: c_int,
+        fd: c_int,
+        offset: c_long,
+    ) -> *mut c_void;
+}
+
+static MAP_MEMORY: Once = Once::new();
+static LOCK: Mutex<()> = Mutex::new(());
+
+/// Map the simulated memory regions at their GBA addresses.
+///
+/// # Panics
+///
+/// If any region can't be mapped at its exact address.
+fn map_memory() {
+    MAP_MEMORY.call_once(|| {
+        for (address, size) in REGIONS {
+            let size = size.next_multiple_of(PAGE_SIZE);
+            let flags = MAP_PRIVATE | MAP_ANONYMOUS | MAP_FIXED_NOREPLACE;
+            // SAFETY: MAP_FIXED_NOREPLACE fails rather than replacing
+            // existing mappings.
+            let mapped = unsafe {
+                mmap(
+                    address as *mut c_void,
+                    size,
+                    PROT_READ | PROT_WRITE,
+                    flags,
+                    -1,
+                    0,
+                )
+            };
+            assert_eq!(
+                mapped as usize, address,
+                "Couldn't map simulated GBA memory at {address:#010x}"
+            );
+        }
+    });
+}
+
+// SAFETY (for all following functions): `map_memory` was called, since
+// they are only called while a `Console` is alive.
+fn read(address: usize) -> u16 {
+    unsafe { ptr::read_volatile(address as *const u16) }
+}
+fn write(address: usize, value: u16) {
+    unsafe { ptr::write_volatile(address as *mut u16, value) };
+}
+fn region((address, size): (usize, usize)) -> &'static [u8] {
+    unsafe { slice::from_raw_parts(address as *const u8, size) }
+}
+
+/// Exclusive access to the simulated console.
+///
+/// There can only be one `Console` at a time, [`Console::new`] blocks until
+/// any other `Console` is dropped. Since tests run in parallel, you should
+/// keep the `Console` alive for the whole test.
+///
+/// See [module-level doc](self) for details.
+pub struct Console {
+    _lock: MutexGuard<'static, ()>,
+}
+impl Console {
+    /// Get exclusive access to the simulated console, and reset it.
+    ///
+    /// The reset state is the state after the BIOS boot: all memory set
+    /// to zero, except for an identity affine transform on backgrounds,
+    /// and no keys pressed.
+    #[must_use]
+    pub fn new() -> Self {
+        map_memory();
+        // A test panicking while holding the lock doesn't matter, since
+        // we reset the whole memory anyway.
+        let lock = LOCK
+            .lock()
+            .unwrap_or_else(std::sync::PoisonError::into_inner);
+        for (address, size) in REGIONS {
+            // SAFETY: all regions are mapped by `map_memory`.
+            unsafe { ptr::write_bytes(address as *mut u8, 0, size) };
+        }
+        for register in AFFINE_IDENTITY {
+            write(register, 0x100);
+        }
+        write(KEYINPUT, NO_KEYS);
+        Self { _lock: lock }
+    }
+    /// Set the keys currently pressed, releasing all others.
+    pub fn press(&mut self, keys: impl Into<KeyGroup>) {
+        write(KEYINPUT, keys.into().keyinput());
+    }
+    /// Release all keys.
+    pub fn release_all(&mut self) {
+        write(KEYINPUT, NO_KEYS);
+    }
+    /// Simulate the start of vblank.
+    ///
+    /// This sets `VCOUNT` to 160 and triggers the VBlank interrupt, calling
+    /// the handlers set with [`interrupt::set_handler`] if interrupts are enabled.
+    pub fn vblank(&mut self) {
+        vblank();
+    }
+    /// Simulate interrupt `source` happening.
+    ///
+    /// Does nothing if `source` is not enabled, see [`interrupt::enable`].
+    pub fn raise(&mut self, source: interrupt::Source) {
+        raise(source);
+    }
+    /// The background and object palettes.
+    #[must_use]
+    pub fn palram(&self) -> &[u8] {
+        region(PALRAM)
+    }
+    /// The tile and map memory.
+    #[must_use]
+    pub fn vram(&self) -> &[u8] {
+        region(VRAM)
+    }
+    /// The object attributes memory.
+    #[must_use]
+    pub fn oam(&self) -> &[u8] {
+        region(OAM)
+    }
+    /// The I/O registers.
+    #[must_use]
+    pub fn io(&self) -> &[u8] {
+        region(IO)
+    }
+    /// The 16 bits I/O register at `address`, for example `0x0400_0000`
+    /// for `DISPCNT`.
+    ///
+    /// # Panics
+    ///
+    /// If `address` is not in the I/O registers range.
+    #[must_use]
+    pub fn io_register(&self, address: usize) -> u16 {
+        assert!(
+            (IO.0..IO.0 + IO.1).contains(&address),
+            "{address:#x} is not an I/O register"
+        );
+        read(address)
+    }
+}
+impl Default for Console {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+fn vblank() {
+    write(VCOUNT, FIRST_VBLANK_LINE);
+    write(DISPSTAT, read(DISPSTAT) | 1);
+    raise(interrupt::Source::VBlank);
+}
+fn raise(source: interrupt::Source) {
+    let mask = 1 << source as u16;
+    if read(IE) & mask == 0 {
+        return;
+    }
+    write(IF, read(IF) | mask);
+    if read(IME) & 1 != 0 {
+        // SAFETY: on host, the master handler is a regular function, and
+        // like on GBA, it is never called concurrently with itself.
+        unsafe { interrupt::master_handler() };
+    }
+}
+
+/// Stand-in for the `VBlankIntrWait` BIOS call.
+///
+/// Requires a live [`Console`], see [`crate::exec::Runner::new`].
+pub(crate) fn wait_vblank() {
+    vblank();
+}
+
+/// Stand-in for the `Stop` BIOS call, immediately woken up by the
+/// keypad interrupt.
+///
+/// # Panics
+///
+/// If the display is not blanked, or if the keypad interrupt is not
+/// enabled, since on GBA, the console would never wake up.
+pub(crate) fn stop() {
+    let keypad = interrupt::Source::Keypad;
+    assert!(
+        read(DISPCNT) & FORCED_BLANK != 0,
+        "Stopped with the display on"
+    );
+    assert!(
+        read(KEYCNT) & KEYCNT_IRQ != 0 && read(IE) & 1 << keypad as u16 != 0,
+        "Stopped without the keypad interrupt, the console would never wake up"
+    );
+    raise(keypad);
+}
+
+#[cfg(test)]
+mod test {
+    use const_default::ConstDefault;
+
+    use super::Console;
+    use crate::{
+        exec::{ConsoleState, GameState, GameStateEnterMode, Runner},
+        input::Key,
+        video::{self, command, mode, object, tile::Color, Pos, Tile},
+    };
+
+    #[test]
+    fn load_palette() {
+        let console = Console::new();
+        // SAFETY: only Control in this test.
+        let mut ctrl = unsafe { video::Control::<mode::Text>::init() };
+        ctrl.load_palette(&[Color(0x1234), Color(0x7FFF)]);
+        assert_eq!(&console.palram()[..4], &[0x34, 0x12, 0xFF, 0x7F]);
+    }
+
+    #[test]
+    fn text_tiles() {
+        let console = Console::new();
+        // SAFETY: only Control in this test.
+        let mut ctrl = unsafe { video::Control::<mode::Text>::init() };
+        let sbb = video::tile::sbb::Slot::_16;
+        ctrl.basic_sbb(sbb)
+            .set_tile(Tile::new(5), Pos { x: 1, y: 2 });
+        let entry = 16 * 0x800 + (2 * 32 + 1) * 2;
+        assert_eq!(console.vram()[entry], 5);
+    }
+
+    #[test]
+    fn objects_uploaded() {
+        let console = Console::new();
+        let mut allocator = object::Allocator::DEFAULT;
+        let slot = allocator.reserve().unwrap();
+        // SAFETY: only Control in this test.
+        let mut ctrl = unsafe { video::Control::<mode::Text>::init() };
+        ctrl.object(&slot).set_pos(Pos { x: 100, y: 42 });
+        ctrl.object(&slot).set_visible(true);
+        assert_eq!(console.oam()[0], 0, "objects are only written at upload");
+        ctrl.upload_objects();
+        let index = slot.get() as usize * 8;
+        assert_eq!(console.oam()[index + 1] & 0b10, 0, "object is visible");
+        assert_eq!(console.oam()[index], 42);
+        assert_eq!(console.oam()[index + 2], 100);
+        allocator.free(slot);
+    }
+
+    #[test]
+    fn objects_sorted() {
+        let console = Console::new();
+        let mut allocator = object::Allocator::DEFAULT;
+        let top = allocator.reserve().unwrap();
+        let bottom = allocator.reserve().unwrap();
+        // SAFETY: only Control in this test.
+        let mut ctrl = unsafe { video::Control::<mode::Text>::init() };
+        ctrl.set_object_sorting(true);
+        for (slot, y) in [(&top, 10), (&bottom, 100)] {
+            ctrl.object(slot).set_pos(Pos { x: y + 1, y });
+            ctrl.object(slot).set_visible(true);
+        }
+        ctrl.upload_objects();
+        assert_eq!(console.oam()[0], 100, "lower object comes first");
+        assert_eq!(console.oam()[2], 101);
+        assert_eq!(console.oam()[8], 10);
+        assert_eq!(console.oam()[8 + 2], 11);
+        assert_eq!(console.oam()[16 + 1] & 0b10, 0b10, "then hidden objects");
+
+        ctrl.set_object_sorting(false);
+        ctrl.upload_objects();
+        let index = top.get() as usize * 8;
+        assert_eq!(console.oam()[index], 10, "slot order without sorting");
+        allocator.free(bottom);
+        allocator.free(top);
+    }
+
+    /// Moves an object and changes a tile in the same `logic`.
+    struct MoveObject(Option<object::Slot>);
+    impl GameState for MoveObject {
+        fn logic(&mut self, console: &mut ConsoleState) -> Option<GameStateEnterMode<Self>> {
+            let slot = self
+                .0
+                .get_or_insert_with(|| console.reserve_object().unwrap());
+            let pos = Pos { x: 100, y: 42 };
+            console.commands.object(slot, command::Object::Pos(pos));
+            let sbb = video::tile::sbb::Slot::_16;
+            let size = video::tile::map::TextSize::Base;
+            console
+                .commands
+                .text_tile(sbb, size, Pos { x: 1, y: 2 }, Tile::new(5));
+            None
+        }
+        fn text_draw(&mut self, _: &mut ConsoleState, _: &mut video::Control<mode::Text>) {}
+    }
+
+    #[test]
+    fn runner_objects_with_commands() {
+        let console = Console::new();
+        // SAFETY: only Runner in this test, and the console is alive.
+        let mut runner = unsafe { Runner::new(MoveObject(None)) };
+        runner.step();
+        let index = runner.state().0.as_ref().unwrap().get() as usize * 8;
+        assert_eq!(console.vram()[16 * 0x800 + (2 * 32 + 1) * 2], 5);
+        assert_eq!(
+            console.oam()[index],
+            42,
+            "objects uploaded in the same frame"
+        );
+        assert_eq!(console.oam()[index + 2], 100);
+    }
+
+    /// Counts frames where `A` is pressed.
+    struct CountA(usize);
+    impl GameState for CountA {
+        fn logic(&mut self, console: &mut ConsoleState) -> Option<GameStateEnterMode<Self>> {
+            if console.input.pressed(Key::A) {
+                self.0 += 1;
+            }
+            None
+        }
+        fn text_draw(&mut self, _: &mut ConsoleState, _: &mut video::Control<mode::Text>) {}
+    }
+
+    #[test]
+    fn runner_input() {
+        let mut console = Console::new();
+        // SAFETY: only Runner in this test, and the console is alive.
+        let mut runner = unsafe { Runner::new(CountA(0)) };
+        runner.step();
+        console.press(Key::A);
+        runner.step();
+        runner.step();
+        console.release_all();
+        runner.step();
+        assert_eq!(runner.state().0, 2);
+        assert_eq!(runner.console().frame, 4);
+    }
+}
@@ -0,0 +1,520 @@
+//! Software rendering of the simulated console, see [`Console::render`].
+// allow: the PPU works with a mix of 8, 9, 10, 16 and 32 bits values,
+// casting between them is the point of this module.
+#![allow(
+    clippy::cast_possible_truncation,
+    clippy::cast_possible_wrap,
+    clippy::cast_sign_loss
+)]
+use std::{fs, io, path::Path};
+
+use super::{raise, read, write, Console, DISPCNT, FORCED_BLANK, OAM, PALRAM, VCOUNT, VRAM};
+use crate::{interrupt::Source, video::tile::Color};
+
+/// Width of the GBA screen in pixels.
+pub const WIDTH: usize = 240;
+/// Height of the GBA screen in pixels.
+pub const HEIGHT: usize = 160;
+
+const BGCNT: usize = 0x0400_0008;
+const BG_OFFSET: usize = 0x0400_0010;
+const BG_AFFINE: usize = 0x0400_0020;
+const LAST_LINE: u16 = 227;
+
+const OBJ_TILES: usize = 0x1_0000;
+const OBJ_PALETTE: usize = 256;
+const TRANSPARENT: u8 = 0;
+
+const PNG_SIGNATURE: [u8; 8] = [0x89, b'P', b'N', b'G', b'\r', b'\n', 0x1A, b'\n'];
+/// Largest block size of the deflate "stored" encoding.
+const STORED_BLOCK: usize = 0xFFFF;
+
+/// Size in pixels of objects, indexed by `[shape][size]`.
+const OBJ_SIZES: [[(usize, usize); 4]; 3] = [
+    [(8, 8), (16, 16), (32, 32), (64, 64)],
+    [(16, 8), (32, 8), (32, 16), (64, 32)],
+    [(8, 16), (8, 32), (16, 32), (32, 64)],
+];
+
+/// A rendered 240×160 frame, see [`Console::render`].
+pub struct Frame {
+    pixels: Vec<Color>,
+}
+impl Frame {
+    /// The color of pixel at `x`, `y`.
+    ///
+    /// # Panics
+    ///
+    /// If `x` or `y` are outside of the screen.
+    #[must_use]
+    pub fn pixel(&self, x: usize, y: usize) -> Color {
+        assert!(
+            x < WIDTH && y < HEIGHT,
+            "({x}, {y}) is outside of the screen"
+        );
+        self.pixels[x + y * WIDTH]
+    }
+    /// Encode the frame as an uncompressed RGB PNG image.
+    #[must_use]
+    pub fn to_png(&self) -> Vec<u8> {
+        let mut raw = Vec::with_capacity(HEIGHT * (1 + WIDTH * 3));
+        for row in self.pixels.chunks(WIDTH) {
+            // The "None" PNG filter.
+            raw.push(0);
+            raw.extend(row.iter().flat_map(|color| rgb888(*color)));
+        }
+        let mut header = Vec::with_capacity(13);
+        header.extend(u32::try_from(WIDTH).unwrap().to_be_bytes());
+        header.extend(u32::try_from(HEIGHT).unwrap().to_be_bytes());
+        // 8 bits depth, RGB, deflate, no filter, no interlace.
+        header.extend([8, 2, 0, 0, 0]);
+
+        let mut png = PNG_SIGNATURE.to_vec();
+        png_chunk(&mut png, *b"IHDR", &header);
+        png_chunk(&mut png, *b"IDAT", &zlib_stored(&raw));
+        png_chunk(&mut png, *b"IEND", &[]);
+        png
+    }
+    /// Write the frame as a PNG image at `path`.
+    ///
+    /// # Errors
+    ///
+    /// If writing to `path` fails.
+    pub fn save_png(&self, path: impl AsRef<Path>) -> io::Result<()> {
+        fs::write(path, self.to_png())
+    }
+    /// Check that the frame is identical to the PNG at `path`.
+    ///
+    /// If the `BLESS` environment variable is set, write the frame at `path`
+    /// instead, so that golden images can be created or updated by running
+    /// the tests once with `BLESS=1`.
+    ///
+    /// # Panics
+    ///
+    /// If `path` doesn't exist or the frame is different from the image at
+    /// `path`, in which case the frame is written next to it with the
+    /// `.new.png` extension, or if reading or writing images fails.
+    pub fn assert_golden(&self, path: impl AsRef<Path>) {
+        let path = path.as_ref();
+        let png = self.to_png();
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent).unwrap();
+        }
+        if std::env::var_os("BLESS").is_some() {
+            fs::write(path, png).unwrap();
+            return;
+        }
+        let new_path = path.with_extension("new.png");
+        if !path.exists() {
+            fs::write(&new_path, png).unwrap();
+            panic!(
+                "Missing golden image {}, see {}. Run with BLESS=1 to create it.",
+                path.display(),
+                new_path.display(),
+            );
+        }
+        let golden = fs::read(path).unwrap();
+        if golden != png {
+            fs::write(&new_path, png).unwrap();
+            panic!(
+                "Frame differs from {}, see {}. Run with BLESS=1 to accept the new frame.",
+                path.display(),
+                new_path.display(),
+            );
+        }
+    }
+}
+
+fn rgb888(color: Color) -> [u8; 3] {
+    let channel = |shift: u16| {
+        let value = ((color.0 >> shift) & 0x1F) as u8;
+        value << 3 | value >> 2
+    };
+    [channel(0), channel(5), channel(10)]
+}
+fn crc32(bytes: impl IntoIterator<Item = u8>) -> u32 {
+    let mut crc = !0_u32;
+    for byte in bytes {
+        crc ^= u32::from(byte);
+        for _ in 0..8 {
+            let mask = (crc & 1).wrapping_neg();
+            crc = (crc >> 1) ^ (0xEDB8_8320 & mask);
+        }
+    }
+    !crc
+}
+fn adler32(bytes: &[u8]) -> u32 {
+    let (mut a, mut b) = (1_u32, 0_u32);
+    for byte in bytes {
+        a = (a + u32::from(*byte)) % 65521;
+        b = (b + a) % 65521;
+    }
+    b << 16 | a
+}
+fn png_chunk(png: &mut Vec<u8>, kind: [u8; 4], data: &[u8]) {
+    png.extend(u32::try_from(data.len()).unwrap().to_be_bytes());
+    png.extend(kind);
+    png.extend(data);
+    png.extend(crc32(kind.into_iter().chain(data.iter().copied())).to_be_bytes());
+}
+/// A zlib stream of `data` with no compression.
+fn zlib_stored(data: &[u8]) -> Vec<u8> {
+    let mut stream = vec![0x78, 0x01];
+    let mut blocks = data.chunks(STORED_BLOCK).peekable();
+    while let Some(block) = blocks.next() {
+        let is_last = blocks.peek().is_none();
+        let len = u16::try_from(block.len()).unwrap();
+        stream.push(u8::from(is_last));
+        stream.extend(len.to_le_bytes());
+        stream.extend((!len).to_le_bytes());
+        stream.extend(block);
+    }
+    stream.extend(adler32(data).to_be_bytes());
+    stream
+}
+
+fn read32(address: usize) -> i32 {
+    let low = u32::from(read(address));
+    let high = u32::from(read(address + 2));
+    (high << 16 | low) as i32
+}
+
+/// A background, as configured in its `BGxCNT` register.
+#[derive(Clone, Copy)]
+struct Background {
+    index: usize,
+    priority: u8,
+    tiles: usize,
+    map: usize,
+    is_8bpp: bool,
+    wraps: bool,
+    size: u16,
+}
+impl Background {
+    fn new(index: usize) -> Self {
+        let control = read(BGCNT + index * 2);
+        Self {
+            index,
+            priority: (control & 0b11) as u8,
+            tiles: usize::from((control >> 2) & 0b11) * 0x4000,
+            map: usize::from((control >> 8) & 0x1F) * 0x800,
+            is_8bpp: control & (1 << 7) != 0,
+            wraps: control & (1 << 13) != 0,
+            size: control >> 14,
+        }
+    }
+}
+
+/// The affine reference point as the PPU sees it, updated each line.
+#[derive(Clone, Copy, Default)]
+struct AffineReference {
+    /// The `BGxX` and `BGxY` register values when last loaded.
+    registers: (i32, i32),
+    current: (i32, i32),
+}
+
+fn color(index: usize) -> Color {
+    Color(read(PALRAM.0 + index * 2))
+}
+fn vram_u8(address: usize) -> u8 {
+    if address >= VRAM.1 {
+        return TRANSPARENT;
+    }
+    let half_word = read(VRAM.0 + (address & !1));
+    (half_word >> ((address & 1) * 8)) as u8
+}
+fn vram_u16(address: usize) -> u16 {
+    u16::from(vram_u8(address)) | u16::from(vram_u8(address + 1)) << 8
+}
+fn oam_u16(address: usize) -> u16 {
+    read(OAM.0 + address)
+}
+/// Palette index of pixel `x`, `y` of `tile`, `None` if transparent.
+fn tile_pixel(tile_address: usize, is_8bpp: bool, bank: u16, x: usize, y: usize) -> Option<usize> {
+    let index = if is_8bpp {
+        vram_u8(tile_address + y * 8 + x)
+    } else {
+        let byte = vram_u8(tile_address + y * 4 + x / 2);
+        let nibble = if x % 2 == 0 { byte & 0xF } else { byte >> 4 };
+        match nibble {
+            TRANSPARENT => TRANSPARENT,
+            nibble => nibble + (bank as u8) * 16,
+        }
+    };
+    (index != TRANSPARENT).then_some(usize::from(index))
+}
+fn text_line(bg: Background, y: usize, line: &mut [Option<usize>; WIDTH]) {
+    let width = if bg.size & 1 == 0 { 256 } else { 512 };
+    let height = if bg.size & 2 == 0 { 256 } else { 512 };
+    let x_offset = usize::from(read(BG_OFFSET + bg.index * 4) & 0x1FF);
+    let y_offset = usize::from(read(BG_OFFSET + bg.index * 4 + 2) & 0x1FF);
+    let map_y = (y + y_offset) % height;
+    for (x, pixel) in line.iter_mut().enumerate() {
+        let map_x = (x + x_offset) % width;
+        let block = map_x / 256 + (map_y / 256) * (width / 256);
+        let (tile_x, tile_y) = ((map_x % 256) / 8, (map_y % 256) / 8);
+        let entry_address = bg.map + block * 0x800 + (tile_x + tile_y * 32) * 2;
+        let entry = vram_u16(entry_address);
+        let mut in_tile = (map_x % 8, map_y % 8);
+        if entry & (1 << 10) != 0 {
+            in_tile.0 = 7 - in_tile.0;
+        }
+        if entry & (1 << 11) != 0 {
+            in_tile.1 = 7 - in_tile.1;
+        }
+        let tile_size = if bg.is_8bpp { 64 } else { 32 };
+        let tile_address = bg.tiles + usize::from(entry & 0x3FF) * tile_size;
+        let bank = entry >> 12;
+        *pixel = tile_pixel(tile_address, bg.is_8bpp, bank, in_tile.0, in_tile.1);
+    }
+}
+/// Palette index and priority of objects on line `y`.
+fn object_line(y: usize, is_1d: bool, line: &mut [Option<(usize, u8)>; WIDTH]) {
+    for entry in 0..128 {
+        let attr0 = oam_u16(entry * 8);
+        let attr1 = oam_u16(entry * 8 + 2);
+        let attr2 = oam_u16(entry * 8 + 4);
+        let is_affine = attr0 & (1 << 8) != 0;
+        let is_double = attr0 & (1 << 9) != 0;
+        let mode = (attr0 >> 10) & 0b11;
+        let shape = usize::from(attr0 >> 14);
+        // Hidden, object window, or invalid
+        if (!is_affine && is_double) || mode >= 2 || shape == 3 {
+            continue;
+        }
+        let (width, height) = OBJ_SIZES[shape][usize::from(attr1 >> 14)];
+        let scale = if is_double { 2 } else { 1 };
+        let (box_width, box_height) = (width * scale, height * scale);
+        let row = (y + 256 - usize::from(attr0 & 0xFF)) % 256;
+        if row >= box_height {
+            continue;
+        }
+        let x_pos = i32::from(attr1 & 0x1FF);
+        let x_pos = if x_pos >= WIDTH as i32 { x_pos - 512 } else { x_pos };
+
+        let is_8bpp = attr0 & (1 << 13) != 0;
+        let priority = ((attr2 >> 10) & 0b11) as u8;
+        let tile_units = if is_8bpp { 2 } else { 1 };
+        let row_units = if is_1d { width / 8 * tile_units } else { 32 };
+        let affine = usize::from((attr1 >> 9) & 0x1F) * 32;
+        let param = |offset: usize| i32::from(oam_u16(affine + offset) as i16);
+        for column in 0..box_width {
+            let x = x_pos + column as i32;
+            if !(0..WIDTH as i32).contains(&x) {
+                continue;
+            }
+            let (tex_x, tex_y) = if is_affine {
+                let dx = column as i32 - box_width as i32 / 2;
+                let dy = row as i32 - box_height as i32 / 2;
+                let tex_x = ((param(6) * dx + param(14) * dy) >> 8) + width as i32 / 2;
+                let tex_y = ((param(22) * dx + param(30) * dy) >> 8) + height as i32 / 2;
+                if !(0..width as i32).contains(&tex_x) || !(0..height as i32).contains(&tex_y) {
+                    continue;
+                }
+                (tex_x as usize, tex_y as usize)
+            } else {
+                let tex_x = if attr1 & (1 << 12) != 0 {
+                    width - 1 - column
+                } else {
+                    column
+                };
+                let tex_y = if attr1 & (1 << 13) != 0 { height - 1 - row } else { row };
+                (tex_x, tex_y)
+            };
+            let tile_offset = (tex_y / 8) * row_units + (tex_x / 8) * tile_units;
+            let tile = usize::from(attr2 & 0x3FF) + tile_offset;
+            let tile_address = OBJ_TILES + (tile % 1024) * 32;
+            let bank = attr2 >> 12;
+            let index = tile_pixel(tile_address, is_8bpp, bank, tex_x % 8, tex_y % 8);
+            let (index, current) = match (index, &mut line[x as usize]) {
+                (Some(index), current) => (index, current),
+                (None, _) => continue,
+            };
+            // Lower OAM entries are in front at equal priority.
+            if current.map_or(true, |(_, current)| priority < current) {
+                *current = Some((OBJ_PALETTE + index, priority));
+            }
+        }
+    }
+}
+
+/// The PPU state while drawing a frame.
+///
+/// Memory is read with volatile reads, since interrupt handlers called
+/// between lines may write to it.
+#[derive(Default)]
+struct Renderer {
+    references: [AffineReference; 2],
+}
+impl Renderer {
+    fn affine_line(&mut self, bg: Background, line: &mut [Option<usize>; WIDTH]) {
+        let params = BG_AFFINE + (bg.index - 2) * 0x10;
+        let param = |offset: usize| i32::from(read(params + offset) as i16);
+        let (a, b, c, d) = (param(0), param(2), param(4), param(6));
+        let registers = (read32(params + 8), read32(params + 12));
+        let reference = &mut self.references[bg.index - 2];
+        if reference.registers != registers {
+            *reference = AffineReference { registers, current: registers };
+        }
+        let (start_x, start_y) = reference.current;
+        reference.current = (start_x + b, start_y + d);
+
+        let size = 128_i32 << bg.size;
+        for (x, pixel) in (0_i32..).zip(line.iter_mut()) {
+            let mut map_x = (start_x + a * x) >> 8;
+            let mut map_y = (start_y + c * x) >> 8;
+            if bg.wraps {
+                map_x = map_x.rem_euclid(size);
+                map_y = map_y.rem_euclid(size);
+            } else if !(0..size).contains(&map_x) || !(0..size).contains(&map_y) {
+                *pixel = None;
+                continue;
+            }
+            let (map_x, map_y) = (map_x as usize, map_y as usize);
+            let tile_per_row = size as usize / 8;
+            let tile = vram_u8(bg.map + map_x / 8 + (map_y / 8) * tile_per_row);
+            let tile_address = bg.tiles + usize::from(tile) * 64;
+            *pixel = tile_pixel(tile_address, true, 0, map_x % 8, map_y % 8);
+        }
+    }
+    fn line(&mut self, y: usize, pixels: &mut Vec<Color>) {
+        let display = read(DISPCNT);
+        if display & FORCED_BLANK != 0 {
+            // Forced blank, the screen is white.
+            pixels.extend([Color(0x7FFF); WIDTH]);
+            return;
+        }
+        let is_affine = |bg: usize| match display & 0b111 {
+            0 => Some(false),
+            1 if bg < 2 => Some(false),
+            1 if bg == 2 => Some(true),
+            2 if bg >= 2 => Some(true),
+            _ => None,
+        };
+        let mut layers = [[None; WIDTH]; 4];
+        let mut backgrounds = Vec::with_capacity(4);
+        for (index, layer) in layers.iter_mut().enumerate() {
+            if display & (1 << (8 + index)) == 0 {
+                continue;
+            }
+            let bg = Background::new(index);
+            match is_affine(index) {
+                Some(false) => text_line(bg, y, layer),
+                Some(true) => self.affine_line(bg, layer),
+                None => continue,
+            }
+            backgrounds.push(bg);
+        }
+        let mut objects = [None; WIDTH];
+        if display & (1 << 12) != 0 {
+            object_line(y, display & (1 << 6) != 0, &mut objects);
+        }
+        for x in 0..WIDTH {
+            // Objects are in front of backgrounds of the same priority,
+            // backgrounds with lower index are in front of higher ones.
+            let background = backgrounds
+                .iter()
+                .filter_map(|bg| Some((bg.priority, bg.index, layers[bg.index][x]?)))
+                .min();
+            let index = match (objects[x], background) {
+                (Some((obj, obj_priority)), Some((bg_priority, _, bg))) => {
+                    if obj_priority <= bg_priority {
+                        obj
+                    } else {
+                        bg
+                    }
+                }
+                (Some((obj, _)), None) => obj,
+                (None, Some((_, _, bg))) => bg,
+                // Backdrop
+                (None, None) => 0,
+            };
+            pixels.push(color(index));
+        }
+    }
+}
+
+impl Console {
+    /// Draw the current frame, as the GBA would.
+    ///
+    /// The [`interrupt::Source::HBlank`] interrupt is triggered before each
+    /// line, if enabled, so per-line effects (see [`video::scanline`])
+    /// are visible in the frame.
+    ///
+    /// This supports:
+    /// - [`mode::Text`], [`mode::Mixed`] and [`mode::Affine`] video modes
+    /// - 4bpp and 8bpp tiles
+    /// - background scrolling, sizes, priorities, and affine transforms
+    /// - regular and affine objects, including flips and priorities
+    /// - layer enables and forced blank in `DISPCNT`
+    ///
+    /// Bitmap video modes, windows, blending and mosaic are not supported,
+    /// they are ignored.
+    ///
+    /// [`interrupt::Source::HBlank`]: crate::interrupt::Source::HBlank
+    /// [`video::scanline`]: crate::video::scanline
+    /// [`mode::Text`]: crate::video::mode::Text
+    /// [`mode::Mixed`]: crate::video::mode::Mixed
+    /// [`mode::Affine`]: crate::video::mode::Affine
+    #[must_use]
+    pub fn render(&mut self) -> Frame {
+        let vcount = read(VCOUNT);
+        let mut renderer = Renderer::default();
+        let mut pixels = Vec::with_capacity(WIDTH * HEIGHT);
+        for y in 0..HEIGHT {
+            // The HBlank of the previous line prepares this line.
+            let previous_line = if y == 0 { LAST_LINE } else { y as u16 - 1 };
+            write(VCOUNT, previous_line);
+            raise(Source::HBlank);
+            renderer.line(y, &mut pixels);
+        }
+        write(VCOUNT, vcount);
+        Frame { pixels }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{write, Console, BGCNT, DISPCNT, OAM, OBJ_TILES, PALRAM, VRAM};
+    use crate::video::tile::Color;
+
+    const BACKDROP: Color = Color(0x0001);
+    const BG_COLOR: Color = Color(0x001F);
+    const OBJ_COLOR: Color = Color(0x03E0);
+
+    #[test]
+    fn text_background_and_object() {
+        let mut console = Console::new();
+        write(PALRAM.0, BACKDROP.0);
+        write(PALRAM.0 + 2, BG_COLOR.0);
+        write(PALRAM.0 + 0x202, OBJ_COLOR.0);
+        // Tile 1 of both BG and OBJ: only the left column is opaque.
+        for row in 0..8 {
+            write(VRAM.0 + 32 + row * 4, 0x0001);
+            write(VRAM.0 + OBJ_TILES + 32 + row * 4, 0x0001);
+        }
+        // SBB 8: tile 1 flipped horizontally, then tile 1.
+        write(VRAM.0 + 8 * 0x800, 1 | 1 << 10);
+        write(VRAM.0 + 8 * 0x800 + 2, 1);
+        write(BGCNT, 8 << 8);
+        // Object 0: tile 1 flipped horizontally at (16, 4).
+        write(OAM.0, 4);
+        write(OAM.0 + 2, 16 | 1 << 12);
+        write(OAM.0 + 4, 1);
+        // BG0, objects, 1D object tile mapping.
+        write(DISPCNT, 1 << 8 | 1 << 12 | 1 << 6);
+
+        let frame = console.render();
+        assert_eq!(frame.pixel(0, 3), BACKDROP);
+        assert_eq!(frame.pixel(7, 3), BG_COLOR);
+        assert_eq!(frame.pixel(8, 3), BG_COLOR);
+        assert_eq!(frame.pixel(9, 3), BACKDROP);
+        assert_eq!(frame.pixel(16, 4), BACKDROP);
+        assert_eq!(frame.pixel(23, 4), OBJ_COLOR);
+        assert_eq!(frame.pixel(23, 3), BACKDROP);
+
+        let png = frame.to_png();
+        assert_eq!(&png[1..4], b"PNG");
+        assert_eq!(&png[png.len() - 8..png.len() - 4], b"IEND");
+    }
+}
@@ -7,8 +7,14 @@ use const_default::ConstDefault;
 
 use volmatrix::{Safe, VolAddress};
 
+pub mod action;
+pub mod replay;
+pub mod sequence;
+
 // SAFETY: non-zero, proper access pattern
 pub(crate) const KEYINPUT: VolAddress<Keys, Safe, ()> = unsafe { VolAddress::new(0x0400_0130) };
+/// The bits of `KEYINPUT` that are actual keys.
+const ALL_KEYS: u16 = 0x03FF;
 
 /// The GBA buttons state.
 #[repr(transparent)]
@@ -50,6 +56,12 @@ pub enum Dir {
 /// A GBA button.
 #[derive(Clone, Copy, PartialEq, Eq)]
 pub struct Key(u16);
+impl Key {
+    /// Index of this key in `KEYINPUT`, and in [`Input`] hold counters.
+    const fn index(self) -> usize {
+        self.0.trailing_zeros() as usize
+    }
+}
 
 // To emulate enum members with consts
 #[allow(non_snake_case, non_upper_case_globals)]
@@ -82,6 +94,28 @@ impl Key {
 /// ```
 #[derive(Clone, Copy, PartialEq, Eq)]
 pub struct KeyGroup(u16);
+impl KeyGroup {
+    /// A group of all `keys`, usable in `const` contexts, unlike `|`.
+    #[must_use]
+    pub const fn of(keys: &[Key]) -> Self {
+        let mut bits = 0;
+        let mut i = 0;
+        while i < keys.len() {
+            bits |= keys[i].0;
+            i += 1;
+        }
+        Self(bits)
+    }
+    /// The raw key bits, as in `KEYCNT`.
+    pub(crate) const fn bits(self) -> u16 {
+        self.0
+    }
+    /// Whether this group has no keys.
+    #[must_use]
+    pub const fn is_empty(self) -> bool {
+        self.0 == 0
+    }
+}
 impl ops::BitOr<Self> for KeyGroup {
     type Output = Self;
 
@@ -103,6 +137,13 @@ impl ops::BitOr<Key> for KeyGroup {
         Self(self.0 | rhs.0)
     }
 }
+#[cfg(any(test, feature = "host"))]
+impl KeyGroup {
+    /// The `KEYINPUT` value when exactly the keys of this group are pressed.
+    pub(crate) const fn keyinput(self) -> u16 {
+        !self.0 & ALL_KEYS
+    }
+}
 impl From<Dir> for KeyGroup {
     fn from(dir: Dir) -> Self {
         Key::Dpad(dir).into()
@@ -118,12 +159,116 @@ impl From<Key> for KeyGroup {
 ///
 /// In [`crate::exec::full_game`], the `Input` struct passed as argument
 /// to the `logic` method is updated every frame.
+///
+/// Besides the current and previous state of buttons, `Input` tracks
+/// for how many frames each button is held, see [`Input::held_for`],
+/// [`Input::released_after`] and [`Input::repeated`].
 #[derive(Clone, Copy, ConstDefault)]
 pub struct Input {
     pub(crate) current: Keys,
     pub(crate) previous: Keys,
+    /// For how many frames each key has been held, 0 when released.
+    held: [u16; KEY_COUNT],
+    /// For how many frames each key was held before last release.
+    last_held: [u16; KEY_COUNT],
+    repeat: Repeat,
 }
+
+/// How many buttons the GBA has.
+const KEY_COUNT: usize = 10;
+
+/// Auto-repeat timing, see [`Input::repeated`].
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub struct Repeat {
+    /// How many frames to wait after the initial press before repeating.
+    pub delay: u16,
+    /// How many frames between repeats.
+    pub rate: u16,
+}
+impl ConstDefault for Repeat {
+    const DEFAULT: Self = Self { delay: 20, rate: 4 };
+}
+
 impl Input {
+    /// Forget all keys state, as if no keys were ever pressed, keeping
+    /// the [`Repeat`] timing.
+    pub(crate) fn reset(&mut self) {
+        *self = Self { repeat: self.repeat, ..Self::DEFAULT };
+    }
+    /// Update with this frame's `keys`.
+    pub(crate) fn update(&mut self, keys: Keys) {
+        self.previous = core::mem::replace(&mut self.current, keys);
+        let counters = self.held.iter_mut().zip(&mut self.last_held);
+        for (index, (held, last_held)) in counters.enumerate() {
+            if keys.0 & (1 << index) == 0 {
+                *held = held.saturating_add(1);
+            } else if *held != 0 {
+                *last_held = *held;
+                *held = 0;
+            }
+        }
+    }
+    /// Set the auto-repeat timing of [`Self::repeated`].
+    ///
+    /// A `rate` of 0 is treated as 1.
+    pub fn set_repeat(&mut self, repeat: Repeat) {
+        self.repeat = Repeat { rate: repeat.rate.max(1), ..repeat };
+    }
+    /// For how many frames `key` has been held, including this frame.
+    ///
+    /// Returns 0 if `key` is not pressed.
+    #[must_use]
+    pub const fn held_for(self, key: Key) -> u16 {
+        self.held[key.index()]
+    }
+    /// If `key` was released this frame, for how many frames it was held.
+    ///
+    /// Useful for charge mechanics, where releasing the button triggers
+    /// an action depending on how long it was held.
+    #[must_use]
+    pub const fn released_after(self, key: Key) -> Option<u16> {
+        if self.just_released(key) {
+            Some(self.last_held[key.index()])
+        } else {
+            None
+        }
+    }
+    /// Whether `key` was just pressed, or is held long enough to repeat.
+    ///
+    /// This is like a keyboard key held down: `true` on the first frame,
+    /// then after [`Repeat::delay`] frames, `true` every [`Repeat::rate`]
+    /// frames. See [`Self::set_repeat`] to change the timing.
+    #[must_use]
+    pub const fn repeated(self, key: Key) -> bool {
+        let Repeat { delay, rate } = self.repeat;
+        match self.held_for(key) {
+            0 => false,
+            1 => true,
+            held => {
+                let since_press = held - 1;
+                since_press >= delay && (since_press - delay) % rate == 0
+            }
+        }
+    }
+    /// Like [`Self::just_direction`], but with auto-repeat, see [`Self::repeated`].
+    #[must_use]
+    pub const fn repeated_direction(self) -> Option<Dir> {
+        match () {
+            () if self.repeated(Key::Dpad(Dir::Down)) => Some(Dir::Down),
+            () if self.repeated(Key::Dpad(Dir::Up)) => Some(Dir::Up),
+            () if self.repeated(Key::Dpad(Dir::Left)) => Some(Dir::Left),
+            () if self.repeated(Key::Dpad(Dir::Right)) => Some(Dir::Right),
+            () => None,
+        }
+    }
+    /// All keys that were just pressed this frame.
+    ///
+    /// Useful to let the player pick a key, for example when rebinding
+    /// controls, see [`action::ActionMap::bind`].
+    #[must_use]
+    pub const fn just_pressed_keys(self) -> KeyGroup {
+        KeyGroup(!self.current.0 & self.previous.0 & ALL_KEYS)
+    }
     #[must_use]
     pub const fn current(self) -> Keys {
         self.current
@@ -179,3 +324,53 @@ impl Input {
         !current && previous
     }
 }
+
+#[cfg(test)]
+mod test {
+    use const_default::ConstDefault;
+
+    use super::{Input, Key, Keys, Repeat};
+
+    /// `Keys` with only `key` pressed.
+    const fn only(key: Key) -> Keys {
+        Keys(!key.0)
+    }
+
+    #[test]
+    fn repeated_after_delay_at_rate() {
+        let mut input = Input::DEFAULT;
+        input.set_repeat(Repeat { delay: 3, rate: 2 });
+        let expected = [true, false, false, true, false, true, false, true, false];
+        for (frame, expected) in expected.into_iter().enumerate() {
+            input.update(only(Key::A));
+            assert_eq!(input.repeated(Key::A), expected, "frame {frame}");
+        }
+        assert!(!input.repeated(Key::B));
+
+        input.update(Keys::DEFAULT);
+        assert!(!input.repeated(Key::A));
+        input.update(only(Key::A));
+        assert!(input.repeated(Key::A), "Pressing again restarts the delay");
+        input.update(only(Key::A));
+        assert!(!input.repeated(Key::A));
+    }
+
+    #[test]
+    fn held_for_across_release() {
+        let mut input = Input::DEFAULT;
+        for frame in 1..=3 {
+            input.update(only(Key::A));
+            assert_eq!(input.held_for(Key::A), frame);
+            assert_eq!(input.released_after(Key::A), None);
+        }
+        input.update(Keys::DEFAULT);
+        assert_eq!(input.held_for(Key::A), 0);
+        assert_eq!(input.released_after(Key::A), Some(3));
+
+        input.update(Keys::DEFAULT);
+        assert_eq!(input.released_after(Key::A), None);
+        input.update(only(Key::A));
+        assert_eq!(input.held_for(Key::A), 1);
+        assert_eq!(input.held_for(Key::B), 0);
+    }
+}
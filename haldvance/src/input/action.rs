@@ -0,0 +1,210 @@
+//! Action-based input, see [`ActionMap`].
+//!
+//! Instead of checking specific keys, the game defines an enum of actions
+//! (fire, confirm, pause…) and an [`ActionMap`] binding each action to a
+//! [`KeyGroup`]. An action is pressed when **any** of the keys bound to it
+//! is pressed.
+//!
+//! Bindings can be changed at runtime with [`ActionMap::bind`], for example
+//! in an options screen, using [`Input::just_pressed_keys`] to pick the
+//! new keys. Use [`ActionMap::encode`] and [`ActionMap::decode`] to store
+//! the bindings, for example in save memory.
+//!
+//! ```ignore
+//! use haldvance::input::{action::ActionMap, Key};
+//!
+//! #[derive(Clone, Copy, PartialEq, Eq)]
+//! enum Action { Fire, Pause }
+//!
+//! let mut controls = ActionMap::new([
+//!     (Action::Fire, Key::A.into()),
+//!     (Action::Pause, Key::Start.into()),
+//! ]);
+//! // Swap fire to B
+//! assert!(controls.bind(Action::Fire, Key::B));
+//! // In the game logic
+//! if controls.pressed(console.input, Action::Fire) {
+//!     // ...
+//! }
+//! ```
+use super::{Input, KeyGroup, ALL_KEYS};
+
+/// Size of a single encoded binding.
+const BINDING_SIZE: usize = 2;
+
+/// Bindings of actions `A` to keys, see [module-level doc](self).
+///
+/// Actions not in the map are never pressed.
+#[derive(Clone, Copy)]
+pub struct ActionMap<A, const N: usize> {
+    defaults: [(A, KeyGroup); N],
+    bindings: [KeyGroup; N],
+}
+impl<A: Copy + PartialEq, const N: usize> ActionMap<A, N> {
+    /// How many bytes [`Self::encode`] writes.
+    pub const ENCODED_LEN: usize = N * BINDING_SIZE;
+
+    /// A map with the `defaults` bindings.
+    #[must_use]
+    pub const fn new(defaults: [(A, KeyGroup); N]) -> Self {
+        let mut bindings = [KeyGroup(0); N];
+        let mut i = 0;
+        while i < N {
+            bindings[i] = defaults[i].1;
+            i += 1;
+        }
+        Self { defaults, bindings }
+    }
+    fn index(&self, action: A) -> Option<usize> {
+        self.defaults.iter().position(|(bound, _)| *bound == action)
+    }
+    /// The keys bound to `action`.
+    #[must_use]
+    pub fn binding(&self, action: A) -> KeyGroup {
+        match self.index(action) {
+            Some(i) => self.bindings[i],
+            None => KeyGroup(0),
+        }
+    }
+    /// Bind `action` to `keys`, replacing its previous binding.
+    ///
+    /// Returns `false` and does nothing if `action` is not in the map, or if
+    /// `keys` is empty, since an action bound to no keys could never be
+    /// pressed, and [`Self::decode`] rejects empty bindings.
+    pub fn bind(&mut self, action: A, keys: impl Into<KeyGroup>) -> bool {
+        let keys = keys.into();
+        match self.index(action) {
+            Some(i) if keys.0 != 0 => {
+                self.bindings[i] = keys;
+                true
+            }
+            _ => false,
+        }
+    }
+    /// Restore the bindings passed to [`Self::new`].
+    pub fn reset(&mut self) {
+        for (binding, (_, default)) in self.bindings.iter_mut().zip(&self.defaults) {
+            *binding = *default;
+        }
+    }
+    /// Is any key bound to `action` pressed?
+    #[must_use]
+    pub fn pressed(&self, input: Input, action: A) -> bool {
+        input.current.any_pressed(self.binding(action))
+    }
+    /// Was `action` just pressed this frame?
+    #[must_use]
+    pub fn just_pressed(&self, input: Input, action: A) -> bool {
+        let keys = self.binding(action);
+        input.current.any_pressed(keys) && !input.previous.any_pressed(keys)
+    }
+    /// Was `action` just released this frame?
+    #[must_use]
+    pub fn just_released(&self, input: Input, action: A) -> bool {
+        let keys = self.binding(action);
+        !input.current.any_pressed(keys) && input.previous.any_pressed(keys)
+    }
+    /// Write the bindings in `bytes`, returns how many bytes were written.
+    ///
+    /// Bindings are written in the order of [`Self::new`]'s `defaults`, so
+    /// [`Self::decode`] requires a map with the same actions in the same order.
+    ///
+    /// Returns `None` if `bytes` is smaller than [`Self::ENCODED_LEN`].
+    pub fn encode(&self, bytes: &mut [u8]) -> Option<usize> {
+        let bytes = bytes.get_mut(..Self::ENCODED_LEN)?;
+        for (binding, bytes) in self
+            .bindings
+            .iter()
+            .zip(bytes.chunks_exact_mut(BINDING_SIZE))
+        {
+            bytes.copy_from_slice(&binding.0.to_le_bytes());
+        }
+        Some(Self::ENCODED_LEN)
+    }
+    /// Read bindings written with [`Self::encode`].
+    ///
+    /// Returns `false` and keeps the current bindings if `bytes` doesn't
+    /// contain valid bindings, for example when reading uninitialized
+    /// save memory.
+    pub fn decode(&mut self, bytes: &[u8]) -> bool {
+        let bytes = match bytes.get(..Self::ENCODED_LEN) {
+            Some(bytes) => bytes,
+            None => return false,
+        };
+        let mut bindings = self.bindings;
+        for (binding, bytes) in bindings.iter_mut().zip(bytes.chunks_exact(BINDING_SIZE)) {
+            let keys = u16::from_le_bytes([bytes[0], bytes[1]]);
+            if keys == 0 || keys & !ALL_KEYS != 0 {
+                return false;
+            }
+            *binding = KeyGroup(keys);
+        }
+        self.bindings = bindings;
+        true
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use const_default::ConstDefault;
+
+    use super::ActionMap;
+    use crate::input::{Input, Key, KeyGroup, Keys};
+
+    #[derive(Clone, Copy, PartialEq, Eq)]
+    enum Action {
+        Fire,
+        Pause,
+        Unbound,
+    }
+    const CONTROLS: ActionMap<Action, 2> = ActionMap::new([
+        (Action::Fire, KeyGroup::of(&[Key::A, Key::R])),
+        (Action::Pause, KeyGroup::of(&[Key::Start])),
+    ]);
+
+    fn input(frames: &[KeyGroup]) -> Input {
+        let mut input = Input::DEFAULT;
+        for keys in frames {
+            input.update(Keys(keys.keyinput()));
+        }
+        input
+    }
+
+    #[test]
+    fn rebind_and_decode() {
+        let mut controls = CONTROLS;
+        let fire = input(&[KeyGroup::of(&[]), Key::R.into()]);
+        assert!(controls.just_pressed(fire, Action::Fire));
+        assert!(!controls.pressed(fire, Action::Pause));
+        assert!(!controls.pressed(fire, Action::Unbound));
+
+        assert!(controls.bind(Action::Fire, Key::B));
+        assert!(!controls.pressed(fire, Action::Fire));
+
+        let mut bytes = [0; ActionMap::<Action, 2>::ENCODED_LEN];
+        controls.encode(&mut bytes).unwrap();
+        let mut decoded = CONTROLS;
+        assert!(decoded.decode(&bytes));
+        assert!(decoded.binding(Action::Fire) == Key::B.into());
+        assert!(!decoded.decode(&[0xFF; 4]), "invalid keys are rejected");
+        assert!(decoded.binding(Action::Fire) == Key::B.into());
+
+        decoded.reset();
+        assert!(decoded.binding(Action::Fire) == CONTROLS.binding(Action::Fire));
+    }
+
+    #[test]
+    fn empty_binding_round_trip() {
+        let mut controls = CONTROLS;
+        assert!(!controls.bind(Action::Pause, KeyGroup::of(&[])));
+        assert!(!controls.bind(Action::Unbound, Key::B));
+        assert!(controls.binding(Action::Pause) == Key::Start.into());
+
+        let mut bytes = [0; ActionMap::<Action, 2>::ENCODED_LEN];
+        controls.encode(&mut bytes).unwrap();
+        let mut decoded = CONTROLS;
+        assert!(decoded.bind(Action::Fire, Key::B));
+        assert!(decoded.decode(&bytes), "bound keys always decode");
+        assert!(decoded.binding(Action::Fire) == CONTROLS.binding(Action::Fire));
+    }
+}
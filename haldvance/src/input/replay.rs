@@ -0,0 +1,306 @@
+//! Input recording and replay, see [`Recording`].
+//!
+//! While recording, the keys read each frame are stored in a fixed buffer,
+//! run-length encoded: holding the same keys for several frames only takes
+//! a single [`Run`]. While replaying, [`crate::exec::full_game`] uses the
+//! recorded keys instead of reading the hardware.
+//!
+//! Starting a recording or a replay also seeds [`ConsoleState::rng`], resets
+//! [`ConsoleState::input`] and, for a replay, restores [`ConsoleState::frame`]
+//! to its value when the recording started, so that given the same game state
+//! when starting, replaying reproduces the exact same game. Typically, you
+//! would start recording when entering gameplay, and start the replay at the
+//! same point.
+//!
+//! Use [`Recording::encode`] and [`Recording::decode`] to store recordings,
+//! for example in save memory, to get reproducible bug reports, attract-mode
+//! demos or regression runs.
+//!
+//! ```ignore
+//! // SAFETY: `RUNS` is only accessed here.
+//! let buffer = unsafe { &mut RUNS };
+//! console.start_recording(buffer, 0xBAD5EED);
+//! // ... later
+//! let recording = console.stop_recording().unwrap();
+//! console.start_replay(recording);
+//! ```
+use const_default::ConstDefault;
+
+use super::Keys;
+
+#[cfg(doc)]
+use crate::exec::ConsoleState;
+
+const HEADER_SIZE: usize = 16;
+const RUN_SIZE: usize = 4;
+
+/// The keys held for a number of frames.
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub struct Run {
+    keys: Keys,
+    frames: u16,
+}
+impl ConstDefault for Run {
+    const DEFAULT: Self = Self { keys: Keys::DEFAULT, frames: 0 };
+}
+impl Run {
+    /// The keys held during this run.
+    #[must_use]
+    pub const fn keys(self) -> Keys {
+        self.keys
+    }
+    /// How many frames the keys were held.
+    #[must_use]
+    pub const fn frames(self) -> u16 {
+        self.frames
+    }
+}
+
+/// A recording of inputs, see [module-level doc](self).
+pub struct Recording {
+    seed: u64,
+    start_frame: usize,
+    runs: &'static mut [Run],
+    len: usize,
+}
+impl Recording {
+    /// An empty recording, storing runs in `buffer`, using `seed` as
+    /// [`ConsoleState::rng`] seed, starting at [`ConsoleState::frame`]
+    /// `start_frame`.
+    #[must_use]
+    pub fn new(buffer: &'static mut [Run], seed: u64, start_frame: usize) -> Self {
+        Self { seed, start_frame, runs: buffer, len: 0 }
+    }
+    #[must_use]
+    pub const fn seed(&self) -> u64 {
+        self.seed
+    }
+    /// The [`ConsoleState::frame`] when the recording started.
+    #[must_use]
+    pub const fn start_frame(&self) -> usize {
+        self.start_frame
+    }
+    /// The recorded runs.
+    #[must_use]
+    pub fn runs(&self) -> &[Run] {
+        &self.runs[..self.len]
+    }
+    /// How many frames were recorded.
+    #[must_use]
+    pub fn frames(&self) -> usize {
+        self.runs().iter().map(|run| usize::from(run.frames)).sum()
+    }
+    /// Whether the buffer is full, in which case recording stopped.
+    #[must_use]
+    pub fn is_full(&self) -> bool {
+        self.len == self.runs.len()
+    }
+    /// Give back the buffer, to record something else.
+    #[must_use]
+    pub fn into_buffer(self) -> &'static mut [Run] {
+        self.runs
+    }
+    /// How many bytes [`Self::encode`] writes.
+    #[must_use]
+    pub const fn encoded_len(&self) -> usize {
+        HEADER_SIZE + self.len * RUN_SIZE
+    }
+    /// Write the recording in `bytes`, returns how many bytes were written.
+    ///
+    /// Returns `None` if `bytes` is smaller than [`Self::encoded_len`].
+    pub fn encode(&self, bytes: &mut [u8]) -> Option<usize> {
+        let len = self.encoded_len();
+        let bytes = bytes.get_mut(..len)?;
+        let (header, runs) = bytes.split_at_mut(HEADER_SIZE);
+        header[..8].copy_from_slice(&self.seed.to_le_bytes());
+        // allow: the buffer is in the GBA memory, so smaller than u32::MAX,
+        // and `usize` is 32 bits on the GBA.
+        #[allow(clippy::cast_possible_truncation)]
+        let (start_frame, runs_len) = (self.start_frame as u32, self.len as u32);
+        header[8..12].copy_from_slice(&start_frame.to_le_bytes());
+        header[12..].copy_from_slice(&runs_len.to_le_bytes());
+        for (run, bytes) in self.runs().iter().zip(runs.chunks_exact_mut(RUN_SIZE)) {
+            bytes[..2].copy_from_slice(&run.keys.0.to_le_bytes());
+            bytes[2..].copy_from_slice(&run.frames.to_le_bytes());
+        }
+        Some(len)
+    }
+    /// Read a recording written with [`Self::encode`], storing runs in `buffer`.
+    ///
+    /// Returns `None` if `bytes` is not a valid recording, or if it doesn't
+    /// fit in `buffer`.
+    #[must_use]
+    pub fn decode(bytes: &[u8], buffer: &'static mut [Run]) -> Option<Self> {
+        let header = bytes.get(..HEADER_SIZE)?;
+        let seed = u64::from_le_bytes(header[..8].try_into().ok()?);
+        let start_frame = u32::from_le_bytes(header[8..12].try_into().ok()?);
+        let start_frame = usize::try_from(start_frame).ok()?;
+        let len = u32::from_le_bytes(header[12..].try_into().ok()?);
+        let len = usize::try_from(len).ok()?;
+        let runs = bytes.get(HEADER_SIZE..HEADER_SIZE + len.checked_mul(RUN_SIZE)?)?;
+        let buffer_runs = buffer.get_mut(..len)?;
+        for (run, bytes) in buffer_runs.iter_mut().zip(runs.chunks_exact(RUN_SIZE)) {
+            let keys = Keys(u16::from_le_bytes([bytes[0], bytes[1]]));
+            let frames = u16::from_le_bytes([bytes[2], bytes[3]]);
+            *run = Run { keys, frames };
+        }
+        Some(Self { seed, start_frame, runs: buffer, len })
+    }
+    /// Add a frame with `keys` held, returns `false` if the buffer is full.
+    fn push(&mut self, keys: Keys) -> bool {
+        if let Some(last) = self.len.checked_sub(1).map(|i| &mut self.runs[i]) {
+            if last.keys == keys && last.frames < u16::MAX {
+                last.frames += 1;
+                return true;
+            }
+        }
+        match self.runs.get_mut(self.len) {
+            Some(run) => {
+                *run = Run { keys, frames: 1 };
+                self.len += 1;
+                true
+            }
+            None => false,
+        }
+    }
+}
+
+/// Where `ConsoleState` reads inputs from.
+pub(crate) enum Source {
+    Hardware,
+    Record(Recording),
+    Replay {
+        recording: Recording,
+        run: usize,
+        frame: u16,
+    },
+}
+impl ConstDefault for Source {
+    const DEFAULT: Self = Self::Hardware;
+}
+impl Source {
+    /// The keys for this frame, given the `hardware` keys.
+    pub(crate) fn keys(&mut self, hardware: Keys) -> Keys {
+        match self {
+            Self::Hardware => hardware,
+            Self::Record(recording) => {
+                let was_full = recording.is_full();
+                let just_filled = !recording.push(hardware) && !was_full;
+                crate::warn!((just_filled) "Input recording buffer is full, stopped recording");
+                hardware
+            }
+            Self::Replay { recording, run, frame } => match recording.runs().get(*run) {
+                Some(current) => {
+                    *frame += 1;
+                    if *frame >= current.frames {
+                        *run += 1;
+                        *frame = 0;
+                    }
+                    current.keys
+                }
+                None => hardware,
+            },
+        }
+    }
+    pub(crate) fn is_replaying(&self) -> bool {
+        match self {
+            Self::Replay { recording, run, .. } => *run < recording.len,
+            Self::Hardware | Self::Record(_) => false,
+        }
+    }
+}
+
+#[cfg(all(test, feature = "host"))]
+mod test {
+    use const_default::ConstDefault;
+
+    use super::{Recording, Run};
+    use crate::{
+        exec::{ConsoleState, GameState, GameStateEnterMode, Runner},
+        host::Console,
+        input::{Dir, Key},
+        video::{self, mode},
+    };
+
+    /// Sums random numbers and frame numbers, depending on input.
+    #[derive(Default)]
+    struct Game(u64);
+    impl GameState for Game {
+        fn logic(&mut self, console: &mut ConsoleState) -> Option<GameStateEnterMode<Self>> {
+            if console.input.pressed(Key::A) {
+                let frame = console.frame as u64;
+                self.0 = self.0.wrapping_add(console.rng.gameplay.u64() ^ frame);
+            }
+            if console.input.just_pressed(Key::Dpad(Dir::Up)) {
+                self.0 = self.0.rotate_left(7);
+            }
+            None
+        }
+        fn text_draw(&mut self, _: &mut ConsoleState, _: &mut video::Control<mode::Text>) {}
+    }
+
+    fn buffer() -> &'static mut [Run] {
+        Box::leak(Box::new([Run::DEFAULT; 8]))
+    }
+
+    #[test]
+    fn record_and_replay() {
+        let mut console = Console::new();
+        // SAFETY: only Runner in this test, and the console is alive.
+        let mut runner = unsafe { Runner::new(Game::default()) };
+        runner.console_mut().start_recording(buffer(), 1234);
+        let inputs = [Key::A | Key::Dpad(Dir::Up), Key::A.into(), Key::B.into()];
+        for keys in inputs {
+            console.press(keys);
+            runner.step();
+            runner.step();
+        }
+        let recorded = runner.state().0;
+        let recording = runner.console_mut().stop_recording().unwrap();
+        assert_eq!(recording.runs().len(), 3);
+        assert_eq!(recording.frames(), 6);
+
+        let mut bytes = [0; 64];
+        let len = recording.encode(&mut bytes).unwrap();
+        let recording = Recording::decode(&bytes[..len], buffer()).unwrap();
+
+        console.release_all();
+        *runner.state_mut() = Game::default();
+        runner.console_mut().start_replay(recording);
+        while runner.console().is_replaying() {
+            runner.step();
+        }
+        assert_eq!(runner.state().0, recorded);
+    }
+
+    #[test]
+    fn replay_restores_frame_and_input() {
+        let mut console = Console::new();
+        // SAFETY: only Runner in this test, and the console is alive.
+        let mut runner = unsafe { Runner::new(Game::default()) };
+        // Up is held when the recording starts, but not when the replay starts.
+        console.press(Key::Dpad(Dir::Up));
+        runner.step();
+        runner.console_mut().start_recording(buffer(), 42);
+        for keys in [Key::A | Key::Dpad(Dir::Up), Key::A.into()] {
+            console.press(keys);
+            runner.step();
+            runner.step();
+        }
+        let recorded = runner.state().0;
+        let recording = runner.console_mut().stop_recording().unwrap();
+        assert_eq!(recording.start_frame(), 1);
+
+        console.release_all();
+        for _ in 0..5 {
+            runner.step();
+        }
+        *runner.state_mut() = Game::default();
+        runner.console_mut().start_replay(recording);
+        assert_eq!(runner.console().frame, 1);
+        while runner.console().is_replaying() {
+            runner.step();
+        }
+        assert_eq!(runner.state().0, recorded);
+    }
+}
@@ -0,0 +1,133 @@
+//! Button sequences and chords detection, see [`Matcher`].
+//!
+//! A [`Sequence`] is a list of steps, each step being a [`KeyGroup`] that
+//! must be pressed at the same time. A single step sequence is a chord,
+//! such as L+R+Select, while a multi-step sequence is a code, such as the
+//! Konami code. The steps must be pressed in order, with less than the
+//! sequence timeout frames between each step. Pressing a key that is not
+//! part of the current step restarts the sequence.
+//!
+//! ```ignore
+//! use haldvance::input::{sequence::{Matcher, Sequence}, Dir, Key, KeyGroup};
+//!
+//! #[derive(Clone, Copy)]
+//! enum Cheat { Invincible, DebugMenu }
+//!
+//! const UP: KeyGroup = KeyGroup::of(&[Key::Dpad(Dir::Up)]);
+//! const DOWN: KeyGroup = KeyGroup::of(&[Key::Dpad(Dir::Down)]);
+//! const DEBUG: KeyGroup = KeyGroup::of(&[Key::L, Key::R, Key::Select]);
+//!
+//! let mut cheats = Matcher::new([
+//!     (Cheat::Invincible, Sequence::new(&[UP, UP, DOWN, DOWN], 30)),
+//!     (Cheat::DebugMenu, Sequence::new(&[DEBUG], 0)),
+//! ]);
+//! // In the game logic
+//! if let Some(cheat) = cheats.update(console.input) {
+//!     // ...
+//! }
+//! ```
+use super::{Input, KeyGroup, Keys};
+
+/// A sequence of key presses, see [module-level doc](self).
+#[derive(Clone, Copy)]
+pub struct Sequence {
+    steps: &'static [KeyGroup],
+    timeout: u16,
+}
+impl Sequence {
+    /// A sequence of `steps`, with at most `timeout` frames between each step.
+    ///
+    /// # Panics
+    ///
+    /// If `steps` is empty.
+    #[must_use]
+    pub const fn new(steps: &'static [KeyGroup], timeout: u16) -> Self {
+        assert!(
+            !steps.is_empty(),
+            "A key sequence must have at least one step"
+        );
+        Self { steps, timeout }
+    }
+}
+
+/// How far in a [`Sequence`] the player is.
+#[derive(Clone, Copy)]
+struct Progress {
+    step: usize,
+    /// Frames since the last completed step.
+    frames: u16,
+}
+impl Progress {
+    const START: Self = Self { step: 0, frames: 0 };
+
+    /// Update with this frame's `just_pressed` keys, returns `true` if
+    /// the sequence just completed.
+    fn update(&mut self, sequence: &Sequence, just_pressed: u16, held: Keys) -> bool {
+        if self.step != 0 {
+            self.frames = self.frames.saturating_add(1);
+            if self.frames > sequence.timeout {
+                *self = Self::START;
+            }
+        }
+        if just_pressed == 0 {
+            return false;
+        }
+        loop {
+            let expected = sequence.steps[self.step];
+            if just_pressed & !expected.0 == 0 {
+                // Only keys of the current step were pressed, advance if
+                // all of them are now held, otherwise, wait for the rest.
+                if held.all_pressed(expected) {
+                    self.step += 1;
+                    self.frames = 0;
+                }
+                break;
+            }
+            if self.step == 0 {
+                break;
+            }
+            // Wrong key, the key may still be the first step of the sequence.
+            *self = Self::START;
+        }
+        let completed = self.step == sequence.steps.len();
+        if completed {
+            *self = Self::START;
+        }
+        completed
+    }
+}
+
+/// Detects key [`Sequence`]s, reporting completed sequences as `E` events.
+///
+/// See [module-level doc](self) for details.
+pub struct Matcher<E, const N: usize> {
+    sequences: [(E, Sequence); N],
+    progress: [Progress; N],
+}
+impl<E: Copy, const N: usize> Matcher<E, N> {
+    /// Detect `sequences`, reporting the associated `E` when completed.
+    #[must_use]
+    pub const fn new(sequences: [(E, Sequence); N]) -> Self {
+        Self { sequences, progress: [Progress::START; N] }
+    }
+    /// Update with this frame's `input`, returns the event of the sequence
+    /// completed this frame, if any.
+    ///
+    /// If several sequences complete on the same frame, the first one
+    /// in the list passed to [`Matcher::new`] is returned.
+    pub fn update(&mut self, input: Input) -> Option<E> {
+        let just_pressed = input.just_pressed_keys().0;
+        let mut completed = None;
+        for ((event, sequence), progress) in self.sequences.iter().zip(&mut self.progress) {
+            let is_complete = progress.update(sequence, just_pressed, input.current);
+            if is_complete && completed.is_none() {
+                completed = Some(*event);
+            }
+        }
+        completed
+    }
+    /// Restart all sequences from the beginning.
+    pub fn reset(&mut self) {
+        self.progress = [Progress::START; N];
+    }
+}
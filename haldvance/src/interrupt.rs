@@ -0,0 +1,217 @@
+//! Hardware interrupts.
+//!
+//! The GBA can interrupt the CPU on a variety of events, see [`Source`].
+//! [`crate::exec::full_game`] installs a master interrupt handler that
+//! acknowledges interrupts and dispatches them to the handler registered
+//! with [`set_handler`] for each [`Source`].
+//!
+//! To receive an interrupt, you need to both:
+//! - Register a handler with [`set_handler`]
+//! - Enable the interrupt with [`enable`]
+//!
+//! Note that some interrupt sources also need to be enabled in the
+//! peripheral that emits them:
+//! - [`Source::VBlank`], [`Source::HBlank`] and [`Source::VCount`] are
+//!   enabled in `DISPSTAT` by [`enable`], nothing else to do.
+//! - Timer, DMA and keypad interrupts must also be enabled in the control
+//!   register of the respective timer, DMA channel or `KEYCNT`.
+//!
+//! # Handlers
+//!
+//! Handlers run in IRQ mode, with interrupts disabled, on a **very small**
+//! stack (a bit less than 160 bytes). They should be kept as short as
+//! possible: set a flag, write a register, and return.
+//!
+//! [`Source::VBlank`] is required by [`crate::exec::full_game`] to wait for
+//! the next frame, so it is always enabled while the game runs. You may still
+//! set a handler for it.
+use core::cell::UnsafeCell;
+
+use volmatrix::rw::VolAddress;
+
+// SAFETY: all of the following are valid GBA memory-mapped registers
+// of the given size.
+const IE: VolAddress<u16> = unsafe { VolAddress::new(0x0400_0200) };
+const IF: VolAddress<u16> = unsafe { VolAddress::new(0x0400_0202) };
+const IME: VolAddress<u16> = unsafe { VolAddress::new(0x0400_0208) };
+const DISPSTAT: VolAddress<u16> = unsafe { VolAddress::new(0x0400_0004) };
+/// Flags the BIOS checks in `IntrWait` and `VBlankIntrWait`, the handler must
+/// set the bits of the interrupts it handled, otherwise the BIOS
+/// never returns.
+const BIOS_IF: VolAddress<u16> = unsafe { VolAddress::new(0x0300_7FF8) };
+/// The address the BIOS jumps to (in ARM mode) when an interrupt occurs.
+const USER_IRQ_HANDLER: VolAddress<Option<unsafe extern "C" fn()>> =
+    unsafe { VolAddress::new(0x0300_7FFC) };
+
+const DISPSTAT_VBLANK_IRQ: u16 = 1 << 3;
+const DISPSTAT_HBLANK_IRQ: u16 = 1 << 4;
+const DISPSTAT_VCOUNT_IRQ: u16 = 1 << 5;
+const SOURCE_COUNT: usize = 14;
+
+/// An interrupt source.
+///
+/// See [module-level doc](self) for how to use interrupts.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+#[repr(u16)]
+pub enum Source {
+    /// Vertical blank starts (once per frame).
+    VBlank = 0,
+    /// Horizontal blank starts (once per line, including lines in vblank).
+    HBlank = 1,
+    /// Drawing reaches the line set with [`set_vcount_trigger`].
+    VCount = 2,
+    Timer0 = 3,
+    Timer1 = 4,
+    Timer2 = 5,
+    Timer3 = 6,
+    Serial = 7,
+    Dma0 = 8,
+    Dma1 = 9,
+    Dma2 = 10,
+    Dma3 = 11,
+    Keypad = 12,
+    /// The game pak was removed.
+    GamePak = 13,
+}
+impl Source {
+    pub(crate) const fn mask(self) -> u16 {
+        1 << self as u16
+    }
+    /// The bit in `DISPSTAT` that enables this interrupt, if any.
+    const fn dispstat_mask(self) -> u16 {
+        match self {
+            Self::VBlank => DISPSTAT_VBLANK_IRQ,
+            Self::HBlank => DISPSTAT_HBLANK_IRQ,
+            Self::VCount => DISPSTAT_VCOUNT_IRQ,
+            _ => 0,
+        }
+    }
+}
+
+/// A function called when an interrupt happens.
+///
+/// See the [module-level doc](self) for limitations.
+pub type Handler = fn();
+
+struct Handlers(UnsafeCell<[Option<Handler>; SOURCE_COUNT]>);
+// SAFETY: the GBA has a single core, and accesses from outside of the
+// interrupt handler are guarded by `free`.
+unsafe impl Sync for Handlers {}
+
+static HANDLERS: Handlers = Handlers(UnsafeCell::new([None; SOURCE_COUNT]));
+
+/// Run `f` with interrupts disabled.
+///
+/// Use this to access state shared with an interrupt [`Handler`].
+pub fn free<R>(f: impl FnOnce() -> R) -> R {
+    let previous = IME.read();
+    IME.write(0);
+    let result = f();
+    IME.write(previous);
+    result
+}
+
+/// Set the handler for `source`, returning the previous one.
+///
+/// `None` removes the handler, the interrupt is still acknowledged if enabled.
+/// This doesn't enable the interrupt, see [`enable`].
+pub fn set_handler(source: Source, handler: Option<Handler>) -> Option<Handler> {
+    free(|| {
+        // SAFETY: interrupts are disabled, so `master_handler` can't
+        // access HANDLERS concurrently.
+        let handlers = unsafe { &mut *HANDLERS.0.get() };
+        core::mem::replace(&mut handlers[source as usize], handler)
+    })
+}
+
+/// Enable interrupts from `source`.
+///
+/// See [module-level doc](self) for sources that require additional setup.
+pub fn enable(source: Source) {
+    free(|| {
+        let dispstat = source.dispstat_mask();
+        if dispstat != 0 {
+            DISPSTAT.write(DISPSTAT.read() | dispstat);
+        }
+        IE.write(IE.read() | source.mask());
+    });
+}
+
+/// Disable interrupts from `source`.
+///
+/// Disabling [`Source::VBlank`] does nothing, since it is required by
+/// [`crate::exec::full_game`].
+pub fn disable(source: Source) {
+    if source == Source::VBlank {
+        crate::warn!("Attempted to disable the VBlank interrupt, ignoring");
+        return;
+    }
+    free(|| {
+        let dispstat = source.dispstat_mask();
+        if dispstat != 0 {
+            DISPSTAT.write(DISPSTAT.read() & !dispstat);
+        }
+        IE.write(IE.read() & !source.mask());
+    });
+}
+
+/// Enable exactly the interrupts in `mask`, returns the previously enabled ones.
+///
+/// Unlike [`enable`], this doesn't change `DISPSTAT`.
+pub(crate) fn replace_enabled(mask: u16) -> u16 {
+    free(|| {
+        let previous = IE.read();
+        IE.write(mask);
+        previous
+    })
+}
+
+/// Set the line at which the [`Source::VCount`] interrupt triggers.
+///
+/// Lines `0..160` are visible, `160..228` are in vblank.
+pub fn set_vcount_trigger(line: u8) {
+    free(|| {
+        let dispstat = DISPSTAT.read() & 0x00FF;
+        DISPSTAT.write(dispstat | u16::from(line) << 8);
+    });
+}
+
+/// The handler called by the BIOS, it dispatches to the registered handlers.
+///
+/// This runs in ARM mode, as required by the BIOS, and from IWRAM for speed.
+/// With the `host` feature, it is called by [`crate::host::Console::raise`].
+#[cfg_attr(not(feature = "host"), instruction_set(arm::a32))]
+#[cfg_attr(not(feature = "host"), link_section = ".iwram.master_handler")]
+pub(crate) unsafe extern "C" fn master_handler() {
+    let flags = IF.read() & IE.read();
+    // Acknowledge the interrupts, both for the hardware and the BIOS.
+    IF.write(flags);
+    BIOS_IF.write(BIOS_IF.read() | flags);
+
+    // SAFETY: interrupts are disabled while in the handler, and outside of
+    // it, HANDLERS is only written with interrupts disabled.
+    let handlers = unsafe { &*HANDLERS.0.get() };
+    let mut remaining = flags;
+    while remaining != 0 {
+        let index = remaining.trailing_zeros() as usize;
+        remaining &= remaining - 1;
+        if let Some(Some(handler)) = handlers.get(index) {
+            handler();
+        }
+    }
+}
+
+/// Install the master interrupt handler and enable interrupts,
+/// including [`Source::VBlank`].
+///
+/// # Safety
+///
+/// Must be called once, before any other function of this module is
+/// expected to have an effect.
+pub(crate) unsafe fn init() {
+    IME.write(0);
+    USER_IRQ_HANDLER.write(Some(master_handler));
+    IF.write(0xFFFF);
+    enable(Source::VBlank);
+    IME.write(1);
+}
@@ -4,7 +4,10 @@
 //! see [`video`].
 //!
 //! Use the [`exec::full_game`] function to define a game.
-#![no_std]
+//!
+//! With the `host` feature, the HAL can run on x86_64 Linux for testing,
+//! see [`host`].
+#![cfg_attr(not(feature = "host"), no_std)]
 #![warn(clippy::pedantic, clippy::nursery)]
 #![allow(clippy::redundant_pub_crate)]
 #![feature(const_mut_refs, const_type_id)]
@@ -12,12 +15,21 @@
 mod planckrand;
 mod unique_id;
 
+pub mod audio;
 pub mod exec;
+#[cfg(feature = "host")]
+pub mod host;
 pub mod input;
+pub mod interrupt;
 pub mod log;
+pub mod power;
+pub mod profile;
 pub mod sane_assert;
+pub mod save;
+pub mod timer;
 pub mod video;
 
 pub use gba::bios;
 pub use input::Input;
 pub use unique_id::UniqueId;
+pub use volmatrix::dma;
@@ -3,33 +3,402 @@
 //! This is mostly taken from the [`gba::debugging`] macros,
 //! with the addition of the feature flag, the predicate and
 //! the file and line number.
+//!
+//! Log lines are written to every [`Sink`] enabled with cargo features:
+//! - `log-mgba`: the mGBA emulator debug log, see [`Mgba`].
+//! - `log-nocash`: the no$gba emulator debug message window, see [`NoCash`].
+//! - `log-ring`: a RAM [`Ring`] buffer of the last [`RING_LINES`] lines,
+//!   read with [`for_each_recent`], for example by the crash screen of
+//!   [`crate::exec::panic_handler`].
+//!
+//! Without any of those features, logging macros compile to nothing.
+//!
+//! # Filtering
+//!
+//! Log calls more verbose than [`STATIC_MAX_LEVEL`] are compiled out
+//! entirely, format strings included. It is set with one of the
+//! `max-level-fatal`, `max-level-error`, `max-level-warn` and `max-level-info`
+//! features, the most restrictive one wins. By default, all levels are kept.
+//!
+//! The remaining calls are filtered at runtime by module, with
+//! [`set_max_level`] and [`set_module_level`]:
+//!
+//! ```ignore
+//! // Only log warnings and errors from the game, and its submodules.
+//! hal::log::set_module_level("gssa_rust::game", Some(Level::Warning));
+//! ```
+use core::fmt;
+
+use arrayvec::{ArrayString, ArrayVec};
+use volmatrix::rw::VolAddress;
+
+use shared::Shared;
 
 // Used in the various macros defined here.
 #[doc(hidden)]
 pub use gba;
 
-/// Log something to mGBA emulator log.
+/// Whether any log backend is enabled, if not, log macros do nothing.
+pub const ENABLED: bool = cfg!(any(
+    feature = "log-mgba",
+    feature = "log-nocash",
+    feature = "log-ring"
+));
+
+/// The most verbose level logged, see [module-level doc](self#filtering).
+///
+/// `None` when logging is disabled.
+pub const STATIC_MAX_LEVEL: Option<Level> = if !ENABLED {
+    None
+} else if cfg!(feature = "max-level-fatal") {
+    Some(Level::Fatal)
+} else if cfg!(feature = "max-level-error") {
+    Some(Level::Error)
+} else if cfg!(feature = "max-level-warn") {
+    Some(Level::Warning)
+} else if cfg!(feature = "max-level-info") {
+    Some(Level::Info)
+} else {
+    Some(Level::Debug)
+};
+
+/// How many modules can have their own level, see [`set_module_level`].
+pub const MODULE_FILTERS: usize = 8;
+/// How many of the last logged lines the `log-ring` feature keeps.
+pub const RING_LINES: usize = 4;
+/// Lines kept by the `log-ring` feature longer than this are truncated.
+pub const RING_LINE_LEN: usize = 60;
+
+// SAFETY: the no$gba "Char Out" debug register, ignored by other emulators
+// and hardware.
+const NOCASH_CHAR_OUT: VolAddress<u8> = unsafe { VolAddress::new(0x04FF_FA1C) };
+
+/// How important a log line is.
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Debug)]
+pub enum Level {
+    Fatal,
+    Error,
+    Warning,
+    Info,
+    Debug,
+}
+impl Level {
+    /// Whether `self` is logged when the most verbose level is `max`,
+    /// `None` logging nothing.
+    const fn within(self, max: Option<Self>) -> bool {
+        match max {
+            Some(max) => self as u8 <= max as u8,
+            None => false,
+        }
+    }
+    const fn name(self) -> &'static str {
+        match self {
+            Self::Fatal => "FATAL",
+            Self::Error => "ERROR",
+            Self::Warning => "WARN",
+            Self::Info => "INFO",
+            Self::Debug => "DEBUG",
+        }
+    }
+}
+
+/// A log backend, see [module-level doc](self).
+pub trait Sink {
+    /// Write a log line, `location` is the `[file:line] ` of the log call.
+    fn write(&mut self, level: Level, location: &str, message: fmt::Arguments);
+}
+
+/// The mGBA emulator debug log.
+///
+/// Levels are filtered by mGBA, configurable with the `--log-level N<128`
+/// mgba command line option.
+pub struct Mgba;
+impl Sink for Mgba {
+    fn write(&mut self, level: Level, location: &str, message: fmt::Arguments) {
+        use fmt::Write;
+        use gba::debugging::mgba::{MGBADebug, MGBADebugLevel};
+
+        if let Some(mut out) = MGBADebug::new() {
+            let _ = out.write_str(location);
+            let _ = out.write_fmt(message);
+            out.send(match level {
+                Level::Fatal => MGBADebugLevel::Fatal,
+                Level::Error => MGBADebugLevel::Error,
+                Level::Warning => MGBADebugLevel::Warning,
+                Level::Info => MGBADebugLevel::Info,
+                Level::Debug => MGBADebugLevel::Debug,
+            });
+        }
+    }
+}
+
+/// The no$gba emulator debug message window.
+///
+/// Lines are prefixed with their level, since no$gba doesn't have levels.
+pub struct NoCash;
+impl fmt::Write for NoCash {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        s.bytes().for_each(|byte| NOCASH_CHAR_OUT.write(byte));
+        Ok(())
+    }
+}
+impl Sink for NoCash {
+    fn write(&mut self, level: Level, location: &str, message: fmt::Arguments) {
+        use fmt::Write;
+
+        let _ = writeln!(self, "[{}] {location}{message}", level.name());
+    }
+}
+
+/// A ring buffer of the last `N` logged lines, each truncated to `LEN` bytes.
+pub struct Ring<const N: usize, const LEN: usize> {
+    lines: [ArrayString<LEN>; N],
+    /// The index in `lines` of the next line to write.
+    next: usize,
+}
+impl<const N: usize, const LEN: usize> Ring<N, LEN> {
+    #[must_use]
+    pub const fn new() -> Self {
+        Self {
+            lines: [ArrayString::new_const(); N],
+            next: 0,
+        }
+    }
+    /// The lines, from oldest to newest.
+    pub fn iter(&self) -> impl Iterator<Item = &str> {
+        let (newest, oldest) = self.lines.split_at(self.next);
+        oldest
+            .iter()
+            .chain(newest)
+            .map(ArrayString::as_str)
+            .filter(|line| !line.is_empty())
+    }
+    pub fn clear(&mut self) {
+        self.lines.iter_mut().for_each(ArrayString::clear);
+        self.next = 0;
+    }
+}
+impl<const N: usize, const LEN: usize> Default for Ring<N, LEN> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+impl<const N: usize, const LEN: usize> fmt::Write for Ring<N, LEN> {
+    /// Append to the last line, truncating it if too long.
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        let last = (self.next + N - 1) % N;
+        for c in s.chars() {
+            if self.lines[last].try_push(c).is_err() {
+                break;
+            }
+        }
+        Ok(())
+    }
+}
+impl<const N: usize, const LEN: usize> Sink for Ring<N, LEN> {
+    fn write(&mut self, _: Level, location: &str, message: fmt::Arguments) {
+        use fmt::Write;
+
+        if N == 0 {
+            return;
+        }
+        self.lines[self.next].clear();
+        self.next = (self.next + 1) % N;
+        let _ = self.write_str(location);
+        let _ = self.write_fmt(message);
+    }
+}
+
+/// Runtime log levels, see [module-level doc](self#filtering).
+struct Filter {
+    max_level: Option<Level>,
+    modules: ArrayVec<(&'static str, Option<Level>), MODULE_FILTERS>,
+}
+impl Filter {
+    const fn new() -> Self {
+        Self {
+            max_level: Some(Level::Debug),
+            modules: ArrayVec::new_const(),
+        }
+    }
+    /// Set the level of `module`, returns `false` if there are already
+    /// [`MODULE_FILTERS`] other modules with their own level.
+    fn set_module_level(&mut self, module: &'static str, level: Option<Level>) -> bool {
+        match self.modules.iter_mut().find(|(name, _)| *name == module) {
+            Some(entry) => {
+                entry.1 = level;
+                true
+            }
+            None => self.modules.try_push((module, level)).is_ok(),
+        }
+    }
+    /// The most verbose level logged in `module`, the one of the closest
+    /// parent module with its own level, or `max_level`.
+    fn level(&self, module: &str) -> Option<Level> {
+        let is_within = |parent: &str| match module.strip_prefix(parent) {
+            Some(rest) => rest.is_empty() || rest.starts_with("::"),
+            None => false,
+        };
+        self.modules
+            .iter()
+            .filter(|(parent, _)| is_within(parent))
+            .max_by_key(|(parent, _)| parent.len())
+            .map_or(self.max_level, |(_, level)| *level)
+    }
+}
+
+#[cfg(not(feature = "host"))]
+mod shared {
+    use core::{
+        cell::UnsafeCell,
+        sync::atomic::{AtomicBool, Ordering},
+    };
+
+    /// A global value, only accessed with interrupts disabled.
+    pub(super) struct Shared<T> {
+        value: UnsafeCell<T>,
+        /// Whether `with` is running, it stays set if `f` panicked.
+        busy: AtomicBool,
+    }
+    // SAFETY: the GBA has a single core, and the value is only accessed
+    // with interrupts disabled.
+    unsafe impl<T: Send> Sync for Shared<T> {}
+
+    impl<T> Shared<T> {
+        pub(super) const fn new(value: T) -> Self {
+            Self {
+                value: UnsafeCell::new(value),
+                busy: AtomicBool::new(false),
+            }
+        }
+        /// Run `f` on the value, `f` must not access `self`.
+        ///
+        /// Returns `None` without running `f` if the value is already in
+        /// use, which only happens when the panic handler logs after a panic
+        /// in `f`.
+        pub(super) fn with<R>(&self, f: impl FnOnce(&mut T) -> R) -> Option<R> {
+            crate::interrupt::free(|| {
+                if self.busy.swap(true, Ordering::Relaxed) {
+                    return None;
+                }
+                // SAFETY: interrupts are disabled, and `busy` was not set,
+                // so this is the only reference to the value.
+                let result = f(unsafe { &mut *self.value.get() });
+                self.busy.store(false, Ordering::Relaxed);
+                Some(result)
+            })
+        }
+    }
+}
+#[cfg(feature = "host")]
+mod shared {
+    use std::sync::{Mutex, PoisonError};
+
+    /// A global value, behind a lock.
+    pub(super) struct Shared<T>(Mutex<T>);
+
+    impl<T> Shared<T> {
+        pub(super) const fn new(value: T) -> Self {
+            Self(Mutex::new(value))
+        }
+        /// Run `f` on the value, `f` must not access `self`.
+        ///
+        /// Always returns `Some`, panics unwind on the host, releasing the lock.
+        // allow: same signature as on the GBA.
+        #[allow(clippy::unnecessary_wraps)]
+        pub(super) fn with<R>(&self, f: impl FnOnce(&mut T) -> R) -> Option<R> {
+            Some(f(&mut self
+                .0
+                .lock()
+                .unwrap_or_else(PoisonError::into_inner)))
+        }
+    }
+}
+
+/// The ring buffer of the `log-ring` feature.
+static RING: Shared<Ring<RING_LINES, RING_LINE_LEN>> = Shared::new(Ring::new());
+static FILTER: Shared<Filter> = Shared::new(Filter::new());
+
+/// Set the most verbose level logged in modules without their own level.
+///
+/// `None` disables logging, this is [`Level::Debug`] by default.
+/// Levels more verbose than [`STATIC_MAX_LEVEL`] are never logged.
+pub fn set_max_level(level: Option<Level>) {
+    FILTER.with(|filter| filter.max_level = level);
+}
+
+/// Set the most verbose level logged in `module` and its submodules.
+///
+/// `module` is a path as returned by [`module_path!`], such as
+/// `"gssa_rust::game"`. When several parent modules of a log call have
+/// their own level, the closest one is used.
+///
+/// At most [`MODULE_FILTERS`] modules can have their own level, further
+/// modules are ignored.
+pub fn set_module_level(module: &'static str, level: Option<Level>) {
+    let is_set = FILTER.with(|filter| filter.set_module_level(module, level)) != Some(false);
+    crate::warn!((!is_set) "Too many module log levels, ignoring {module}");
+}
+
+/// Remove the levels set with [`set_module_level`].
+pub fn clear_module_levels() {
+    FILTER.with(|filter| filter.modules.clear());
+}
+
+/// Whether `level` is within [`STATIC_MAX_LEVEL`], used in [`crate::emlog!`].
+#[doc(hidden)]
+#[must_use]
+pub const fn static_enabled(level: Level) -> bool {
+    level.within(STATIC_MAX_LEVEL)
+}
+
+/// Whether `level` is logged in `module`, used in [`crate::emlog!`].
+#[doc(hidden)]
+#[must_use]
+pub fn enabled(level: Level, module: &str) -> bool {
+    // Not logging if the panic handler logs a panic that happened while logging.
+    FILTER.with(|filter| level.within(filter.level(module))) == Some(true)
+}
+
+/// Call `f` on the last logged lines, from oldest to newest.
+///
+/// Lines are only kept with the `log-ring` feature.
+pub fn for_each_recent(f: impl FnMut(&str)) {
+    RING.with(|ring| ring.iter().for_each(f));
+}
+
+/// Write a log line to all enabled sinks, used in [`crate::emlog!`].
+#[doc(hidden)]
+pub fn write(level: Level, location: &str, message: fmt::Arguments) {
+    #[cfg(feature = "log-mgba")]
+    Mgba.write(level, location, message);
+    #[cfg(feature = "log-nocash")]
+    NoCash.write(level, location, message);
+    #[cfg(feature = "log-ring")]
+    RING.with(|ring| ring.write(level, location, message));
+    let _ = (level, location, message);
+}
+
+/// Log something to the enabled log [`crate::log::Sink`]s.
 #[doc = include_str!("log_doc_start.md")]
 /// ```
 /// emlog!($loglevel: ident, [($predicate: expr)]? $format_string [, $format_args]*)
 /// ```
 /// Where:
-/// - `$loglevel`: one of `Fatal`, `Error`, `Warning`, `Info` or `Debug`;
-///   the log level, configurable with the `--log-level N<128` mgba
-///   command line option
+/// - `$loglevel`: one of the [`crate::log::Level`] variants, the log level
 #[doc = include_str!("log_doc_arguments.md")]
 #[macro_export]
 macro_rules! emlog {
     ($loglevel:ident, ($cond:expr) $fmt:literal, $($fmt_args:tt)*) => {
-        #[cfg(feature = "log")]
-        if $cond {
-            use $crate::log::gba::debugging::mgba;
-            use core::fmt::Write;
-            if let Some(mut out) = mgba::MGBADebug::new() {
-                let _ = out.write_str(concat!("[", file!(), ":", line!(), "] "));
-                let _ = write!(&mut out, $fmt , $($fmt_args)* );
-                out.send(mgba::MGBADebugLevel::$loglevel);
-            }
+        if $crate::log::static_enabled($crate::log::Level::$loglevel)
+            && $cond
+            && $crate::log::enabled($crate::log::Level::$loglevel, module_path!())
+        {
+            $crate::log::write(
+                $crate::log::Level::$loglevel,
+                concat!("[", file!(), ":", line!(), "] "),
+                format_args!($fmt, $($fmt_args)*),
+            );
         }
     };
     ($loglevel:ident, ($cond:expr) $fmt:literal) => {
@@ -43,7 +412,7 @@ macro_rules! emlog {
     };
 }
 
-/// Log a fatal error to mGBA emulator log.
+/// Log a fatal error.
 #[doc = include_str!("log_doc_start.md")]
 /// ```
 /// fatal!([($predicate: expr)]? $format_string [, $format_args]*)
@@ -53,7 +422,7 @@ macro_rules! emlog {
 #[macro_export]
 macro_rules! fatal { ($($anything:tt)*) => { $crate::emlog!(Fatal, $($anything)*) } }
 
-/// Log an error to mGBA emulator log.
+/// Log an error.
 #[doc = include_str!("log_doc_start.md")]
 /// ```
 /// error!([($predicate: expr)]? $format_string [, $format_args]*)
@@ -63,7 +432,7 @@ macro_rules! fatal { ($($anything:tt)*) => { $crate::emlog!(Fatal, $($anything)*
 #[macro_export]
 macro_rules! error { ($($anything:tt)*) => { $crate::emlog!(Error, $($anything)*) } }
 
-/// Log a warning to mGBA emulator log.
+/// Log a warning.
 #[doc = include_str!("log_doc_start.md")]
 /// ```
 /// warn!([($predicate: expr)]? $format_string [, $format_args]*)
@@ -73,7 +442,7 @@ macro_rules! error { ($($anything:tt)*) => { $crate::emlog!(Error, $($anything)*
 #[macro_export]
 macro_rules! warn { ($($anything:tt)*) => { $crate::emlog!(Warning, $($anything)*) } }
 
-/// Log an info to mGBA emulator log.
+/// Log an info.
 #[doc = include_str!("log_doc_start.md")]
 /// ```
 /// info!([($predicate: expr)]? $format_string [, $format_args]*)
@@ -83,7 +452,7 @@ macro_rules! warn { ($($anything:tt)*) => { $crate::emlog!(Warning, $($anything)
 #[macro_export]
 macro_rules! info { ($($anything:tt)*) => { $crate::emlog!(Info, $($anything)*) } }
 
-/// Log a debug message to mGBA emulator log.
+/// Log a debug message.
 #[doc = include_str!("log_doc_start.md")]
 /// ```
 /// debug!([($predicate: expr)]? $format_string [, $format_args]*)
@@ -92,3 +461,34 @@ macro_rules! info { ($($anything:tt)*) => { $crate::emlog!(Info, $($anything)*)
 #[doc = include_str!("log_doc_arguments.md")]
 #[macro_export]
 macro_rules! debug { ($($anything:tt)*) => { $crate::emlog!(Debug, $($anything)*) } }
+
+#[cfg(all(test, feature = "host"))]
+mod test {
+    use super::{Filter, Level, Ring, Sink};
+
+    #[test]
+    fn closest_module_level() {
+        let mut filter = Filter::new();
+        filter.max_level = Some(Level::Info);
+        assert!(filter.set_module_level("game", Some(Level::Warning)));
+        assert!(filter.set_module_level("game::space", None));
+        assert_eq!(filter.level("game"), Some(Level::Warning));
+        assert_eq!(filter.level("game::ship"), Some(Level::Warning));
+        assert_eq!(filter.level("game::space::bullet"), None);
+        assert_eq!(filter.level("game_over"), Some(Level::Info));
+        assert!(Level::Error.within(filter.level("game")));
+        assert!(!Level::Info.within(filter.level("game")));
+    }
+
+    #[test]
+    fn ring_keeps_last_lines() {
+        let mut ring = Ring::<2, 8>::new();
+        ring.write(Level::Info, "[a] ", format_args!("{}", 1));
+        assert!(ring.iter().eq(["[a] 1"]));
+        ring.write(Level::Info, "[b] ", format_args!("too long"));
+        ring.write(Level::Error, "[c] ", format_args!("{}", 3));
+        assert!(ring.iter().eq(["[b] too ", "[c] 3"]));
+        ring.clear();
+        assert_eq!(ring.iter().count(), 0);
+    }
+}
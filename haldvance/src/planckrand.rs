@@ -1,6 +1,12 @@
 //! Tinny tinny tinny random module using the wyhash algorithm.
 //!
 //! Taken from <https://github.com/eldruin/wyhash-rs>
+//!
+//! [`Streams`] derives several independent [`Rng`]s from a single seed, so
+//! that drawing numbers for visual effects doesn't change the outcome of
+//! gameplay, which would break input replays.
+
+use core::ops::Range;
 
 use const_default::ConstDefault;
 
@@ -18,20 +24,42 @@ const fn random(a: u64, b: u64) -> u64 {
 }
 
 /// A random seed generator.
-/// Use [`Rng::u64`] or [`Rng::random_bits`] to get a random numbers.
+/// Use [`Rng::u64`], [`Rng::below`] or [`Rng::bits`] to get a random numbers.
+///
+/// Clone it to save its state, or see [`Rng::encode`] to store it.
+#[derive(Clone, PartialEq, Eq, Debug)]
 pub struct Rng {
     seed: u64,
+    /// Random bits not used yet by [`Rng::bits`], lowest first.
+    pool: u64,
+    /// How many bits are left in `pool`.
+    pool_len: u32,
 }
 impl ConstDefault for Rng {
     const DEFAULT: Self = Self::new(P0);
 }
-// TODO: implement a "almost divisionless" mean to translate to a smaller
-// random value, or "really divisionless" one as in
-// https://dotat.at/@/2022-04-20-really-divisionless.html
 impl Rng {
+    /// Size in bytes of the state written by [`Self::encode`].
+    pub const ENCODED_LEN: usize = 17;
+
     #[must_use]
     pub const fn new(seed: u64) -> Self {
-        Self { seed }
+        Self { seed, pool: 0, pool_len: 0 }
+    }
+    /// The stream `name` of `seed`, independent from other streams of `seed`.
+    ///
+    /// See [`Streams`] for typical use.
+    #[must_use]
+    pub const fn stream(seed: u64, name: &str) -> Self {
+        // FNV-1a hash of `name`.
+        let name = name.as_bytes();
+        let mut hash: u64 = 0xcbf2_9ce4_8422_2325;
+        let mut i = 0;
+        while i < name.len() {
+            hash = (hash ^ name[i] as u64).wrapping_mul(0x0100_0000_01b3);
+            i += 1;
+        }
+        Self::new(random(seed ^ P0, hash ^ P1))
     }
     /// A random `u64`, advances the rng.
     #[must_use]
@@ -39,6 +67,118 @@ impl Rng {
         self.seed = self.seed.wrapping_add(P0);
         random(self.seed, self.seed ^ P1)
     }
+    /// A random `u32`.
+    #[must_use]
+    pub fn u32(&mut self) -> u32 {
+        self.bits(u32::BITS)
+    }
+    /// A `u32` of which the `count` lowest bits are randomly set.
+    ///
+    /// Unlike [`Rng::random_bits`], bits left over from the last call are
+    /// used before advancing the rng, so no random bits are wasted.
+    ///
+    /// # Panics
+    /// If `count > 32`
+    #[must_use]
+    pub fn bits(&mut self, count: u32) -> u32 {
+        assert!(
+            count <= u32::BITS,
+            "Can't get more than 32 random bits at once"
+        );
+        let mask = (1 << count) - 1;
+        let value = if count <= self.pool_len {
+            let value = self.pool & mask;
+            self.pool >>= count;
+            self.pool_len -= count;
+            value
+        } else {
+            let missing = count - self.pool_len;
+            let fresh = self.u64();
+            let value = (self.pool | (fresh << self.pool_len)) & mask;
+            self.pool = fresh >> missing;
+            self.pool_len = u64::BITS - missing;
+            value
+        };
+        // unwrap: `mask` has at most 32 bits set.
+        value.try_into().unwrap()
+    }
+    /// A random 0.`count` fixed point number in `[0, 1)`.
+    ///
+    /// For example, `rng.fraction(8)` is a 0.8 fixed point number, in `0..256`.
+    ///
+    /// # Panics
+    /// If `count > 32`
+    #[must_use]
+    pub fn fraction(&mut self, count: u32) -> u32 {
+        self.bits(count)
+    }
+    /// A random `u32` in `0..bound`, without bias.
+    ///
+    /// This is [Lemire's "nearly divisionless" method][lemire], the GBA has
+    /// no hardware division, and a division only happens with a probability
+    /// of `bound / 2³²`. Powers of two only use as many bits as needed, see
+    /// [`Rng::bits`].
+    ///
+    /// [lemire]: https://lemire.me/blog/2019/06/06/nearly-divisionless-random-integer-generation-on-various-systems/
+    ///
+    /// # Panics
+    /// If `bound` is 0.
+    #[must_use]
+    // allow: we explicitly want the low and high 32 bits of the product.
+    #[allow(clippy::cast_possible_truncation)]
+    pub fn below(&mut self, bound: u32) -> u32 {
+        assert!(bound != 0, "Can't get a random number below 0");
+        if bound.is_power_of_two() {
+            return self.bits(bound.trailing_zeros());
+        }
+        let mut product = u64::from(self.u32()) * u64::from(bound);
+        if (product as u32) < bound {
+            // 2³² % bound, the values that would bias the result.
+            let threshold = bound.wrapping_neg() % bound;
+            while (product as u32) < threshold {
+                product = u64::from(self.u32()) * u64::from(bound);
+            }
+        }
+        (product >> 32) as u32
+    }
+    /// A random `i32` in `range`, without bias, see [`Rng::below`].
+    ///
+    /// # Panics
+    /// If `range` is empty.
+    #[must_use]
+    // allow: wrapping around is how we compute the range size and offset.
+    #[allow(clippy::cast_sign_loss, clippy::cast_possible_wrap)]
+    pub fn range(&mut self, range: Range<i32>) -> i32 {
+        assert!(
+            !range.is_empty(),
+            "Can't get a random number in an empty range"
+        );
+        let size = range.end.wrapping_sub(range.start) as u32;
+        range.start.wrapping_add(self.below(size) as i32)
+    }
+    /// `true` with a probability of `numerator / denominator`.
+    ///
+    /// # Panics
+    /// If `denominator` is 0.
+    #[must_use]
+    pub fn chance(&mut self, numerator: u32, denominator: u32) -> bool {
+        self.below(denominator) < numerator
+    }
+    /// A random item of `table`, picked according to its weight.
+    #[must_use]
+    pub fn choose<'a, T, const N: usize>(&mut self, table: &'a Weighted<T, N>) -> &'a T {
+        let target = self.below(table.total());
+        let index = table.cumulative.partition_point(|&sum| sum <= target);
+        &table.items[index].0
+    }
+    /// Shuffle `slice` in place, all permutations being equally likely.
+    pub fn shuffle<T>(&mut self, slice: &mut [T]) {
+        for i in (1..slice.len()).rev() {
+            // unwrap: the GBA doesn't have enough memory for longer slices.
+            let j = self.below((i + 1).try_into().unwrap());
+            slice.swap(i, j as usize);
+        }
+    }
     /// An infinite iterator, each item an `u32` of which `bit_count` bits
     /// are randomly set.
     ///
@@ -53,7 +193,161 @@ impl Rng {
         }
     }
     pub fn reseed(&mut self, seed: u64) {
-        self.seed = seed;
+        *self = Self::new(seed);
+    }
+    /// Write the state of this rng to `bytes`, for example to save memory.
+    ///
+    /// Returns the count of bytes written, [`Self::ENCODED_LEN`], or `None`
+    /// if `bytes` is too small.
+    pub fn encode(&self, bytes: &mut [u8]) -> Option<usize> {
+        let bytes = bytes.get_mut(..Self::ENCODED_LEN)?;
+        bytes[..8].copy_from_slice(&self.seed.to_le_bytes());
+        bytes[8..16].copy_from_slice(&self.pool.to_le_bytes());
+        // unwrap: `pool_len` is at most 64.
+        bytes[16] = self.pool_len.try_into().unwrap();
+        Some(Self::ENCODED_LEN)
+    }
+    /// Restore a state written with [`Self::encode`].
+    ///
+    /// Returns `false` and keeps the current state if `bytes` doesn't
+    /// contain a valid state.
+    pub fn decode(&mut self, bytes: &[u8]) -> bool {
+        let bytes = match bytes.get(..Self::ENCODED_LEN) {
+            Some(bytes) => bytes,
+            None => return false,
+        };
+        let (seed, rest) = bytes.split_at(8);
+        let (pool, pool_len) = rest.split_at(8);
+        // unwrap: split at 8 bytes above.
+        let seed = u64::from_le_bytes(seed.try_into().unwrap());
+        let pool = u64::from_le_bytes(pool.try_into().unwrap());
+        let pool_len = u32::from(pool_len[0]);
+        // Bits above `pool_len` must be cleared, see `Self::bits`.
+        let is_valid = match pool.checked_shr(pool_len) {
+            Some(unused) => unused == 0,
+            None => pool_len == u64::BITS,
+        };
+        if is_valid {
+            *self = Self { seed, pool, pool_len };
+        }
+        is_valid
+    }
+}
+
+/// Independent [`Rng`]s derived from one seed, see [module-level doc](self).
+///
+/// Use [`Streams::stream`] for streams other than `gameplay` and `cosmetic`.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct Streams {
+    seed: u64,
+    /// For anything affecting the game state, reproduced by input replays.
+    pub gameplay: Rng,
+    /// For visual effects that don't affect the game state, such as
+    /// background generation.
+    pub cosmetic: Rng,
+}
+impl ConstDefault for Streams {
+    const DEFAULT: Self = Self::new(P0);
+}
+impl Streams {
+    /// Size in bytes of the state written by [`Self::encode`].
+    pub const ENCODED_LEN: usize = 8 + 2 * Rng::ENCODED_LEN;
+
+    #[must_use]
+    pub const fn new(seed: u64) -> Self {
+        Self {
+            seed,
+            gameplay: Rng::stream(seed, "gameplay"),
+            cosmetic: Rng::stream(seed, "cosmetic"),
+        }
+    }
+    /// The seed all streams are derived from.
+    #[must_use]
+    pub const fn seed(&self) -> u64 {
+        self.seed
+    }
+    /// The stream `name`, independent from `gameplay`, `cosmetic` and
+    /// streams with other names.
+    #[must_use]
+    pub const fn stream(&self, name: &str) -> Rng {
+        Rng::stream(self.seed, name)
+    }
+    /// Write the state of all streams to `bytes`, see [`Rng::encode`].
+    pub fn encode(&self, bytes: &mut [u8]) -> Option<usize> {
+        let bytes = bytes.get_mut(..Self::ENCODED_LEN)?;
+        let (seed, rngs) = bytes.split_at_mut(8);
+        seed.copy_from_slice(&self.seed.to_le_bytes());
+        let (gameplay, cosmetic) = rngs.split_at_mut(Rng::ENCODED_LEN);
+        self.gameplay.encode(gameplay)?;
+        self.cosmetic.encode(cosmetic)?;
+        Some(Self::ENCODED_LEN)
+    }
+    /// Restore a state written with [`Self::encode`], see [`Rng::decode`].
+    pub fn decode(&mut self, bytes: &[u8]) -> bool {
+        let bytes = match bytes.get(..Self::ENCODED_LEN) {
+            Some(bytes) => bytes,
+            None => return false,
+        };
+        let (seed, rngs) = bytes.split_at(8);
+        let (gameplay, cosmetic) = rngs.split_at(Rng::ENCODED_LEN);
+        let mut decoded = self.clone();
+        // unwrap: split at 8 bytes above.
+        decoded.seed = u64::from_le_bytes(seed.try_into().unwrap());
+        let is_valid = decoded.gameplay.decode(gameplay) && decoded.cosmetic.decode(cosmetic);
+        if is_valid {
+            *self = decoded;
+        }
+        is_valid
+    }
+}
+
+/// Mix `values` into a single seed, for [`Streams::new`].
+pub(crate) fn mix_seed(values: &[u64]) -> u64 {
+    values
+        .iter()
+        .fold(P0, |seed, value| random(seed ^ P1, *value ^ P0))
+}
+
+/// Items with weights, to pick from with [`Rng::choose`].
+///
+/// ```ignore
+/// const LOOT: Weighted<Loot, 3> =
+///     Weighted::new([(Loot::LifeUp, 1), (Loot::Coin, 8), (Loot::Nothing, 23)]);
+/// // One time out of 32, a life up.
+/// let loot = rng.choose(&LOOT);
+/// ```
+pub struct Weighted<T, const N: usize> {
+    items: [(T, u32); N],
+    /// The sum of the weights of all items up to the one at the same index.
+    cumulative: [u32; N],
+}
+impl<T, const N: usize> Weighted<T, N> {
+    /// A table of `items`, each with its weight.
+    ///
+    /// # Panics
+    /// (const time only) If all weights are 0, or their sum overflows.
+    #[must_use]
+    pub const fn new(items: [(T, u32); N]) -> Self {
+        let mut cumulative = [0; N];
+        let mut total: u32 = 0;
+        let mut i = 0;
+        while i < N {
+            total = match total.checked_add(items[i].1) {
+                Some(total) => total,
+                None => panic!("Weighted item weights sum overflows a u32"),
+            };
+            cumulative[i] = total;
+            i += 1;
+        }
+        assert!(
+            total != 0,
+            "Weighted items need at least one non-zero weight"
+        );
+        Self { items, cumulative }
+    }
+    /// The sum of all weights.
+    const fn total(&self) -> u32 {
+        self.cumulative[N - 1]
     }
 }
 /// Iterator for the [`Rng::random_bits`] return value.
@@ -84,3 +378,75 @@ impl<'a, const COUNT: u32> Iterator for RandBitsIter<'a, COUNT> {
         Some(ret.try_into().unwrap())
     }
 }
+
+#[cfg(all(test, feature = "host"))]
+mod test {
+    use super::{Rng, Streams, Weighted};
+
+    #[test]
+    fn bits_are_not_wasted() {
+        let mut bits = Rng::new(0xDEAD_BEEF);
+        let mut whole = Rng::new(0xDEAD_BEEF);
+        let first = whole.u64();
+        let second = whole.u64();
+        assert_eq!(u64::from(bits.bits(20)), first & 0xF_FFFF);
+        assert_eq!(u64::from(bits.bits(32)), (first >> 20) & 0xFFFF_FFFF);
+        // 12 bits left from `first`, the other 8 from `second`.
+        let expected = (first >> 52) | ((second & 0xFF) << 12);
+        assert_eq!(u64::from(bits.bits(20)), expected);
+    }
+
+    #[test]
+    fn fraction_below_one() {
+        let mut rng = Rng::new(0xDEAD_BEEF);
+        for count in [1, 8, 12, 31] {
+            for _ in 0..100 {
+                assert!(rng.fraction(count) < 1 << count);
+            }
+        }
+    }
+
+    #[test]
+    fn bounded_and_weighted() {
+        const TABLE: Weighted<char, 3> = Weighted::new([('a', 1), ('b', 0), ('c', 3)]);
+        let mut rng = Rng::new(0xDEAD_BEEF);
+        let mut picked = [0; 3];
+        for _ in 0..400 {
+            assert!(rng.below(10) < 10);
+            assert!((-3..5).contains(&rng.range(-3..5)));
+            match rng.choose(&TABLE) {
+                'a' => picked[0] += 1,
+                'b' => picked[1] += 1,
+                _ => picked[2] += 1,
+            }
+        }
+        assert_eq!(picked[1], 0);
+        assert!(picked[0] > 50 && picked[2] > 250, "{picked:?}");
+
+        let mut items = [1, 2, 3, 4, 5, 6, 7, 8];
+        rng.shuffle(&mut items);
+        assert_ne!(items, [1, 2, 3, 4, 5, 6, 7, 8]);
+        items.sort_unstable();
+        assert_eq!(items, [1, 2, 3, 4, 5, 6, 7, 8]);
+    }
+
+    #[test]
+    fn restore_streams() {
+        let mut streams = Streams::new(1234);
+        assert_ne!(streams.gameplay, streams.cosmetic);
+        assert_ne!(streams.stream("enemies"), streams.stream("items"));
+        let _ = streams.gameplay.bits(3);
+        let mut bytes = [0; Streams::ENCODED_LEN];
+        streams.encode(&mut bytes).unwrap();
+        let expected = (streams.gameplay.bits(20), streams.cosmetic.u64());
+
+        let mut restored = Streams::new(0);
+        assert!(restored.decode(&bytes));
+        assert_eq!(restored.seed(), 1234);
+        assert_eq!(
+            (restored.gameplay.bits(20), restored.cosmetic.u64()),
+            expected
+        );
+        assert!(!restored.decode(&bytes[..10]));
+    }
+}
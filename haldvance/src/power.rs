@@ -0,0 +1,106 @@
+//! Low-power sleep mode, see [`ConsoleState::sleep`].
+//!
+//! The GBA has no power button that games can react to, so handheld games
+//! usually offer a "sleep" option in their pause menu: the screen and sound
+//! are turned off, and the CPU is stopped until the player presses a key
+//! combination, typically L+R+Select.
+//!
+//! This uses the BIOS `Stop` function, waking up with the
+//! [`interrupt::Source::Keypad`] interrupt configured in `KEYCNT`. Video
+//! memory, palettes and objects are retained while stopped, so only the
+//! display control register needs to be restored on wake up.
+//!
+//! ```ignore
+//! // In the pause menu logic
+//! if console.input.just_pressed(Key::Select) {
+//!     console.sleep(Key::L | Key::R | Key::Select);
+//! }
+//! ```
+use volmatrix::rw::VolAddress;
+
+use crate::{
+    exec::wait_vblank,
+    input::{KeyGroup, KEYINPUT},
+    interrupt,
+};
+
+#[cfg(feature = "host")]
+use crate::host::stop;
+
+#[cfg(doc)]
+use crate::exec::ConsoleState;
+
+// SAFETY: all of the following are valid GBA memory-mapped registers
+// of the given size.
+const DISPCNT: VolAddress<u16> = unsafe { VolAddress::new(0x0400_0000) };
+const SOUNDCNT_L: VolAddress<u16> = unsafe { VolAddress::new(0x0400_0080) };
+const SOUNDCNT_H: VolAddress<u16> = unsafe { VolAddress::new(0x0400_0082) };
+const KEYCNT: VolAddress<u16> = unsafe { VolAddress::new(0x0400_0132) };
+
+const FORCED_BLANK: u16 = 1 << 7;
+const KEYCNT_IRQ: u16 = 1 << 14;
+/// Trigger the keypad interrupt when all keys are pressed, rather than any.
+const KEYCNT_ALL_KEYS: u16 = 1 << 15;
+
+#[cfg(not(feature = "host"))]
+fn stop() {
+    crate::bios::Stop();
+}
+
+/// Wait for vblank until none of `keys` are pressed.
+fn wait_release(keys: KeyGroup) {
+    while KEYINPUT.read().any_pressed(keys) {
+        wait_vblank();
+    }
+}
+
+/// Stop the console until all `wake` keys are pressed, see [module-level doc](self).
+pub(crate) fn sleep(wake: KeyGroup) {
+    assert!(!wake.is_empty(), "Can't sleep without keys to wake up");
+    // Otherwise, the keypad interrupt would wake us up right away.
+    wait_release(wake);
+    let display = DISPCNT.read();
+    let sound = (SOUNDCNT_L.read(), SOUNDCNT_H.read());
+    let keycnt = KEYCNT.read();
+
+    // Blank during vblank, so that the last frame isn't cut midway.
+    wait_vblank();
+    DISPCNT.write(display | FORCED_BLANK);
+    // Mute all channels, without disabling sound, which would reset
+    // all sound registers.
+    SOUNDCNT_L.write(0);
+    SOUNDCNT_H.write(0);
+    KEYCNT.write(wake.bits() | KEYCNT_IRQ | KEYCNT_ALL_KEYS);
+    let enabled = interrupt::replace_enabled(interrupt::Source::Keypad.mask());
+
+    stop();
+
+    interrupt::replace_enabled(enabled);
+    KEYCNT.write(keycnt);
+    SOUNDCNT_L.write(sound.0);
+    SOUNDCNT_H.write(sound.1);
+    wait_vblank();
+    DISPCNT.write(display);
+    // Otherwise, the game would see the wake keys as just pressed.
+    wait_release(wake);
+}
+
+#[cfg(all(test, feature = "host"))]
+mod test {
+    use super::{sleep, DISPCNT, KEYCNT, SOUNDCNT_L};
+    use crate::{host::Console, input::Key, interrupt};
+
+    #[test]
+    fn sleep_restores_registers() {
+        let console = Console::new();
+        DISPCNT.write(0x1F40);
+        SOUNDCNT_L.write(0x1177);
+        KEYCNT.write(0x0003);
+        interrupt::replace_enabled(0x0001);
+        sleep(Key::L | Key::R | Key::Select);
+        assert_eq!(console.io_register(0x0400_0000), 0x1F40);
+        assert_eq!(console.io_register(0x0400_0080), 0x1177);
+        assert_eq!(console.io_register(0x0400_0132), 0x0003);
+        assert_eq!(console.io_register(0x0400_0200), 0x0001);
+    }
+}
@@ -0,0 +1,203 @@
+//! CPU time profiling, see [`Profiler`].
+//!
+//! The profiler counts CPU cycles using [`timer::Id::_2`] cascaded into
+//! [`timer::Id::_3`], so it is disabled by default, enable it with
+//! [`ConsoleState::enable_profiler`].
+//!
+//! Once enabled, [`crate::exec::full_game`] records the `"logic"` and `"draw"`
+//! spans each frame, respectively the time spent in [`GameState::logic`]
+//! and the `GameState::*_draw` methods. You can record your own named spans
+//! with [`Profiler::start`] and [`Profiler::record`]:
+//!
+//! ```ignore
+//! let start = console.profiler.start();
+//! update_bullets(&mut self.bullets);
+//! console.profiler.record("bullets", start);
+//! ```
+//!
+//! Statistics are computed over windows of [`WINDOW_FRAMES`] frames. Use
+//! [`Profiler::stats`] to get the statistics of the last complete window,
+//! or [`Profiler::log`] to print them with [`crate::info!`].
+use arrayvec::ArrayVec;
+use const_default::ConstDefault;
+
+use crate::timer;
+
+#[cfg(doc)]
+use crate::exec::{ConsoleState, GameState};
+
+/// How many CPU cycles there are in a single frame.
+pub const FRAME_CYCLES: u32 = 280_896;
+/// How many frames statistics are computed over.
+pub const WINDOW_FRAMES: u32 = 64;
+/// How many different spans can be recorded.
+pub const MAX_SPANS: usize = 8;
+
+/// A point in time, see [`Profiler::start`].
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Debug)]
+pub struct Timestamp(u32);
+
+/// Statistics of a span over a window of [`WINDOW_FRAMES`] frames.
+///
+/// All durations are in CPU cycles, see [`FRAME_CYCLES`] for reference.
+#[derive(Clone, Copy, PartialEq, Eq, Debug, ConstDefault)]
+pub struct Stats {
+    pub min: u32,
+    pub max: u32,
+    pub average: u32,
+    /// How many times the span was recorded during the window.
+    pub samples: u32,
+}
+
+#[derive(Clone, Copy)]
+struct Accumulator {
+    min: u32,
+    max: u32,
+    total: u32,
+    samples: u32,
+}
+impl ConstDefault for Accumulator {
+    const DEFAULT: Self = Self {
+        min: u32::MAX,
+        max: 0,
+        total: 0,
+        samples: 0,
+    };
+}
+impl Accumulator {
+    fn add(&mut self, cycles: u32) {
+        self.min = self.min.min(cycles);
+        self.max = self.max.max(cycles);
+        self.total = self.total.saturating_add(cycles);
+        self.samples += 1;
+    }
+    fn stats(&self) -> Stats {
+        match self.samples {
+            0 => Stats::DEFAULT,
+            samples => Stats {
+                min: self.min,
+                max: self.max,
+                average: self.total / samples,
+                samples,
+            },
+        }
+    }
+}
+
+struct Span {
+    name: &'static str,
+    current: Accumulator,
+    last: Stats,
+}
+
+/// Records how many CPU cycles parts of the game take.
+///
+/// Accessible as `ConsoleState::profiler`, see [module-level doc](self)
+/// for details.
+pub struct Profiler {
+    /// The low and high half of the cycle counter, `None` when disabled.
+    timers: Option<(timer::Slot, timer::Slot)>,
+    spans: ArrayVec<Span, MAX_SPANS>,
+    frame_in_window: u32,
+}
+impl ConstDefault for Profiler {
+    const DEFAULT: Self = Self {
+        timers: None,
+        spans: ArrayVec::new_const(),
+        frame_in_window: 0,
+    };
+}
+impl Profiler {
+    #[must_use]
+    pub const fn is_enabled(&self) -> bool {
+        self.timers.is_some()
+    }
+    /// The current time, to pass to [`Self::record`] at the end of the span.
+    #[must_use]
+    pub fn start(&self) -> Timestamp {
+        Timestamp(self.cycles())
+    }
+    /// Record the duration of the span `name` that started at `start`.
+    ///
+    /// Does nothing if the profiler is disabled, or if there are already
+    /// [`MAX_SPANS`] different spans.
+    pub fn record(&mut self, name: &'static str, start: Timestamp) {
+        if !self.is_enabled() {
+            return;
+        }
+        let cycles = self.cycles().wrapping_sub(start.0);
+        if let Some(span) = self.spans.iter_mut().find(|span| span.name == name) {
+            span.current.add(cycles);
+            return;
+        }
+        let mut current = Accumulator::DEFAULT;
+        current.add(cycles);
+        let new_span = Span { name, current, last: Stats::DEFAULT };
+        let is_full = self.spans.try_push(new_span).is_err();
+        crate::warn!((is_full) "Too many profiler spans, ignoring {name}");
+    }
+    /// Statistics of span `name` over the last complete window.
+    #[must_use]
+    pub fn stats(&self, name: &str) -> Option<Stats> {
+        let span = self.spans.iter().find(|span| span.name == name)?;
+        Some(span.last)
+    }
+    /// Statistics of all spans over the last complete window.
+    pub fn all_stats(&self) -> impl Iterator<Item = (&'static str, Stats)> + '_ {
+        self.spans.iter().map(|span| (span.name, span.last))
+    }
+    /// Print statistics of all spans with [`crate::info!`].
+    pub fn log(&self) {
+        for (name, stats) in self.all_stats() {
+            let Stats { min, max, average, samples } = stats;
+            let percent = average * 100 / FRAME_CYCLES;
+            crate::info!(
+                "{name}: avg {average} ({percent}%) min {min} max {max} ({samples} samples)"
+            );
+        }
+    }
+    /// Start counting cycles with the given timers.
+    pub(crate) fn enable(&mut self, mut low: timer::Slot, mut high: timer::Slot) {
+        low.set_reload(0);
+        high.set_reload(0);
+        high.start_cascade();
+        low.start(timer::Prescaler::Cycles1);
+        self.timers = Some((low, high));
+    }
+    /// Stop counting cycles, returning the timers used.
+    pub(crate) fn disable(&mut self) -> Option<(timer::Slot, timer::Slot)> {
+        self.spans.clear();
+        self.frame_in_window = 0;
+        self.timers.take()
+    }
+    /// Complete a frame, computing statistics if the window is complete.
+    pub(crate) fn end_frame(&mut self) {
+        if !self.is_enabled() {
+            return;
+        }
+        self.frame_in_window += 1;
+        if self.frame_in_window < WINDOW_FRAMES {
+            return;
+        }
+        self.frame_in_window = 0;
+        for span in &mut self.spans {
+            span.last = span.current.stats();
+            span.current = Accumulator::DEFAULT;
+        }
+    }
+    fn cycles(&self) -> u32 {
+        let (low, high) = match &self.timers {
+            Some(timers) => timers,
+            None => return 0,
+        };
+        // Read high twice, in case `low` overflowed between the two reads.
+        loop {
+            let high_before = high.counter();
+            let low_value = low.counter();
+            let high_after = high.counter();
+            if high_before == high_after {
+                return u32::from(high_before) << 16 | u32::from(low_value);
+            }
+        }
+    }
+}
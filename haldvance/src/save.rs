@@ -0,0 +1,232 @@
+//! Battery-backed save memory, see [`Storage`] and [`Record`].
+//!
+//! GBA cartridges store saves in one of several kinds of backup memory,
+//! see [`Kind`]. The game can't know which one the cartridge has, so it has
+//! to declare it: emulators and flashcarts look for an ID string in the ROM
+//! to pick the backup memory to emulate. The [`crate::backup!`] macro embeds
+//! this string, and returns the matching [`Backup`]:
+//!
+//! ```ignore
+//! let mut backup = hal::backup!(Flash128K);
+//! ```
+//!
+//! Flash chips can also be identified at runtime, see [`Flash::detect`].
+//!
+//! [`Storage`] gives raw byte access to the backup memory. Since a power cut
+//! while writing would leave corrupted data behind, prefer storing game data
+//! as [`Record`]s: they are versioned, checksummed, and written alternately
+//! to two slots, so that the previous save is still there if the last write
+//! didn't complete.
+//!
+//! With the `host` feature, [`File`] stores saves in a regular file, for tests.
+use volmatrix::rw::VolAddress;
+
+mod eeprom;
+#[cfg(feature = "host")]
+mod file;
+mod flash;
+mod record;
+mod sram;
+
+pub use eeprom::Eeprom;
+#[cfg(feature = "host")]
+pub use file::File;
+pub use flash::Flash;
+pub use record::{Data, Record, HEADER_LEN, MAX_RECORD_LEN};
+pub use sram::Sram;
+
+// SAFETY: WAITCNT is a read/write 16 bits register.
+const WAITCNT: VolAddress<u16> = unsafe { VolAddress::new(0x0400_0204) };
+const RAM_WAIT_8_CYCLES: u16 = 0b11;
+
+/// A kind of cartridge backup memory.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Kind {
+    /// 32KiB battery-backed SRAM.
+    Sram,
+    /// 64KiB Flash.
+    Flash64K,
+    /// 128KiB Flash, in two 64KiB banks.
+    Flash128K,
+    /// 512 bytes EEPROM, accessed through DMA.
+    Eeprom512B,
+    /// 8KiB EEPROM, accessed through DMA.
+    Eeprom8K,
+}
+impl Kind {
+    /// The size in bytes of the backup memory.
+    #[must_use]
+    pub const fn size(self) -> usize {
+        match self {
+            Self::Sram => 0x8000,
+            Self::Flash64K => 0x1_0000,
+            Self::Flash128K => 0x2_0000,
+            Self::Eeprom512B => 0x200,
+            Self::Eeprom8K => 0x2000,
+        }
+    }
+    /// The size of the smallest area [`Storage::erase`] can erase.
+    #[must_use]
+    pub const fn sector_size(self) -> usize {
+        match self {
+            Self::Sram => 1,
+            Self::Flash64K | Self::Flash128K => 0x1000,
+            Self::Eeprom512B | Self::Eeprom8K => 8,
+        }
+    }
+    /// The string emulators search in ROMs to detect the backup memory
+    /// kind, see [`crate::backup!`].
+    #[must_use]
+    pub const fn id_string(self) -> IdString {
+        let name: &[u8] = match self {
+            Self::Sram => b"SRAM_V113",
+            Self::Flash64K => b"FLASH512_V131",
+            Self::Flash128K => b"FLASH1M_V103",
+            Self::Eeprom512B | Self::Eeprom8K => b"EEPROM_V124",
+        };
+        let mut id = [0; 16];
+        let mut i = 0;
+        while i < name.len() {
+            id[i] = name[i];
+            i += 1;
+        }
+        IdString(id)
+    }
+}
+
+/// The backup memory ID string, word aligned and zero-padded, as emulators
+/// expect it.
+#[repr(C, align(4))]
+pub struct IdString(pub [u8; 16]);
+
+/// Why a [`Storage`] operation failed.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Error {
+    /// The operation goes past the end of the backup memory.
+    OutOfBounds,
+    /// The backup memory didn't complete the operation, it may be missing
+    /// or of another [`Kind`]. With [`File`], the file couldn't be accessed.
+    Device,
+}
+
+/// Byte access to backup memory, see [module-level doc](self).
+///
+/// Flash memory can only be written to after being erased, so call
+/// [`Storage::erase`] before [`Storage::write`].
+pub trait Storage {
+    /// The kind of backup memory.
+    fn kind(&self) -> Kind;
+    /// Read `bytes.len()` bytes starting at `offset`.
+    ///
+    /// # Errors
+    ///
+    /// If reading past the end of the memory, or the memory didn't respond.
+    fn read(&mut self, offset: usize, bytes: &mut [u8]) -> Result<(), Error>;
+    /// Write `bytes` starting at `offset`.
+    ///
+    /// # Errors
+    ///
+    /// If writing past the end of the memory, or the memory didn't respond.
+    fn write(&mut self, offset: usize, bytes: &[u8]) -> Result<(), Error>;
+    /// Erase all sectors containing the `len` bytes starting at `offset`, see
+    /// [`Kind::sector_size`]. Only needed with Flash, this does nothing otherwise.
+    ///
+    /// # Errors
+    ///
+    /// If erasing past the end of the memory, or the memory didn't respond.
+    fn erase(&mut self, offset: usize, len: usize) -> Result<(), Error> {
+        check_bounds(self.kind(), offset, len)
+    }
+}
+
+/// The backup memory declared with [`crate::backup!`].
+pub enum Backup {
+    Sram(Sram),
+    Flash(Flash),
+    Eeprom(Eeprom),
+}
+impl Backup {
+    /// Access backup memory of `kind`, prefer [`crate::backup!`], which also
+    /// embeds the ID string of `kind` in the ROM.
+    #[must_use]
+    pub fn new(kind: Kind) -> Self {
+        match kind {
+            Kind::Sram => Self::Sram(Sram::new()),
+            Kind::Flash64K | Kind::Flash128K => Self::Flash(Flash::new(kind)),
+            Kind::Eeprom512B | Kind::Eeprom8K => Self::Eeprom(Eeprom::new(kind)),
+        }
+    }
+    fn storage(&mut self) -> &mut dyn Storage {
+        match self {
+            Self::Sram(sram) => sram,
+            Self::Flash(flash) => flash,
+            Self::Eeprom(eeprom) => eeprom,
+        }
+    }
+}
+impl Storage for Backup {
+    fn kind(&self) -> Kind {
+        match self {
+            Self::Sram(sram) => sram.kind(),
+            Self::Flash(flash) => flash.kind(),
+            Self::Eeprom(eeprom) => eeprom.kind(),
+        }
+    }
+    fn read(&mut self, offset: usize, bytes: &mut [u8]) -> Result<(), Error> {
+        self.storage().read(offset, bytes)
+    }
+    fn write(&mut self, offset: usize, bytes: &[u8]) -> Result<(), Error> {
+        self.storage().write(offset, bytes)
+    }
+    fn erase(&mut self, offset: usize, len: usize) -> Result<(), Error> {
+        self.storage().erase(offset, len)
+    }
+}
+
+/// Whether the `len` bytes at `offset` are within backup memory of `kind`.
+fn check_bounds(kind: Kind, offset: usize, len: usize) -> Result<(), Error> {
+    match offset.checked_add(len) {
+        Some(end) if end <= kind.size() => Ok(()),
+        Some(_) | None => {
+            crate::error!("Save access of {len} bytes at {offset:#x} out of bounds");
+            Err(Error::OutOfBounds)
+        }
+    }
+}
+
+/// Set the cartridge RAM access time to 8 cycles, the only one that works
+/// with all SRAM and Flash chips.
+fn set_ram_waitstate() {
+    WAITCNT.write(WAITCNT.read() | RAM_WAIT_8_CYCLES);
+}
+
+/// Poll `is_done` until it returns `true`, for at most `tries` times.
+fn wait(tries: u32, mut is_done: impl FnMut() -> bool) -> Result<(), Error> {
+    if (0..tries).any(|_| is_done()) {
+        Ok(())
+    } else {
+        crate::error!("Save memory timed out");
+        Err(Error::Device)
+    }
+}
+
+/// Access backup memory, embedding its ID string in the ROM, see [`save`].
+///
+/// ```ignore
+/// let mut backup: hal::save::Backup = hal::backup!(Sram);
+/// ```
+///
+/// Where the argument is one of the [`save::Kind`] variants.
+///
+/// [`save`]: crate::save
+/// [`save::Kind`]: crate::save::Kind
+#[macro_export]
+macro_rules! backup {
+    ($kind:ident) => {{
+        #[used]
+        static BACKUP_ID: $crate::save::IdString = $crate::save::Kind::$kind.id_string();
+        // Otherwise, the linker may remove the unused ID string.
+        let _ = core::hint::black_box(&BACKUP_ID);
+        $crate::save::Backup::new($crate::save::Kind::$kind)
+    }};
+}
@@ -0,0 +1,131 @@
+//! EEPROM, see [`Eeprom`].
+//!
+//! The EEPROM is a serial device: requests and responses are sent one bit
+//! per half word, with DMA 3. It is read and written 8 bytes blocks at a time.
+use core::ops::Range;
+
+use arrayvec::ArrayVec;
+use volmatrix::{dma, rw::VolAddress};
+
+use super::{check_bounds, wait, Error, Kind, Storage};
+use crate::interrupt;
+
+/// The EEPROM port, for ROMs up to 16MiB.
+const EEPROM: usize = 0x0D00_0000;
+// SAFETY: reading the EEPROM port returns the next bit of a response.
+const EEPROM_PORT: VolAddress<u16> = unsafe { VolAddress::new(EEPROM) };
+
+const BLOCK_SIZE: usize = 8;
+const BLOCK_BITS: usize = BLOCK_SIZE * 8;
+const READ_REQUEST: u16 = 0b11;
+const WRITE_REQUEST: u16 = 0b10;
+/// Read responses start with 4 bits to ignore.
+const READ_PADDING: usize = 4;
+/// The longest request: 2 bits of request, 14 bits of address, a block,
+/// and a stop bit.
+const MAX_REQUEST_LEN: usize = 2 + 14 + BLOCK_BITS + 1;
+/// Writing a block takes up to 10ms, about 168K CPU cycles.
+const WRITE_TRIES: u32 = 0x4_0000;
+
+/// The blocks containing the `len` bytes at `offset`, with the range of
+/// bytes within the block, and within the `len` bytes.
+fn blocks(offset: usize, len: usize) -> impl Iterator<Item = (usize, Range<usize>, Range<usize>)> {
+    let end = offset + len;
+    (offset / BLOCK_SIZE..end.div_ceil(BLOCK_SIZE)).map(move |block| {
+        let block_start = block * BLOCK_SIZE;
+        let start = offset.max(block_start);
+        let block_end = end.min(block_start + BLOCK_SIZE);
+        let in_block = start - block_start..block_end - block_start;
+        (block, in_block, start - offset..block_end - offset)
+    })
+}
+
+/// 512B or 8KiB of EEPROM, see [module-level doc](self).
+pub struct Eeprom {
+    kind: Kind,
+}
+impl Eeprom {
+    pub(super) const fn new(kind: Kind) -> Self {
+        Self { kind }
+    }
+    /// The request bits for `request` on `block`, without the stop bit.
+    fn request(&self, request: u16, block: usize) -> ArrayVec<u16, MAX_REQUEST_LEN> {
+        let address_bits = match self.kind {
+            Kind::Eeprom512B => 6,
+            _ => 14,
+        };
+        let mut bits = ArrayVec::new();
+        bits.push(request >> 1);
+        bits.push(request & 1);
+        bits.extend(
+            (0..address_bits)
+                .rev()
+                .map(|i| u16::from((block >> i) & 1 == 1)),
+        );
+        bits
+    }
+    /// Send the request `bits` through DMA.
+    fn send(bits: &[u16]) {
+        // SAFETY: `bits` is a valid buffer of half words, and the EEPROM
+        // port takes as many half words as sent.
+        unsafe { dma::copy_halfwords(bits.as_ptr(), EEPROM as *mut u16, bits.len()) };
+    }
+    fn read_block(&self, block: usize) -> [u8; BLOCK_SIZE] {
+        let mut request = self.request(READ_REQUEST, block);
+        request.push(0);
+        let mut response = [0; READ_PADDING + BLOCK_BITS];
+        interrupt::free(|| {
+            Self::send(&request);
+            // SAFETY: `response` is a valid buffer of half words, and the
+            // EEPROM port returns as many half words as read.
+            unsafe {
+                dma::copy_halfwords(EEPROM as *const u16, response.as_mut_ptr(), response.len());
+            };
+        });
+        let mut data = [0; BLOCK_SIZE];
+        for (byte, bits) in data
+            .iter_mut()
+            .zip(response[READ_PADDING..].chunks_exact(8))
+        {
+            *byte = bits
+                .iter()
+                .fold(0, |byte, bit| (byte << 1) | u8::from(bit & 1 == 1));
+        }
+        data
+    }
+    fn write_block(&self, block: usize, data: &[u8; BLOCK_SIZE]) -> Result<(), Error> {
+        let mut request = self.request(WRITE_REQUEST, block);
+        for byte in data {
+            request.extend((0..8).rev().map(|i| u16::from((byte >> i) & 1 == 1)));
+        }
+        request.push(0);
+        // Interrupts could use DMA 3 while we are using it.
+        interrupt::free(|| Self::send(&request));
+        // The EEPROM returns 1 once it's done writing.
+        wait(WRITE_TRIES, || EEPROM_PORT.read() & 1 == 1)
+    }
+}
+impl Storage for Eeprom {
+    fn kind(&self) -> Kind {
+        self.kind
+    }
+    fn read(&mut self, offset: usize, bytes: &mut [u8]) -> Result<(), Error> {
+        check_bounds(self.kind, offset, bytes.len())?;
+        for (block, in_block, in_bytes) in blocks(offset, bytes.len()) {
+            bytes[in_bytes].copy_from_slice(&self.read_block(block)[in_block]);
+        }
+        Ok(())
+    }
+    fn write(&mut self, offset: usize, bytes: &[u8]) -> Result<(), Error> {
+        check_bounds(self.kind, offset, bytes.len())?;
+        for (block, in_block, in_bytes) in blocks(offset, bytes.len()) {
+            let mut data = match in_block.len() {
+                BLOCK_SIZE => [0; BLOCK_SIZE],
+                _ => self.read_block(block),
+            };
+            data[in_block].copy_from_slice(&bytes[in_bytes]);
+            self.write_block(block, &data)?;
+        }
+        Ok(())
+    }
+}
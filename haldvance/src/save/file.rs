@@ -0,0 +1,73 @@
+//! Backup memory in a file, see [`File`].
+use std::{
+    fs,
+    io::{self, Read, Seek, SeekFrom, Write},
+    path::Path,
+};
+
+use super::{check_bounds, Error, Kind, Storage};
+
+const ERASED: u8 = 0xFF;
+
+fn io_error(err: io::Error) -> Error {
+    crate::error!("Couldn't access the save file: {err}");
+    Error::Device
+}
+
+/// Backup memory of a given [`Kind`] stored in a file, for tests on the host.
+///
+/// New files are filled with `0xFF`, like erased Flash.
+pub struct File {
+    file: fs::File,
+    kind: Kind,
+}
+impl File {
+    /// Open the file at `path`, creating it if it doesn't exist.
+    ///
+    /// # Errors
+    ///
+    /// If the file can't be opened or created.
+    pub fn open(path: impl AsRef<Path>, kind: Kind) -> io::Result<Self> {
+        let mut file = fs::OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(false)
+            .open(path)?;
+        let len = usize::try_from(file.metadata()?.len()).unwrap_or(usize::MAX);
+        if len < kind.size() {
+            file.seek(SeekFrom::End(0))?;
+            file.write_all(&vec![ERASED; kind.size() - len])?;
+        }
+        Ok(Self { file, kind })
+    }
+}
+impl Storage for File {
+    fn kind(&self) -> Kind {
+        self.kind
+    }
+    fn read(&mut self, offset: usize, bytes: &mut [u8]) -> Result<(), Error> {
+        check_bounds(self.kind, offset, bytes.len())?;
+        self.file
+            .seek(SeekFrom::Start(offset as u64))
+            .map_err(io_error)?;
+        self.file.read_exact(bytes).map_err(io_error)
+    }
+    fn write(&mut self, offset: usize, bytes: &[u8]) -> Result<(), Error> {
+        check_bounds(self.kind, offset, bytes.len())?;
+        self.file
+            .seek(SeekFrom::Start(offset as u64))
+            .map_err(io_error)?;
+        self.file.write_all(bytes).map_err(io_error)
+    }
+    fn erase(&mut self, offset: usize, len: usize) -> Result<(), Error> {
+        check_bounds(self.kind, offset, len)?;
+        if !matches!(self.kind, Kind::Flash64K | Kind::Flash128K) {
+            return Ok(());
+        }
+        let sector_size = self.kind.sector_size();
+        let start = offset / sector_size * sector_size;
+        let end = (offset + len).next_multiple_of(sector_size);
+        self.write(start, &vec![ERASED; end - start])
+    }
+}
@@ -0,0 +1,120 @@
+//! Flash memory, see [`Flash`].
+//!
+//! Flash chips are driven by writing command sequences to specific
+//! addresses. Only bytes erased to `0xFF` can be written to, and erasing is
+//! done a whole 4KiB sector at a time. 128KiB chips are split in two 64KiB
+//! banks, only one of which is visible at a time.
+use volmatrix::rw::VolBlock;
+
+use super::{check_bounds, set_ram_waitstate, wait, Error, Kind, Storage};
+
+const BANK_SIZE: usize = 0x1_0000;
+// SAFETY: the cartridge Flash, the current bank of it, only accessible
+// 8 bits at a time.
+const FLASH: VolBlock<u8, BANK_SIZE> = unsafe { VolBlock::new(0x0E00_0000) };
+
+const COMMAND_ADDRESS_1: usize = 0x5555;
+const COMMAND_ADDRESS_2: usize = 0x2AAA;
+const ENTER_ID_MODE: u8 = 0x90;
+const EXIT_ID_MODE: u8 = 0xF0;
+const ERASE: u8 = 0x80;
+const ERASE_SECTOR: u8 = 0x30;
+const WRITE_BYTE: u8 = 0xA0;
+const SWITCH_BANK: u8 = 0xB0;
+
+const ERASED: u8 = 0xFF;
+/// Erasing a sector takes up to 20ms, about 335K CPU cycles.
+const ERASE_TRIES: u32 = 0x8_0000;
+/// Writing a byte takes up to 20µs, about 335 CPU cycles.
+const WRITE_TRIES: u32 = 0x1000;
+
+/// Start the command sequence of `command`.
+fn command(command: u8) {
+    FLASH.index(COMMAND_ADDRESS_1).write(0xAA);
+    FLASH.index(COMMAND_ADDRESS_2).write(0x55);
+    FLASH.index(COMMAND_ADDRESS_1).write(command);
+}
+
+/// 64KiB or 128KiB of Flash, see [module-level doc](self).
+pub struct Flash {
+    kind: Kind,
+    /// The visible bank of 128KiB Flash, `None` if unknown.
+    bank: Option<usize>,
+}
+impl Flash {
+    pub(super) fn new(kind: Kind) -> Self {
+        set_ram_waitstate();
+        Self { kind, bank: None }
+    }
+    /// Identify the Flash chip of the cartridge, `None` if there isn't a
+    /// supported one.
+    ///
+    /// Atmel chips, which are written 128 bytes at a time, are not supported.
+    #[must_use]
+    pub fn detect() -> Option<Self> {
+        set_ram_waitstate();
+        command(ENTER_ID_MODE);
+        let id = (FLASH.index(0).read(), FLASH.index(1).read());
+        command(EXIT_ID_MODE);
+        let kind = match id {
+            // Sanyo and Macronix
+            (0x62, 0x13) | (0xC2, 0x09) => Kind::Flash128K,
+            // Panasonic, SST and Macronix
+            (0x32, 0x1B) | (0xBF, 0xD4) | (0xC2, 0x1C) => Kind::Flash64K,
+            (manufacturer, device) => {
+                crate::warn!("Unsupported Flash chip {manufacturer:#04x}:{device:#04x}");
+                return None;
+            }
+        };
+        Some(Self::new(kind))
+    }
+    /// Switch to the bank of `offset`, returns `offset` within the bank.
+    fn select(&mut self, offset: usize) -> usize {
+        let bank = offset / BANK_SIZE;
+        if self.kind == Kind::Flash128K && self.bank != Some(bank) {
+            command(SWITCH_BANK);
+            // unwrap: there are only two banks.
+            FLASH.index(0).write(bank.try_into().unwrap());
+            self.bank = Some(bank);
+        }
+        offset % BANK_SIZE
+    }
+}
+impl Storage for Flash {
+    fn kind(&self) -> Kind {
+        self.kind
+    }
+    fn read(&mut self, offset: usize, bytes: &mut [u8]) -> Result<(), Error> {
+        check_bounds(self.kind, offset, bytes.len())?;
+        for (i, byte) in bytes.iter_mut().enumerate() {
+            let address = self.select(offset + i);
+            *byte = FLASH.index(address).read();
+        }
+        Ok(())
+    }
+    fn write(&mut self, offset: usize, bytes: &[u8]) -> Result<(), Error> {
+        check_bounds(self.kind, offset, bytes.len())?;
+        for (i, &byte) in bytes.iter().enumerate() {
+            let address = self.select(offset + i);
+            command(WRITE_BYTE);
+            FLASH.index(address).write(byte);
+            wait(WRITE_TRIES, || FLASH.index(address).read() == byte)?;
+        }
+        Ok(())
+    }
+    fn erase(&mut self, offset: usize, len: usize) -> Result<(), Error> {
+        check_bounds(self.kind, offset, len)?;
+        let sector_size = self.kind.sector_size();
+        let first = offset / sector_size;
+        let end = (offset + len).div_ceil(sector_size);
+        for sector in first..end {
+            let address = self.select(sector * sector_size);
+            command(ERASE);
+            FLASH.index(COMMAND_ADDRESS_1).write(0xAA);
+            FLASH.index(COMMAND_ADDRESS_2).write(0x55);
+            FLASH.index(address).write(ERASE_SECTOR);
+            wait(ERASE_TRIES, || FLASH.index(address).read() == ERASED)?;
+        }
+        Ok(())
+    }
+}
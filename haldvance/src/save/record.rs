@@ -0,0 +1,245 @@
+//! Versioned and checksummed data in backup memory, see [`Record`].
+use core::marker::PhantomData;
+
+use super::{Error, Storage};
+
+/// The largest [`Data::ENCODED_LEN`] a [`Record`] can store.
+pub const MAX_RECORD_LEN: usize = 512;
+/// Size of the header preceding the data in each [`Record`] slot.
+///
+/// It holds a magic number, the [`Data::VERSION`], a generation counter
+/// increasing with each save, and a checksum of the header and data.
+pub const HEADER_LEN: usize = 12;
+
+const MAGIC: [u8; 2] = *b"SV";
+
+/// Data that can be stored in a [`Record`].
+pub trait Data {
+    /// Change this when the encoding changes, records of other versions
+    /// are then ignored.
+    const VERSION: u16;
+    /// Size in bytes of the encoded data, at most [`MAX_RECORD_LEN`].
+    const ENCODED_LEN: usize;
+
+    /// Write the data to `bytes`, which is [`Self::ENCODED_LEN`] long.
+    fn encode(&self, bytes: &mut [u8]);
+    /// Read data written with [`Self::encode`].
+    ///
+    /// Returns `false` and keeps `self` unchanged if `bytes` doesn't
+    /// contain valid data.
+    fn decode(&mut self, bytes: &[u8]) -> bool;
+}
+
+/// CRC-32 of all `parts`, computed bit by bit, since records are small.
+fn crc32(parts: &[&[u8]]) -> u32 {
+    let mut crc = !0_u32;
+    for &byte in parts.iter().flat_map(|part| part.iter()) {
+        crc ^= u32::from(byte);
+        for _ in 0..8 {
+            let mask = (crc & 1).wrapping_neg();
+            crc = (crc >> 1) ^ (0xEDB8_8320 & mask);
+        }
+    }
+    !crc
+}
+
+/// Whether `generation` was saved after `other`, accounting for overflows.
+fn is_newer(generation: u32, other: u32) -> bool {
+    (1..=u32::MAX / 2).contains(&generation.wrapping_sub(other))
+}
+
+/// A [`Data`] stored in backup memory, see [module-level doc](super).
+///
+/// The record takes [`Record::size`] bytes, split in two slots. Each save
+/// overwrites the slot not holding the last save, so that if the save is
+/// interrupted, by a power cut for example, the last save is still there.
+///
+/// ```ignore
+/// let mut scores = Record::<Scores>::new(0);
+/// let mut options = Record::<Options>::new(Record::<Scores>::size(&backup));
+///
+/// if !scores.load(&mut backup, &mut table)? {
+///     hal::info!("No saved high scores yet");
+/// }
+/// ```
+pub struct Record<T> {
+    offset: usize,
+    /// The slot of the last save, and its generation, if known.
+    newest: Option<(usize, u32)>,
+    _data: PhantomData<T>,
+}
+impl<T: Data> Record<T> {
+    /// A record stored at `offset` in backup memory.
+    ///
+    /// With Flash, `offset` should be a multiple of [`Kind::sector_size`],
+    /// otherwise saving erases data before the record.
+    ///
+    /// # Panics
+    ///
+    /// If `T::ENCODED_LEN` is larger than [`MAX_RECORD_LEN`].
+    ///
+    /// [`Kind::sector_size`]: super::Kind::sector_size
+    #[must_use]
+    pub const fn new(offset: usize) -> Self {
+        assert!(T::ENCODED_LEN <= MAX_RECORD_LEN, "Record data is too large");
+        Self { offset, newest: None, _data: PhantomData }
+    }
+    /// Size of one slot, a whole count of sectors, so that erasing one slot
+    /// doesn't erase the other.
+    fn slot_size(storage: &impl Storage) -> usize {
+        (HEADER_LEN + T::ENCODED_LEN).next_multiple_of(storage.kind().sector_size())
+    }
+    /// Size in bytes of the record in `storage`, the next record can be
+    /// stored at `offset + size`.
+    #[must_use]
+    pub fn size(storage: &impl Storage) -> usize {
+        2 * Self::slot_size(storage)
+    }
+    /// Read `slot` into `bytes`, returns its generation if it holds a valid
+    /// record of this version.
+    fn read_slot(
+        &self,
+        storage: &mut impl Storage,
+        slot: usize,
+        bytes: &mut [u8],
+    ) -> Result<Option<u32>, Error> {
+        let offset = self.offset + slot * Self::slot_size(storage);
+        storage.read(offset, bytes)?;
+        let (header, data) = bytes.split_at(HEADER_LEN);
+        let word = |at: usize| {
+            u32::from_le_bytes([header[at], header[at + 1], header[at + 2], header[at + 3]])
+        };
+        let version = u16::from_le_bytes([header[2], header[3]]);
+        let is_valid = header[..2] == MAGIC
+            && version == T::VERSION
+            && word(8) == crc32(&[&header[..8], data]);
+        Ok(is_valid.then(|| word(4)))
+    }
+    /// The generations of the records in both slots, `None` if invalid.
+    fn generations(&self, storage: &mut impl Storage) -> Result<[Option<u32>; 2], Error> {
+        let mut bytes = [0; HEADER_LEN + MAX_RECORD_LEN];
+        let bytes = &mut bytes[..HEADER_LEN + T::ENCODED_LEN];
+        Ok([
+            self.read_slot(storage, 0, bytes)?,
+            self.read_slot(storage, 1, bytes)?,
+        ])
+    }
+    /// Load the last saved record into `value`.
+    ///
+    /// Returns `false` and keeps `value` unchanged if there is no valid
+    /// record, for example before the first save, or after changing
+    /// [`Data::VERSION`].
+    ///
+    /// # Errors
+    ///
+    /// If `storage` couldn't be read, see [`Storage::read`].
+    pub fn load(&mut self, storage: &mut impl Storage, value: &mut T) -> Result<bool, Error> {
+        let generations = self.generations(storage)?;
+        let newest_first = match generations {
+            [Some(first), Some(second)] if is_newer(second, first) => [1, 0],
+            _ => [0, 1],
+        };
+        let mut bytes = [0; HEADER_LEN + MAX_RECORD_LEN];
+        let bytes = &mut bytes[..HEADER_LEN + T::ENCODED_LEN];
+        for slot in newest_first {
+            let generation = match generations[slot] {
+                Some(generation) => generation,
+                None => continue,
+            };
+            self.read_slot(storage, slot, bytes)?;
+            if value.decode(&bytes[HEADER_LEN..]) {
+                self.newest = Some((slot, generation));
+                return Ok(true);
+            }
+            crate::warn!("Couldn't decode the record in slot {slot}, trying the other one");
+        }
+        Ok(false)
+    }
+    /// Save `value`, keeping the previous save until it's written.
+    ///
+    /// # Errors
+    ///
+    /// If `storage` couldn't be read, erased or written, see [`Storage`].
+    pub fn save(&mut self, storage: &mut impl Storage, value: &T) -> Result<(), Error> {
+        let newest = match self.newest {
+            Some(newest) => Some(newest),
+            None => match self.generations(storage)? {
+                [Some(first), Some(second)] if is_newer(second, first) => Some((1, second)),
+                [Some(first), _] => Some((0, first)),
+                [None, second] => second.map(|second| (1, second)),
+            },
+        };
+        let (slot, generation) = match newest {
+            Some((slot, generation)) => (1 - slot, generation.wrapping_add(1)),
+            None => (0, 0),
+        };
+        let mut bytes = [0; HEADER_LEN + MAX_RECORD_LEN];
+        let (header, data) = bytes[..HEADER_LEN + T::ENCODED_LEN].split_at_mut(HEADER_LEN);
+        value.encode(data);
+        header[..2].copy_from_slice(&MAGIC);
+        header[2..4].copy_from_slice(&T::VERSION.to_le_bytes());
+        header[4..8].copy_from_slice(&generation.to_le_bytes());
+        let checksum = crc32(&[&header[..8], data]);
+        header[8..].copy_from_slice(&checksum.to_le_bytes());
+
+        let slot_size = Self::slot_size(storage);
+        let offset = self.offset + slot * slot_size;
+        storage.erase(offset, slot_size)?;
+        // Write the header last, so that the slot stays invalid until
+        // the whole record is written.
+        storage.write(offset + HEADER_LEN, data)?;
+        storage.write(offset, header)?;
+        self.newest = Some((slot, generation));
+        Ok(())
+    }
+}
+
+#[cfg(all(test, feature = "host"))]
+mod test {
+    use std::{env, fs, process};
+
+    use super::{Data, Record, HEADER_LEN};
+    use crate::save::{File, Kind, Storage};
+
+    #[derive(PartialEq, Debug)]
+    struct Score(u32);
+    impl Data for Score {
+        const VERSION: u16 = 1;
+        const ENCODED_LEN: usize = 4;
+
+        fn encode(&self, bytes: &mut [u8]) {
+            bytes.copy_from_slice(&self.0.to_le_bytes());
+        }
+        fn decode(&mut self, bytes: &[u8]) -> bool {
+            self.0 = u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]);
+            true
+        }
+    }
+
+    #[test]
+    fn interrupted_save_keeps_previous() {
+        let path = env::temp_dir().join(format!("haldvance-record-{}.sav", process::id()));
+        let _ = fs::remove_file(&path);
+        let mut storage = File::open(&path, Kind::Flash64K).unwrap();
+        let mut score = Score(0);
+        let mut record = Record::<Score>::new(0);
+        assert_eq!(record.load(&mut storage, &mut score), Ok(false));
+        record.save(&mut storage, &Score(10)).unwrap();
+        record.save(&mut storage, &Score(20)).unwrap();
+        assert_eq!(Record::new(0).load(&mut storage, &mut score), Ok(true));
+        assert_eq!(score, Score(20));
+
+        // Corrupt the last save, as if the power was cut while writing it.
+        let second_slot = Record::<Score>::size(&storage) / 2;
+        storage.write(second_slot + HEADER_LEN, &[0xAB]).unwrap();
+        assert_eq!(Record::new(0).load(&mut storage, &mut score), Ok(true));
+        assert_eq!(score, Score(10));
+
+        // The next save overwrites the corrupted slot, not the valid one.
+        record = Record::new(0);
+        record.save(&mut storage, &Score(30)).unwrap();
+        assert_eq!(Record::new(0).load(&mut storage, &mut score), Ok(true));
+        assert_eq!(score, Score(30));
+        fs::remove_file(path).unwrap();
+    }
+}
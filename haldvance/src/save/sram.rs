@@ -0,0 +1,38 @@
+//! Battery-backed SRAM, see [`Sram`].
+use volmatrix::rw::VolBlock;
+
+use super::{check_bounds, set_ram_waitstate, Error, Kind, Storage};
+
+// SAFETY: the cartridge SRAM, only accessible 8 bits at a time.
+const SRAM: VolBlock<u8, { Kind::Sram.size() }> = unsafe { VolBlock::new(0x0E00_0000) };
+
+/// 32KiB of battery-backed SRAM, see [`Kind::Sram`].
+///
+/// SRAM doesn't need to be erased, but an interrupted write may leave
+/// partially written data, see [`super::Record`].
+pub struct Sram(());
+impl Sram {
+    pub(super) fn new() -> Self {
+        set_ram_waitstate();
+        Self(())
+    }
+}
+impl Storage for Sram {
+    fn kind(&self) -> Kind {
+        Kind::Sram
+    }
+    fn read(&mut self, offset: usize, bytes: &mut [u8]) -> Result<(), Error> {
+        check_bounds(Kind::Sram, offset, bytes.len())?;
+        for (i, byte) in bytes.iter_mut().enumerate() {
+            *byte = SRAM.index(offset + i).read();
+        }
+        Ok(())
+    }
+    fn write(&mut self, offset: usize, bytes: &[u8]) -> Result<(), Error> {
+        check_bounds(Kind::Sram, offset, bytes.len())?;
+        for (i, byte) in bytes.iter().enumerate() {
+            SRAM.index(offset + i).write(*byte);
+        }
+        Ok(())
+    }
+}
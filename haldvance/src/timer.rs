@@ -0,0 +1,184 @@
+//! Hardware timers, see [`Slot`].
+//!
+//! The GBA has four 16 bits timers, counting up at a fraction of the
+//! CPU frequency (16.78 MHz), see [`Prescaler`]. When a timer overflows,
+//! it restarts counting at its reload value, and may:
+//! - Trigger an interrupt, see [`Slot::set_overflow_irq`].
+//! - Increment the next timer, if the next timer is started with
+//!   [`Slot::start_cascade`], this allows building 32 bits timers.
+//! - Drive Direct Sound sample rate (timers 0 and 1 only).
+//!
+//! To use a timer, reserve it with [`ConsoleState::reserve_timer`].
+//! Since subsystems may need specific timers, for example, audio requires
+//! timers 0 or 1, and a cascade requires two consecutive timers, you reserve
+//! a specific [`Id`] rather than any free timer.
+//!
+//! Once you are done with the timer, free it with [`ConsoleState::free_timer`].
+use volmatrix::rw::VolAddress;
+
+use crate::interrupt::{self, Source};
+
+#[cfg(doc)]
+use crate::exec::ConsoleState;
+
+const TIMER_ADDR_USIZE: usize = 0x0400_0100;
+const TIMER_STRIDE: usize = 4;
+
+const CASCADE: u16 = 1 << 2;
+const IRQ: u16 = 1 << 6;
+const ENABLE: u16 = 1 << 7;
+
+/// A hardware timer.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+#[repr(u16)]
+pub enum Id {
+    _0 = 0,
+    _1 = 1,
+    _2 = 2,
+    _3 = 3,
+}
+impl Id {
+    const fn interrupt(self) -> Source {
+        match self {
+            Self::_0 => Source::Timer0,
+            Self::_1 => Source::Timer1,
+            Self::_2 => Source::Timer2,
+            Self::_3 => Source::Timer3,
+        }
+    }
+    const fn counter_register(self) -> VolAddress<u16> {
+        let address = TIMER_ADDR_USIZE + self as usize * TIMER_STRIDE;
+        // SAFETY: TMxCNT_L, reading returns the counter, writing sets the reload value.
+        unsafe { VolAddress::new(address) }
+    }
+    const fn control_register(self) -> VolAddress<u16> {
+        let address = TIMER_ADDR_USIZE + self as usize * TIMER_STRIDE + 2;
+        // SAFETY: TMxCNT_H is a read/write 16 bits register.
+        unsafe { VolAddress::new(address) }
+    }
+}
+
+/// The counters of all timers, the one of timer 0 in the lowest bits.
+///
+/// Used as a source of entropy by [`ConsoleState::seed_from_entropy`].
+pub(crate) fn counters() -> u64 {
+    [Id::_0, Id::_1, Id::_2, Id::_3]
+        .iter()
+        .fold(0, |counters, id| {
+            counters | (u64::from(id.counter_register().read()) << (*id as u64 * 16))
+        })
+}
+
+/// How many CPU cycles it takes for the timer to increment.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+#[repr(u16)]
+pub enum Prescaler {
+    /// 16.78 MHz, overflows every 3.9 ms.
+    Cycles1 = 0,
+    /// 262.2 KHz, overflows every 250 ms.
+    Cycles64 = 1,
+    /// 65.5 KHz, overflows every second.
+    Cycles256 = 2,
+    /// 16.4 KHz, overflows every 4 seconds.
+    Cycles1024 = 3,
+}
+impl Prescaler {
+    /// How many CPU cycles per timer increment.
+    #[must_use]
+    pub const fn cycles(self) -> u32 {
+        match self {
+            Self::Cycles1 => 1,
+            Self::Cycles64 => 64,
+            Self::Cycles256 => 256,
+            Self::Cycles1024 => 1024,
+        }
+    }
+}
+
+/// A reserved hardware timer.
+///
+/// Get one with [`ConsoleState::reserve_timer`], and make sure to free it
+/// with [`ConsoleState::free_timer`], otherwise, the timer will forever be leaked.
+///
+/// See [module-level doc](self) for details.
+#[derive(Debug)]
+pub struct Slot(Id);
+impl Slot {
+    /// # Safety
+    /// There must be no other `Slot` with the same `id`.
+    pub(crate) const unsafe fn new_unchecked(id: Id) -> Self {
+        Self(id)
+    }
+    /// Which timer this is.
+    #[must_use]
+    pub const fn id(&self) -> Id {
+        self.0
+    }
+    /// Set the value the counter restarts from when overflowing or started.
+    ///
+    /// To get an overflow every `n` increments, use `0_u16.wrapping_sub(n)`.
+    /// If the timer is running, this only takes effect at the next overflow.
+    pub fn set_reload(&mut self, reload: u16) {
+        self.0.counter_register().write(reload);
+    }
+    /// The current counter value.
+    #[must_use]
+    pub fn counter(&self) -> u16 {
+        self.0.counter_register().read()
+    }
+    /// Start counting every `prescaler` cycles, from the reload value.
+    ///
+    /// Restarts the timer if it was already running.
+    pub fn start(&mut self, prescaler: Prescaler) {
+        self.restart(prescaler as u16);
+    }
+    /// Start counting overflows of the previous timer, from the reload value.
+    ///
+    /// Does nothing for [`Id::_0`], since there is no previous timer.
+    pub fn start_cascade(&mut self) {
+        if self.0 == Id::_0 {
+            crate::error!("Timer 0 can't cascade, not starting it");
+            return;
+        }
+        self.restart(CASCADE);
+    }
+    /// Stop counting, the counter keeps its current value.
+    pub fn stop(&mut self) {
+        let register = self.0.control_register();
+        register.write(register.read() & !ENABLE);
+    }
+    #[must_use]
+    pub fn is_running(&self) -> bool {
+        self.0.control_register().read() & ENABLE != 0
+    }
+    /// Call `handler` when the timer overflows, or stop calling it if `None`.
+    ///
+    /// This sets the handler and enables the interrupt for this timer,
+    /// see [`interrupt`] for limitations on handlers.
+    pub fn set_overflow_irq(&mut self, handler: Option<interrupt::Handler>) {
+        let source = self.0.interrupt();
+        let register = self.0.control_register();
+        if handler.is_some() {
+            interrupt::set_handler(source, handler);
+            interrupt::enable(source);
+            register.write(register.read() | IRQ);
+        } else {
+            register.write(register.read() & !IRQ);
+            interrupt::disable(source);
+            interrupt::set_handler(source, None);
+        }
+    }
+    /// Stop the timer and its interrupt, in preparation to free it.
+    pub(crate) fn reset(&mut self) {
+        self.set_overflow_irq(None);
+        self.0.control_register().write(0);
+    }
+    fn restart(&mut self, mode: u16) {
+        let register = self.0.control_register();
+        let irq = register.read() & IRQ;
+        // The reload value is only loaded into the counter when ENABLE
+        // goes from 0 to 1.
+        register.write(0);
+        register.write(mode | irq | ENABLE);
+    }
+}
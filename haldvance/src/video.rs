@@ -6,14 +6,13 @@
 // TODO: consider replacing the enum { _1, _2 ... } by a macro.
 // TODO: consider having a const_generic for the textmode tile map width,
 //       so that checks and computations are done at compile time.
-// TODO: consider using a "video command" buffer, so that methods on
-//       `Control` can be called anytime, but will be submitted guarentee at
-//       vblank with minimal memory moving.
 
 pub mod colmod;
+pub mod command;
 pub mod mode;
 pub mod object;
 pub mod palette;
+pub mod scanline;
 mod structs;
 pub mod tile;
 
@@ -21,6 +20,7 @@ use core::hint::unreachable_unchecked;
 use core::marker::PhantomData;
 use core::mem;
 
+use const_default::ConstDefault;
 use gba::mmio_addresses::DISPCNT;
 use gba::mmio_types::DisplayControl;
 use volmatrix::VolMemcopy;
@@ -70,12 +70,13 @@ pub use tile::Tile;
 pub struct Control<M: Mode> {
     _t: PhantomData<fn() -> M>,
     inner: (),
+    objects: object::Shadow,
 }
 
 /// General `Control` methods available in all [`Mode`]s.
 impl<M: Mode> Control<M> {
-    const fn new() -> Self {
-        Self { _t: PhantomData, inner: () }
+    const fn new(objects: object::Shadow) -> Self {
+        Self { _t: PhantomData, inner: (), objects }
     }
 
     /// Create an instance of `Control`.
@@ -93,7 +94,7 @@ impl<M: Mode> Control<M> {
     /// reference model.
     #[must_use]
     pub const unsafe fn init() -> Control<mode::Text> {
-        Control::<mode::Text>::new()
+        Control::<mode::Text>::new(object::Shadow::DEFAULT)
     }
 
     // TODO: Consider doing something similar to TextLayerHandle::commit
@@ -106,7 +107,7 @@ impl<M: Mode> Control<M> {
     pub fn enter_mode<N: Mode>(self) -> Control<N> {
         let old_settings = DISPCNT.read();
         DISPCNT.write(old_settings.with_display_mode(N::TYPE as u16));
-        Control::new()
+        Control::new(self.objects)
     }
 
     pub fn enable_layer(&mut self, layer: Layer<M>) {
@@ -155,6 +156,20 @@ impl<M: Mode> Control<M> {
     pub fn object<'a>(&'a mut self, slot: &object::Slot) -> object::Handle<'a> {
         object::Handle::new(self, slot)
     }
+    /// Whether to sort objects by priority and vertical position when
+    /// uploading them to video memory.
+    ///
+    /// Objects are drawn in front of objects with a higher OAM index.
+    /// When sorting, objects with lower [`Priority`], then objects lower
+    /// on screen, are given lower OAM indices. This is independent of the
+    /// [`object::Slot`] they use. Sorting is disabled by default.
+    pub fn set_object_sorting(&mut self, sort: bool) {
+        self.objects.set_sorting(sort);
+    }
+    /// Upload the RAM copy of OAM to video memory.
+    pub(crate) fn upload_objects(&mut self) {
+        self.objects.upload();
+    }
     // TODO: method for palette::Bank type, since this is what I use for objects
     // in gssa
     /// Load a palette to the object palette memory.
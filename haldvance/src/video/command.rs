@@ -0,0 +1,216 @@
+//! Deferred video commands, see [`Buffer`].
+//!
+//! Video memory should only be written to during vblank, which is why
+//! [`video::Control`] is only available in the `*_draw` methods of
+//! [`GameState`]. A [`Buffer`] lets you queue video operations at any
+//! time, typically in [`GameState::logic`], they are then all executed by
+//! [`crate::exec::full_game`] at the start of the next vblank, right before
+//! the `*_draw` method is called. Object commands are applied to the RAM
+//! copy of OAM right before vblank, so that they are uploaded in the same
+//! vblank as the other commands are executed.
+//!
+//! The command buffer is accessible as [`ConsoleState::commands`].
+//!
+//! Commands are applied regardless of the current video [`Mode`], for example,
+//! a [`Command::TextOffset`] while in [`mode::Affine`] will write to the text
+//! layer offset registers, which is harmless, but has no visible effect.
+use arrayvec::ArrayVec;
+use const_default::ConstDefault;
+use volmatrix::VolMemcopy;
+
+use crate::video::{
+    self, object,
+    object::sprite,
+    palette,
+    tile::{layer, map, sbb, Color, BG_PALRAM, OBJ_PALRAM},
+    Mode, Pos, Priority, Tile,
+};
+
+#[cfg(doc)]
+use crate::{
+    exec::{ConsoleState, GameState},
+    video::mode,
+};
+
+/// How many commands can be queued in a single frame.
+///
+/// Games should make sure they never queue more than this in a frame,
+/// for example with a `const` assert on their own worst case.
+pub const CAPACITY: usize = 128;
+
+/// A change to the attributes of an object, see [`object::Handle`] for
+/// what each of those does.
+#[derive(Clone, Copy)]
+pub enum Object {
+    Pos(Pos),
+    Visible(bool),
+    Sprite(sprite::Slot),
+    Shape(object::Shape),
+    Priority(Priority),
+    Mode(object::Mode),
+    Mosaic(bool),
+    PaletteMode(palette::Type),
+}
+
+/// A single deferred video operation.
+///
+/// Prefer using the methods on [`Buffer`] to queue commands.
+#[derive(Clone, Copy)]
+pub enum Command {
+    /// Change an attribute of the object in slot `slot`.
+    Object { slot: u32, change: Object },
+    /// Set the offset of a text layer.
+    TextOffset {
+        layer: layer::text::Slot,
+        x: u16,
+        y: u16,
+    },
+    /// Set the offset of an affine layer (20.8 fixed point).
+    AffineOffset {
+        layer: layer::affine::Slot,
+        x: i32,
+        y: i32,
+    },
+    /// Write a single tile into a text mode tile map.
+    TextTile {
+        sbb: sbb::Slot,
+        size: map::TextSize,
+        pos: Pos,
+        tile: Tile,
+    },
+    /// Load colors into background palette memory at `offset`.
+    Palette {
+        offset: usize,
+        colors: &'static [Color],
+    },
+    /// Load colors into object palette memory at `offset`.
+    ObjectPalette {
+        offset: usize,
+        colors: &'static [Color],
+    },
+}
+
+/// A fixed-capacity queue of [`Command`]s, executed at the next vblank.
+///
+/// See [module-level doc](self) for details.
+pub struct Buffer {
+    commands: ArrayVec<Command, CAPACITY>,
+}
+impl ConstDefault for Buffer {
+    const DEFAULT: Self = Self { commands: ArrayVec::new_const() };
+}
+impl Buffer {
+    /// Queue `command`.
+    ///
+    /// # Panics
+    ///
+    /// If [`CAPACITY`] commands are already queued. Dropping a command
+    /// would leave the screen in an inconsistent state, for example an
+    /// object that should have been hidden.
+    pub fn push(&mut self, command: Command) {
+        if self.commands.try_push(command).is_err() {
+            panic!("Video command buffer full, more than {CAPACITY} commands this frame");
+        }
+    }
+    /// Queue a change to the object in `slot`.
+    pub fn object(&mut self, slot: &object::Slot, change: Object) {
+        self.push(Command::Object { slot: slot.get(), change })
+    }
+    /// Queue a text layer offset change.
+    pub fn text_offset(&mut self, layer: layer::text::Slot, x: u16, y: u16) {
+        self.push(Command::TextOffset { layer, x, y })
+    }
+    /// Queue an affine layer offset change.
+    pub fn affine_offset(&mut self, layer: layer::affine::Slot, x: i32, y: i32) {
+        self.push(Command::AffineOffset { layer, x, y })
+    }
+    /// Queue writing `tile` at `pos` in the text mode tile map `sbb`.
+    pub fn text_tile(&mut self, sbb: sbb::Slot, size: map::TextSize, pos: Pos, tile: Tile) {
+        self.push(Command::TextTile { sbb, size, pos, tile })
+    }
+    /// Queue loading a background palette.
+    pub fn load_palette(&mut self, offset: usize, colors: &'static [Color]) {
+        self.push(Command::Palette { offset, colors })
+    }
+    /// Queue loading an object palette.
+    pub fn load_object_palette(&mut self, offset: usize, colors: &'static [Color]) {
+        self.push(Command::ObjectPalette { offset, colors })
+    }
+    /// How many commands are currently queued.
+    #[must_use]
+    pub fn len(&self) -> usize {
+        self.commands.len()
+    }
+    #[must_use]
+    pub fn is_empty(&self) -> bool {
+        self.commands.is_empty()
+    }
+    /// Drop all queued commands without executing them.
+    pub fn clear(&mut self) {
+        self.commands.clear();
+    }
+}
+
+impl Object {
+    fn apply(self, handle: &mut object::Handle) {
+        match self {
+            Self::Pos(pos) => handle.set_pos(pos),
+            Self::Visible(visible) => handle.set_visible(visible),
+            Self::Sprite(sprite) => handle.set_sprite(sprite),
+            Self::Shape(shape) => handle.set_shape(shape),
+            Self::Priority(priority) => handle.set_priority(priority),
+            Self::Mode(mode) => handle.set_mode(mode),
+            Self::Mosaic(is_mosaic) => handle.set_mosaic(is_mosaic),
+            Self::PaletteMode(kind) => handle.set_palette_mode(kind),
+        }
+    }
+}
+
+impl<M: Mode> video::Control<M> {
+    /// Apply and remove the [`Command::Object`] commands in `buffer`.
+    ///
+    /// Those only change the RAM copy of OAM, so unlike [`Self::execute`],
+    /// this can be called outside of vblank.
+    pub(crate) fn execute_objects(&mut self, buffer: &mut Buffer) {
+        buffer.commands.retain(|command| match *command {
+            Command::Object { slot, change } => {
+                // SAFETY: `slot` was read from an existing `object::Slot`
+                let slot = unsafe { object::Slot::new_unchecked(slot) };
+                change.apply(&mut self.object(&slot));
+                false
+            }
+            _ => true,
+        });
+    }
+    /// Execute and empty all commands in `buffer`.
+    pub(crate) fn execute(&mut self, buffer: &mut Buffer) {
+        for command in buffer.commands.drain(..) {
+            match command {
+                Command::Object { slot, change } => {
+                    // SAFETY: `slot` was read from an existing `object::Slot`
+                    let slot = unsafe { object::Slot::new_unchecked(slot) };
+                    change.apply(&mut self.object(&slot));
+                }
+                Command::TextOffset { layer, x, y } => {
+                    let mut layer = layer::Handle::<video::mode::Text>::new(self, layer);
+                    layer.set_x_offset(x);
+                    layer.set_y_offset(y);
+                }
+                Command::AffineOffset { layer, x, y } => {
+                    let mut layer = layer::Handle::<video::mode::Affine>::new(self, layer);
+                    layer.set_x_offset(x);
+                    layer.set_y_offset(y);
+                }
+                Command::TextTile { sbb, size, pos, tile } => {
+                    sbb.text_handle(size, self).set_tile(tile, pos);
+                }
+                Command::Palette { offset, colors } => {
+                    BG_PALRAM.write_slice_at_offset(offset, colors);
+                }
+                Command::ObjectPalette { offset, colors } => {
+                    OBJ_PALRAM.write_slice_at_offset(offset, colors);
+                }
+            }
+        }
+    }
+}
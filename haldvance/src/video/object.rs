@@ -45,9 +45,12 @@ use core::mem;
 use const_default::ConstDefault;
 use gba::mmio_types::{ObjAttr0, ObjAttr1, ObjAttr2};
 use utils::{Bitset128, Blocks};
-use volmatrix::rw::{VolAddress, VolBlock};
+use volmatrix::rw::VolBlock;
 
-use crate::video::{self, palette, Pos, Priority};
+use crate::{
+    dma,
+    video::{self, palette, Pos, Priority},
+};
 
 #[cfg(doc)]
 use crate::exec::ConsoleState;
@@ -176,6 +179,123 @@ struct Attributes {
     attr1: ObjAttr1,
     attr2: ObjAttr2,
 }
+impl Attributes {
+    const HIDDEN: Self = Self {
+        attr0: ObjAttr0::new().with_double_disabled(true),
+        attr1: ObjAttr1::new(),
+        attr2: ObjAttr2::new(),
+    };
+    /// Key to sort objects by, see [`video::Control::set_object_sorting`].
+    fn sort_key(&self) -> u16 {
+        let priority = self.attr2.priority() & 0b11;
+        let from_bottom = 0xFF - (self.attr0.y_pos() & 0xFF);
+        priority << 8 | from_bottom
+    }
+}
+
+/// A single OAM entry.
+///
+/// NOTE: The offset for object attribute is of 4 u16, despite attributes being
+/// 3 u16s. They have padding, the padding itself being used for rot/scale
+/// parameters. (see  LCD OBJ - OAM Rotation/Scaling Parameters section
+/// of GBATEK)
+#[derive(Clone, Copy)]
+#[repr(C, align(4))]
+struct Entry {
+    attributes: Attributes,
+    affine_param: u16,
+}
+impl Entry {
+    const HIDDEN: Self = Self {
+        attributes: Attributes::HIDDEN,
+        affine_param: 0,
+    };
+}
+
+/// A RAM copy of the whole OAM, owned by [`video::Control`].
+///
+/// [`Handle`]s edit this copy, which is uploaded with a single DMA transfer
+/// to OAM once per frame by [`crate::exec::full_game`], right after vblank
+/// starts.
+pub(crate) struct Shadow {
+    entries: [Entry; OBJ_COUNT],
+    /// OAM index order, only used when `sort` is true.
+    order: [u8; OBJ_COUNT],
+    /// The attributes of `entries` in `order`, uploaded when `sort` is true.
+    ///
+    /// Affine parameters are not objects attributes, they stay at their index.
+    sorted: [Entry; OBJ_COUNT],
+    sort: bool,
+}
+impl ConstDefault for Shadow {
+    // allow: OBJ_COUNT is 128, all indices fit in a u8.
+    #[allow(clippy::cast_possible_truncation)]
+    const DEFAULT: Self = {
+        let mut order = [0; OBJ_COUNT];
+        let mut i = 0;
+        while i < OBJ_COUNT {
+            order[i] = i as u8;
+            i += 1;
+        }
+        Self {
+            entries: [Entry::HIDDEN; OBJ_COUNT],
+            order,
+            sorted: [Entry::HIDDEN; OBJ_COUNT],
+            sort: false,
+        }
+    };
+}
+impl Shadow {
+    pub(crate) fn set_sorting(&mut self, sort: bool) {
+        self.sort = sort;
+    }
+    /// Insertion sort of `self.order`, objects rarely change order between
+    /// frames, so this is close to linear.
+    fn sort_order(&mut self) {
+        let entries = &self.entries;
+        let key = |index: u8| entries[index as usize].attributes.sort_key();
+        for i in 1..OBJ_COUNT {
+            let current = self.order[i];
+            let current_key = key(current);
+            let mut j = i;
+            while j > 0 && key(self.order[j - 1]) > current_key {
+                self.order[j] = self.order[j - 1];
+                j -= 1;
+            }
+            self.order[j] = current;
+        }
+    }
+    /// Upload the shadow OAM to video memory.
+    pub(crate) fn upload(&mut self) {
+        if self.sort {
+            self.sort_order();
+            for (entry, index) in self.sorted.iter_mut().zip(self.order) {
+                entry.attributes = self.entries[index as usize].attributes;
+            }
+            upload_entries(&self.sorted);
+        } else {
+            upload_entries(&self.entries);
+        }
+    }
+    fn attributes(&mut self, slot: &Slot) -> &mut Attributes {
+        // SAFETY: `slot.0` is by definition lower than Slot::MAX_BLOCKS,
+        // which is the size of `entries`.
+        unsafe { &mut self.entries.get_unchecked_mut(slot.0 as usize).attributes }
+    }
+}
+
+/// Copy the whole `entries` into OAM using DMA3.
+fn upload_entries(entries: &[Entry; OBJ_COUNT]) {
+    let word_count = mem::size_of_val(entries) / mem::size_of::<u32>();
+    // SAFETY: `Entry` is 4 bytes aligned, and `entries` is exactly the size of OAM.
+    unsafe {
+        dma::copy_words(
+            entries.as_ptr().cast(),
+            OBJ_ADDR_USIZE as *mut u32,
+            word_count,
+        )
+    };
+}
 
 /// An object slot.
 ///
@@ -200,21 +320,12 @@ impl Slot {
         Self(inner)
     }
 
-    const fn register(&self) -> VolAddress<Attributes> {
-        // SAFETY: `self.objects` is by definition lower than Self::MAX_BLOCKS,
-        // which is the size of OBJ_ARRAY, meaning that `.get` returns always a `Some`.
-        //
-        // NOTE: The offset for object attribute is of 4 u16, despite attributes being
-        // 3 u16s. They have padding, the padding itself being used for rot/scale
-        // parameters. (see  LCD OBJ - OAM Rotation/Scaling Parameters section
-        // of GBATEK)
-        let offset = mem::size_of::<[u16; 4]>() * self.0 as usize;
-        unsafe { VolAddress::new(OBJ_ADDR_USIZE + offset) }
+    /// The index of this slot in OAM.
+    pub(crate) const fn get(&self) -> u32 {
+        self.0
     }
 }
 
-// TODO: reduce memory operations. (probably impossible to outperform
-// memory load/store, unless I manage a compression scheme)
 /// Game object video operations.
 ///
 /// An "object" is a sprite on screen that can move independently from the
@@ -222,23 +333,16 @@ impl Slot {
 /// objects on screen at the same time.
 ///
 /// To get an `object::Handle`, use [`video::Control::object`].
-/// Note that the changes are only effective when the handle is dropped,
-/// to avoid extraneous memory reads/writes.
+/// Changes are written to a RAM copy of OAM, which is uploaded to video
+/// memory once per frame, at the start of vblank.
 ///
 /// See [`self`] module doc for how to use objects.
 pub struct Handle<'a> {
-    value: Attributes,
-    register: VolAddress<Attributes>,
-    _ctrl: &'a mut (),
+    value: &'a mut Attributes,
 }
 impl<'a> Handle<'a> {
-    pub(super) fn new<N: video::Mode>(ctrl: &'a mut video::Control<N>, bg: &Slot) -> Self {
-        let register = bg.register();
-        Self {
-            _ctrl: ctrl.erased(),
-            value: register.read(),
-            register,
-        }
+    pub(super) fn new<N: video::Mode>(ctrl: &'a mut video::Control<N>, slot: &Slot) -> Self {
+        Self { value: ctrl.objects.attributes(slot) }
     }
     /// Set `x` and `y` coordinate of object.
     pub fn set_pos(&mut self, pos: Pos) {
@@ -255,7 +359,7 @@ impl<'a> Handle<'a> {
     }
     /// Set the object size.
     pub fn set_shape(&mut self, shape: Shape) {
-        shape.set_attributes(&mut self.value);
+        shape.set_attributes(self.value);
     }
     pub fn set_visible(&mut self, visible: bool) {
         self.value.attr0.set_double_disabled(!visible);
@@ -283,16 +387,6 @@ impl<'a> Handle<'a> {
         // TODO: the method in rust-console/gba is just wrongly named
         self.value.attr0.set_use_palbank(!use_palbank);
     }
-    /// Execute changes specified in this handle.
-    pub fn commit(&mut self) {
-        self.register.write(self.value);
-    }
-}
-impl<'a> Drop for Handle<'a> {
-    /// Commit all changes to video memory.
-    fn drop(&mut self) {
-        self.commit();
-    }
 }
 
 // TODO: drop impl on Slot that updates this probably.
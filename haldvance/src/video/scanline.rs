@@ -0,0 +1,293 @@
+//! Per-scanline video effects, see [`Effects`].
+//!
+//! The GBA draws the screen line by line, with a short horizontal blank
+//! (hblank) between each line. Changing video registers during hblank
+//! lets you change how each line is drawn: gradients by changing a palette
+//! color, wavy backgrounds and parallax scrolling by changing layer offsets,
+//! non-rectangular windows by changing window bounds etc.
+//!
+//! An [`Effect`] is a table of [`LINES`] values written to a [`Target`]
+//! register, one value per line. Up to [`MAX_EFFECTS`] effects can be active
+//! at the same time, set them in [`ConsoleState::scanline`]. The changes are
+//! applied by [`crate::exec::full_game`] at the next vblank, and are then
+//! repeated every frame until changed or removed.
+//!
+//! # Implementation
+//!
+//! Effects are applied by a [`Source::HBlank`] interrupt handler, which is
+//! only enabled when at least one effect is active. This means you shouldn't
+//! set your own [`Source::HBlank`] handler while scanline effects are in use.
+//!
+//! Hblank is very short (about 270 cycles), so the more effects are active,
+//! the more likely the last ones are to be applied late, after the start
+//! of the next line.
+use core::{
+    cell::UnsafeCell,
+    ops::{Deref, DerefMut},
+};
+
+use const_default::ConstDefault;
+use gba::mmio_addresses::VCOUNT;
+use volmatrix::{Safe, VolAddress};
+
+use crate::{
+    interrupt::{self, Source},
+    video::tile::{
+        layer::{affine, text},
+        Color, BG_PALRAM,
+    },
+};
+
+#[cfg(doc)]
+use crate::exec::ConsoleState;
+
+/// How many lines are visible on screen.
+pub const LINES: usize = 160;
+/// How many [`Effect`]s can be active at the same time.
+pub const MAX_EFFECTS: usize = 4;
+/// The last line of vblank, values for line 0 are written after it.
+const LAST_LINE: u16 = 227;
+
+// SAFETY: WIN0H and WIN1H are write-only 16 bits registers.
+const WIN0H: VolAddress<u16, (), Safe> = unsafe { VolAddress::new(0x0400_0040) };
+const WIN1H: VolAddress<u16, (), Safe> = unsafe { VolAddress::new(0x0400_0042) };
+
+/// One of the two rectangular windows.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Window {
+    _0,
+    _1,
+}
+
+/// An element of an affine layer transform matrix.
+///
+/// See [`video::tile::layer::Handle::set_transform`].
+///
+/// [`video::tile::layer::Handle::set_transform`]: crate::video::tile::layer::Handle::set_transform
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+#[repr(u16)]
+pub enum AffineParam {
+    A = 0,
+    B = 1,
+    C = 2,
+    D = 3,
+}
+
+/// The register an [`Effect`] writes to.
+#[derive(Clone, Copy)]
+pub enum Target {
+    /// Horizontal offset of a text layer.
+    TextXOffset(text::Slot),
+    /// Vertical offset of a text layer.
+    TextYOffset(text::Slot),
+    /// Horizontal offset of an affine layer.
+    ///
+    /// Unlike [`layer::Handle::set_x_offset`], the value is a signed `i16`
+    /// in 8.8 fixed point, not a 20.8 fixed point number. It wraps every
+    /// 256 pixels, which is seamless for overflowing 256×256 layers.
+    ///
+    /// [`layer::Handle::set_x_offset`]: crate::video::tile::layer::Handle::set_x_offset
+    AffineXOffset(affine::Slot),
+    /// Vertical offset of an affine layer, see [`Target::AffineXOffset`].
+    ///
+    /// Note that the vertical offset is the offset of the line itself, so
+    /// the line number must be added to it to get a regular scroll.
+    AffineYOffset(affine::Slot),
+    /// An element of the transform matrix of an affine layer, as a signed
+    /// `i16` in 8.8 fixed point.
+    AffineParam(affine::Slot, AffineParam),
+    /// A color in the background palette.
+    Color(u8),
+    /// The horizontal bounds of a window, `left << 8 | right`.
+    WindowHorizontal(Window),
+}
+impl Target {
+    // allow: values are reinterpreted as signed on purpose, see `Target` doc.
+    #[allow(clippy::cast_possible_wrap)]
+    fn write(self, value: u16) {
+        match self {
+            Self::TextXOffset(slot) => slot.offset_register().0.write(value),
+            Self::TextYOffset(slot) => slot.offset_register().1.write(value),
+            Self::AffineXOffset(slot) => slot.offset_register().0.write(i32::from(value as i16)),
+            Self::AffineYOffset(slot) => slot.offset_register().1.write(i32::from(value as i16)),
+            Self::AffineParam(slot, param) => slot.param_register(param).write(value as i16),
+            Self::Color(index) => BG_PALRAM.index(usize::from(index)).write(Color(value)),
+            Self::WindowHorizontal(Window::_0) => WIN0H.write(value),
+            Self::WindowHorizontal(Window::_1) => WIN1H.write(value),
+        }
+    }
+}
+
+/// A table of values to write to a [`Target`] register, one per line.
+///
+/// See [module-level doc](self) for details.
+#[derive(Clone, Copy)]
+pub struct Effect {
+    target: Target,
+    values: [u16; LINES],
+}
+impl Effect {
+    /// An effect writing `0` to `target` on every line.
+    #[must_use]
+    pub const fn new(target: Target) -> Self {
+        Self { target, values: [0; LINES] }
+    }
+    /// An effect writing `values[line]` to `target` on each line.
+    #[must_use]
+    pub const fn from_values(target: Target, values: [u16; LINES]) -> Self {
+        Self { target, values }
+    }
+    #[must_use]
+    pub const fn target(&self) -> Target {
+        self.target
+    }
+    /// Set the value for `line`, does nothing if `line >= LINES`.
+    pub fn set(&mut self, line: usize, value: u16) {
+        if let Some(line_value) = self.values.get_mut(line) {
+            *line_value = value;
+        }
+    }
+    /// Set the value of all lines.
+    pub fn fill(&mut self, value: u16) {
+        self.values = [value; LINES];
+    }
+    #[must_use]
+    pub const fn values(&self) -> &[u16; LINES] {
+        &self.values
+    }
+    pub fn values_mut(&mut self) -> &mut [u16; LINES] {
+        &mut self.values
+    }
+    fn apply(&self, line: usize) {
+        if let Some(value) = self.values.get(line) {
+            self.target.write(*value);
+        }
+    }
+}
+
+/// An [`Effects`] slot.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+#[repr(u8)]
+pub enum Slot {
+    _0 = 0,
+    _1 = 1,
+    _2 = 2,
+    _3 = 3,
+}
+
+/// A mutable reference to an [`Effect`] in [`Effects`], see [`Effects::get_mut`].
+///
+/// Marks the effect as changed on the first mutable access.
+pub struct EffectMut<'a> {
+    effect: &'a mut Effect,
+    changed: &'a mut u8,
+    slot: Slot,
+}
+impl Deref for EffectMut<'_> {
+    type Target = Effect;
+
+    fn deref(&self) -> &Effect {
+        self.effect
+    }
+}
+impl DerefMut for EffectMut<'_> {
+    fn deref_mut(&mut self) -> &mut Effect {
+        *self.changed |= 1 << self.slot as u8;
+        self.effect
+    }
+}
+
+/// The scanline effects to apply each frame.
+///
+/// Accessible as [`ConsoleState::scanline`], see [module-level doc](self)
+/// for details.
+pub struct Effects {
+    effects: [Option<Effect>; MAX_EFFECTS],
+    /// Bitset of modified slots since last vblank.
+    changed: u8,
+}
+impl ConstDefault for Effects {
+    const DEFAULT: Self = Self { effects: [None; MAX_EFFECTS], changed: 0 };
+}
+impl Effects {
+    /// Set the effect in `slot`, replacing the previous one.
+    pub fn set(&mut self, slot: Slot, effect: Effect) {
+        self.effects[slot as usize] = Some(effect);
+        self.changed |= 1 << slot as u8;
+    }
+    /// Remove the effect in `slot`, returning it.
+    ///
+    /// Note that the register the effect wrote to keeps the last value
+    /// written to it.
+    pub fn remove(&mut self, slot: Slot) -> Option<Effect> {
+        self.changed |= 1 << slot as u8;
+        self.effects[slot as usize].take()
+    }
+    /// Remove all effects.
+    pub fn clear(&mut self) {
+        self.effects = [None; MAX_EFFECTS];
+        self.changed = u8::MAX;
+    }
+    #[must_use]
+    pub const fn get(&self, slot: Slot) -> Option<&Effect> {
+        self.effects[slot as usize].as_ref()
+    }
+    /// Modify the effect in `slot`, if any.
+    ///
+    /// The slot is only uploaded at the next vblank if the returned
+    /// [`EffectMut`] is actually written to.
+    pub fn get_mut(&mut self, slot: Slot) -> Option<EffectMut<'_>> {
+        let effect = self.effects[slot as usize].as_mut()?;
+        Some(EffectMut { effect, changed: &mut self.changed, slot })
+    }
+    /// Make the changes visible to the hblank handler, and enable or
+    /// disable the [`Source::HBlank`] interrupt accordingly.
+    ///
+    /// Should be called in vblank.
+    pub(crate) fn upload(&mut self) {
+        if self.changed == 0 {
+            return;
+        }
+        let changed = self.changed;
+        self.changed = 0;
+        interrupt::free(|| {
+            // SAFETY: interrupts are disabled, so `hblank` can't access
+            // ACTIVE concurrently.
+            let active = unsafe { &mut *ACTIVE.0.get() };
+            let slots = active.iter_mut().zip(&self.effects);
+            for (i, (active, effect)) in slots.enumerate() {
+                if changed & (1 << i) != 0 {
+                    *active = *effect;
+                }
+            }
+        });
+        if self.effects.iter().any(Option::is_some) {
+            interrupt::set_handler(Source::HBlank, Some(hblank));
+            interrupt::enable(Source::HBlank);
+        } else {
+            interrupt::disable(Source::HBlank);
+            interrupt::set_handler(Source::HBlank, None);
+        }
+    }
+}
+
+struct Active(UnsafeCell<[Option<Effect>; MAX_EFFECTS]>);
+// SAFETY: the GBA has a single core, and accesses from outside of the
+// interrupt handler are guarded by `interrupt::free`.
+unsafe impl Sync for Active {}
+
+/// The effects read by `hblank`, copied from [`Effects`] in vblank.
+static ACTIVE: Active = Active(UnsafeCell::new([None; MAX_EFFECTS]));
+
+/// Write the values of the next line.
+#[cfg_attr(not(feature = "host"), link_section = ".iwram.scanline_hblank")]
+fn hblank() {
+    let line = VCOUNT.read();
+    let next_line = if line == LAST_LINE { 0 } else { usize::from(line) + 1 };
+    // SAFETY: this runs in the interrupt handler, where interrupts are
+    // disabled, outside of it, ACTIVE is only accessed with interrupts disabled.
+    let active = unsafe { &*ACTIVE.0.get() };
+    for effect in active.iter().flatten() {
+        effect.apply(next_line);
+    }
+}
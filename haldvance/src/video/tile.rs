@@ -89,7 +89,8 @@ const TILE_IMG_DATA: VolMatrix<u16, CBB_SIZE, CBB_COUNT> =
 // - PALRAM_ADDR_USIZE is non-zero
 // - repr(u16) Color & BG_PALRAM bus size is 16
 // - BG_PALRAM size is 1Kb == 4 * 256
-const BG_PALRAM: VolBlock<Color, PALRAM_SIZE> = unsafe { VolBlock::new(PALRAM_ADDR_USIZE) };
+pub(super) const BG_PALRAM: VolBlock<Color, PALRAM_SIZE> =
+    unsafe { VolBlock::new(PALRAM_ADDR_USIZE) };
 pub(super) const OBJ_PALRAM: VolBlock<Color, PALRAM_SIZE> =
     unsafe { VolBlock::new(PALRAM_ADDR_USIZE + PALRAM_SIZE * mem::size_of::<Color>()) };
 
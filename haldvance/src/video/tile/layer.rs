@@ -59,7 +59,7 @@ pub struct Handle<'a, M: mode::Background> {
     _t: PhantomData<fn() -> M>,
 }
 impl<'a, M: mode::Background> Handle<'a, M> {
-    pub(super) fn new<N: Mode>(ctrl: &'a mut video::Control<N>, bg: M::Slot) -> Self {
+    pub(in crate::video) fn new<N: Mode>(ctrl: &'a mut video::Control<N>, bg: M::Slot) -> Self {
         Self {
             _ctrl: ctrl.erased(),
             value: bg.register().read(),
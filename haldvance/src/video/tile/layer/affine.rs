@@ -5,6 +5,7 @@ use gba::mmio_types::BackgroundControl;
 use volmatrix::rw::VolAddress;
 
 use super::{mode, AffineSize, Handle, WoVolAddress};
+use crate::video::scanline::AffineParam;
 
 #[cfg(doc)]
 use super::*;
@@ -42,7 +43,7 @@ impl super::Slot for Slot {
     }
 }
 impl Slot {
-    const fn offset_register(self) -> (WoVolAddress<i32>, WoVolAddress<i32>) {
+    pub(in crate::video) const fn offset_register(self) -> (WoVolAddress<i32>, WoVolAddress<i32>) {
         let stride = ((self as usize) - 2) * REG_AFFINE_TRS_STRIDE;
         let address = REG_AFFINE_OFFSET_ADDR_USIZE + stride;
         // SAFETY: within the VRAM
@@ -54,6 +55,12 @@ impl Slot {
         let address = REG_AFFINE_BG_PARAMETERS_ADDR_USIZE + stride;
         unsafe { WoVolAddress::new(address) }
     }
+    pub(in crate::video) const fn param_register(self, param: AffineParam) -> WoVolAddress<i16> {
+        let stride = ((self as usize) - 2) * REG_AFFINE_TRS_STRIDE;
+        let address = REG_AFFINE_BG_PARAMETERS_ADDR_USIZE + stride + (param as usize) * 2;
+        // SAFETY: within the VRAM
+        unsafe { WoVolAddress::new(address) }
+    }
 }
 /// [`mode::Affine`] specific layer controls.
 ///
@@ -33,7 +33,7 @@ impl super::Slot for Slot {
     }
 }
 impl Slot {
-    const fn offset_register(self) -> (WoVolAddress<u16>, WoVolAddress<u16>) {
+    pub(in crate::video) const fn offset_register(self) -> (WoVolAddress<u16>, WoVolAddress<u16>) {
         let stride = (self as usize) * 4;
         let address = BG_OFS_ADDR_USIZE + stride;
         // SAFETY: within the VRAM
@@ -24,7 +24,7 @@ use crate::video::{
 pub struct Slot(usize);
 impl Slot {
     /// [`TextHandle`] for a given sbb and screen size.
-    pub(super) const fn text_handle<M: mode::Tile>(
+    pub(in crate::video) const fn text_handle<M: mode::Tile>(
         self,
         size: map::TextSize,
         ctrl: &mut video::Control<M>,
@@ -0,0 +1,29 @@
+#!/usr/bin/env python3
+"""Generate the sound effect samples, as signed 8 bits PCM at 8192 Hz.
+
+Run from this directory: `python3 generate.py`
+"""
+import math
+
+RATE = 8192
+
+
+def envelope(i, length):
+    return (1 - i / length) ** 2
+
+
+def write(name, samples):
+    data = bytes(round(max(-1, min(1, s)) * 127) & 0xFF for s in samples)
+    with open(name, "wb") as file:
+        file.write(data)
+
+
+def pickup():
+    note = RATE // 20
+    for step, frequency in enumerate((660, 880, 1320)):
+        for i in range(note):
+            fade = envelope(i, note * (3 - step))
+            yield 0.7 * fade * math.sin(2 * math.pi * frequency * i / RATE)
+
+
+write("pickup.pcm", pickup())
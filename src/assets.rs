@@ -1,8 +1,9 @@
 //! Embedded game asset definitions.
 
 use gbassets::{image, palette, Cycle, Image, Palette};
+use hal::audio::{psg, Sample};
 use hal::video::{colmod, object, object::sprite, Tileset};
-use hal::{sprite, sprite_sheet, tileset};
+use hal::{sample, sprite, sprite_sheet, tileset};
 
 /// Asset definitions of the main game graphical elements.
 #[allow(non_upper_case_globals, clippy::wildcard_imports)]
@@ -71,6 +72,7 @@ pub(crate) mod space {
         cycle(26..26 + 6, 8),
     );
 
+    #[derive(Clone, Copy)]
     #[repr(u8)]
     pub(crate) enum Ships {
         SmallBlue,
@@ -211,3 +213,26 @@ pub(crate) mod menu {
     // TODO: all the main menu tileset individual images
     pub(crate) const title_card: Image = image!(title_card_offset, title_card_width, 9, 32);
 }
+
+/// Asset definitions of sound effects, samples are generated by
+/// `sounds/generate.py`.
+#[allow(non_upper_case_globals, clippy::wildcard_imports)]
+pub(crate) mod sfx {
+    use super::*;
+
+    use psg::{Channel, Direction, Duty, Effect, Envelope, Frequency, Step, Sweep, Write};
+
+    /// Played when the player fires, on the PSG since it plays often.
+    pub(crate) const shoot: Effect = Effect::new(
+        Channel::Square1,
+        &[
+            Step::new(Write::Sweep(Sweep::new(4, 2, Direction::Decrease))),
+            Step::new(Write::Envelope(
+                Envelope::new(9, Direction::Decrease, 1).with_duty(Duty::Quarter),
+            )),
+            Step::new(Write::Frequency(Frequency::from_hz(1400).restart())).with_wait(9),
+        ],
+    );
+    /// Played when the player picks up an item.
+    pub(crate) const pickup: Sample = sample!("../sounds/pickup.pcm", 8192);
+}
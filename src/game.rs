@@ -2,14 +2,20 @@
 
 use hal::video::tile::sbb;
 
+pub(crate) mod action;
 pub(crate) mod blink;
 pub(crate) mod cursor;
+pub(crate) mod highscore;
+pub(crate) mod initials;
 pub(crate) mod mainmenu;
+pub(crate) mod pause;
 mod posi;
 mod ship;
 pub(crate) mod space;
 pub(crate) mod state;
 
+pub(crate) use initials::Initials;
+pub(crate) use pause::Pause;
 pub(crate) use posi::{Area, Posi};
 use ship::Player;
 pub(crate) use ship::Ship;
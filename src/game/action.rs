@@ -0,0 +1,25 @@
+//! Player actions, and the keys they are bound to.
+
+use hal::input::{action::ActionMap, Key, KeyGroup};
+
+/// Everything the player can do, bound to keys in [`Controls`].
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub(crate) enum Action {
+    Fire,
+    Bomb,
+    Focus,
+    Confirm,
+    Back,
+    Pause,
+}
+
+pub(crate) type Controls = ActionMap<Action, 6>;
+
+pub(crate) const DEFAULT_CONTROLS: Controls = ActionMap::new([
+    (Action::Fire, KeyGroup::of(&[Key::A])),
+    (Action::Bomb, KeyGroup::of(&[Key::B])),
+    (Action::Focus, KeyGroup::of(&[Key::R])),
+    (Action::Confirm, KeyGroup::of(&[Key::A, Key::Start])),
+    (Action::Back, KeyGroup::of(&[Key::B])),
+    (Action::Pause, KeyGroup::of(&[Key::Start])),
+]);
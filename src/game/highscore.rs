@@ -0,0 +1,270 @@
+//! The high score table, kept in the cartridge SRAM, see [`Table`].
+//!
+//! A game ends when the player gives up from the pause screen. If the
+//! [`Score`] makes the table, the player enters their initials on the
+//! [`super::Initials`] screen, and the table is saved. The table is shown
+//! from the main menu.
+use core::{fmt::Write, str};
+
+use arrayvec::{ArrayString, ArrayVec};
+use const_default::ConstDefault;
+use hal::{
+    save::{Backup, Data, Record},
+    video::{
+        tile::{drawable::EmptyRect, map::Rect, sbb},
+        Pos,
+    },
+};
+
+use super::Ship;
+
+/// Count of entries in the [`Table`].
+pub(crate) const TABLE_LEN: usize = 10;
+pub(crate) const INITIALS_LEN: usize = 3;
+/// Letters allowed in initials.
+pub(crate) const ALPHABET: &str = "ABCDEFGHIJKLMNOPQRSTUVWXYZ";
+/// Header of the columns of [`draw_table`].
+pub(crate) const TABLE_HEADER: &str = "    Who   Score Ship    Stg";
+/// Width in tiles of [`draw_table`].
+// allow: the header is short.
+#[allow(clippy::cast_possible_truncation)]
+pub(crate) const TABLE_WIDTH: u16 = TABLE_HEADER.len() as u16;
+/// Height in tiles of [`draw_table`].
+// allow: the table is short.
+#[allow(clippy::cast_possible_truncation)]
+pub(crate) const TABLE_HEIGHT: u16 = TABLE_LEN as u16 + 1;
+/// Scores are capped, so that they fit in the table.
+const MAX_POINTS: u32 = 9_999_999;
+/// Where the [`Table`] is stored in SRAM.
+const RECORD_OFFSET: usize = 0;
+/// Size of an [`Entry`] in [`Table::encode`].
+const ENTRY_LEN: usize = 4 + INITIALS_LEN + 2;
+
+impl Ship {
+    const fn save_id(self) -> u8 {
+        match self {
+            Self::Blank => 0,
+            Self::Spear => 1,
+            Self::Paladin => 2,
+        }
+    }
+    const fn from_save_id(id: u8) -> Option<Self> {
+        match id {
+            0 => Some(Self::Blank),
+            1 => Some(Self::Spear),
+            2 => Some(Self::Paladin),
+            _ => None,
+        }
+    }
+}
+
+/// The result of a game.
+#[derive(Clone, Copy)]
+pub(crate) struct Score {
+    pub(crate) points: u32,
+    pub(crate) ship: Ship,
+    /// The stage reached, starting at 1, one more every minute survived.
+    pub(crate) stage: u8,
+}
+impl Score {
+    pub(crate) const fn new(ship: Ship) -> Self {
+        Self { points: 0, ship, stage: 1 }
+    }
+    pub(crate) fn add(&mut self, points: u32) {
+        self.points = self.points.saturating_add(points).min(MAX_POINTS);
+    }
+}
+
+/// A [`Score`] in the [`Table`].
+#[derive(Clone, Copy)]
+pub(crate) struct Entry {
+    pub(crate) score: Score,
+    /// Uppercase ASCII letters, from [`ALPHABET`].
+    pub(crate) initials: [u8; INITIALS_LEN],
+}
+impl Entry {
+    fn initials(&self) -> &str {
+        match str::from_utf8(&self.initials) {
+            Ok(initials) => initials,
+            Err(_) => "???",
+        }
+    }
+    fn encode(&self, bytes: &mut [u8]) {
+        let Score { points, ship, stage } = self.score;
+        bytes[..4].copy_from_slice(&points.to_le_bytes());
+        bytes[4..7].copy_from_slice(&self.initials);
+        bytes[7] = ship.save_id();
+        bytes[8] = stage;
+    }
+    fn decode(bytes: &[u8]) -> Option<Self> {
+        let points = u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]);
+        let initials = [bytes[4], bytes[5], bytes[6]];
+        let ship = Ship::from_save_id(bytes[7])?;
+        let stage = bytes[8];
+        let valid_initials = initials.iter().all(|c| ALPHABET.as_bytes().contains(c));
+        (valid_initials && stage != 0).then_some(Self {
+            score: Score { points, ship, stage },
+            initials,
+        })
+    }
+}
+
+/// The best scores, highest first, see [module-level doc](self).
+pub(crate) struct Table {
+    entries: ArrayVec<Entry, TABLE_LEN>,
+}
+impl ConstDefault for Table {
+    const DEFAULT: Self = Self { entries: ArrayVec::new_const() };
+}
+impl Table {
+    /// Where a game with `points` would rank, `None` if it doesn't make
+    /// the table.
+    ///
+    /// Ties rank below the existing entries.
+    pub(crate) fn rank(&self, points: u32) -> Option<usize> {
+        if points == 0 {
+            return None;
+        }
+        let entries = &self.entries;
+        let rank = entries
+            .iter()
+            .position(|entry| entry.score.points < points)
+            .unwrap_or(entries.len());
+        (rank < TABLE_LEN).then_some(rank)
+    }
+    /// Insert `entry` at its rank, dropping the last entry if the table
+    /// is full. Returns the rank, `None` if `entry` doesn't make the table.
+    pub(crate) fn insert(&mut self, entry: Entry) -> Option<usize> {
+        let rank = self.rank(entry.score.points)?;
+        if self.entries.is_full() {
+            self.entries.pop();
+        }
+        self.entries.insert(rank, entry);
+        Some(rank)
+    }
+}
+impl Data for Table {
+    const VERSION: u16 = 1;
+    const ENCODED_LEN: usize = 1 + TABLE_LEN * ENTRY_LEN;
+
+    // allow: there are at most TABLE_LEN entries.
+    #[allow(clippy::cast_possible_truncation)]
+    fn encode(&self, bytes: &mut [u8]) {
+        bytes.fill(0);
+        bytes[0] = self.entries.len() as u8;
+        let slots = bytes[1..].chunks_exact_mut(ENTRY_LEN);
+        for (entry, bytes) in self.entries.iter().zip(slots) {
+            entry.encode(bytes);
+        }
+    }
+    fn decode(&mut self, bytes: &[u8]) -> bool {
+        let len = usize::from(bytes[0]);
+        if len > TABLE_LEN {
+            return false;
+        }
+        let mut entries = ArrayVec::new();
+        for bytes in bytes[1..].chunks_exact(ENTRY_LEN).take(len) {
+            match Entry::decode(bytes) {
+                Some(entry) => entries.push(entry),
+                None => return false,
+            }
+        }
+        let sorted = entries
+            .windows(2)
+            .all(|pair| pair[0].score.points >= pair[1].score.points);
+        if sorted {
+            self.entries = entries;
+        }
+        sorted
+    }
+}
+
+fn backup() -> Backup {
+    hal::backup!(Sram)
+}
+
+/// Load the table from SRAM, empty if there is no valid save.
+pub(crate) fn load() -> Table {
+    let mut table = Table::DEFAULT;
+    match Record::new(RECORD_OFFSET).load(&mut backup(), &mut table) {
+        Ok(true) => {}
+        Ok(false) => hal::info!("No saved high scores yet"),
+        Err(err) => hal::error!("Couldn't load the high scores: {err:?}"),
+    }
+    table
+}
+
+/// Save the table in SRAM.
+pub(crate) fn save(table: &Table) {
+    if let Err(err) = Record::new(RECORD_OFFSET).save(&mut backup(), table) {
+        hal::error!("Couldn't save the high scores: {err:?}");
+    }
+}
+
+/// Draw `table` at `pos`, one row per rank, below [`TABLE_HEADER`].
+pub(crate) fn draw_table(table: &Table, pos: Pos, sbb: &mut sbb::TextHandle) {
+    let area = Rect { width: TABLE_WIDTH, height: TABLE_HEIGHT };
+    sbb.clear_tiles(pos, &EmptyRect(area));
+    sbb.set_tiles(pos, &TABLE_HEADER);
+    for (row, rank) in (1..).zip(1..=TABLE_LEN) {
+        let mut line = ArrayString::<{ TABLE_HEADER.len() }>::new();
+        let _ = match table.entries.get(rank - 1) {
+            Some(entry) => {
+                let Score { points, ship, stage } = entry.score;
+                let (initials, ship) = (entry.initials(), ship.name());
+                write!(
+                    line,
+                    "{rank:>2}. {initials} {points:>7} {ship:<7} {stage:>3}"
+                )
+            }
+            None => write!(line, "{rank:>2}. ---"),
+        };
+        sbb.set_tiles(pos + Pos::y(row), &line.as_str());
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use const_default::ConstDefault;
+    use hal::{host::Console, save::Data};
+
+    use super::{load, save, Entry, Score, Table, TABLE_LEN};
+    use crate::game::Ship;
+
+    fn entry(points: u32, initials: &[u8; 3]) -> Entry {
+        let score = Score { points, ship: Ship::Spear, stage: 2 };
+        Entry { score, initials: *initials }
+    }
+
+    #[test]
+    fn ranked_and_saved() {
+        let _console = Console::new();
+        let mut table = load();
+        assert_eq!(table.rank(0), None);
+        assert_eq!(table.insert(entry(50, b"AAA")), Some(0));
+        assert_eq!(table.insert(entry(80, b"BBB")), Some(0));
+        assert_eq!(table.insert(entry(50, b"CCC")), Some(2));
+        for points in (100..).take(TABLE_LEN) {
+            table.insert(entry(points, b"DDD"));
+        }
+        assert_eq!(table.rank(100), None);
+        assert_eq!(table.rank(101), Some(TABLE_LEN - 1));
+        save(&table);
+
+        let loaded = load();
+        let points = |table: &Table| {
+            let entries = table.entries.iter();
+            entries.map(|entry| entry.score.points).collect::<Vec<_>>()
+        };
+        assert_eq!(points(&loaded), points(&table));
+        assert_eq!(loaded.entries[0].initials, *b"DDD");
+
+        let mut bytes = [0; Table::ENCODED_LEN];
+        table.encode(&mut bytes);
+        // The last entry has an invalid initial.
+        bytes[Table::ENCODED_LEN - 5] = b'a';
+        let mut decoded = Table::DEFAULT;
+        assert!(!decoded.decode(&bytes));
+        assert!(decoded.entries.is_empty());
+    }
+}
@@ -0,0 +1,177 @@
+//! The initials entry screen, shown when a game makes the high score table.
+
+use core::fmt::Write;
+
+use arrayvec::ArrayString;
+use const_default::ConstDefault;
+use hal::{
+    exec::ConsoleState,
+    input::Dir,
+    video::{
+        self, mode,
+        tile::{drawable::EmptyRect, map::Rect, sbb},
+        Pos,
+    },
+};
+
+use super::{
+    action::{Action, Controls},
+    cursor::Cursor,
+    highscore::{self, Entry, Score, Table, ALPHABET, INITIALS_LEN},
+    mainmenu,
+    state::Transition,
+};
+use crate::layout;
+
+const INITIALS_SBB: sbb::Slot = sbb::Slot::_19;
+const CURSOR_BLINK_RATE: usize = 1 << 5;
+/// Index in [`ALPHABET`] of the last letter.
+// allow: the alphabet is short.
+#[allow(clippy::cast_possible_truncation)]
+const LAST_LETTER: u8 = ALPHABET.len() as u8 - 1;
+
+/// Enter initials for a [`Score`] making the high score table, then save it.
+pub(crate) struct Initials {
+    score: Score,
+    /// The high score table, loaded when the game ended.
+    table: Table,
+    controls: Controls,
+    /// Index in [`ALPHABET`] of each letter.
+    letters: [u8; INITIALS_LEN],
+    /// The letter being edited.
+    current: usize,
+    letter_pos: [Pos; INITIALS_LEN],
+    score_pos: Pos,
+    /// Whether the letters need to be drawn again.
+    changed: bool,
+    cursor: Cursor<CURSOR_BLINK_RATE>,
+}
+impl Initials {
+    /// Enter initials for `score`, if it makes it into `table`.
+    pub(crate) fn new(score: Score, table: Table, controls: Controls) -> Option<Self> {
+        table.rank(score.points)?;
+        Some(Self {
+            score,
+            table,
+            controls,
+            letters: [0; INITIALS_LEN],
+            current: 0,
+            letter_pos: [Pos::DEFAULT; INITIALS_LEN],
+            score_pos: Pos::DEFAULT,
+            changed: false,
+            cursor: Cursor::DEFAULT,
+        })
+    }
+
+    fn letter(&self, index: usize) -> &'static str {
+        let letter = usize::from(self.letters[index]);
+        &ALPHABET[letter..=letter]
+    }
+
+    pub(crate) fn setup_video(
+        &mut self,
+        console: &ConsoleState,
+        ctrl: &mut video::Control<mode::Text>,
+    ) {
+        mainmenu::setup_menu_video(ctrl, INITIALS_SBB);
+        let screen = Rect { width: 30, height: 20 };
+        ctrl.basic_sbb(INITIALS_SBB)
+            .clear_tiles(Pos::DEFAULT, &EmptyRect(screen));
+        let [first, second, third] = &mut self.letter_pos;
+        let score = &mut self.score_pos;
+        layout! {
+            #[sbb(ctrl.basic_sbb(INITIALS_SBB))]
+            horizontal(
+                space(5),
+                vertical(
+                    space(4),
+                    text("New high score!"),
+                    space(2),
+                    horizontal(text("Score:"), space(1), rect(score, 7 x 1)),
+                    space(2),
+                    text("Enter your initials:"),
+                    space(2),
+                    horizontal(
+                        space(3),
+                        rect(first, 1 x 1),
+                        space(2),
+                        rect(second, 1 x 1),
+                        space(2),
+                        rect(third, 1 x 1),
+                    ),
+                ),
+            )
+        };
+        let mut points = ArrayString::<7>::new();
+        let _ = write!(points, "{}", self.score.points);
+        let mut sbb = ctrl.basic_sbb(INITIALS_SBB);
+        sbb.set_tiles(self.score_pos, &points.as_str());
+        self.draw_letters(&mut sbb);
+        self.select(self.current, console);
+    }
+
+    fn draw_letters(&self, sbb: &mut sbb::TextHandle) {
+        for (index, pos) in self.letter_pos.iter().enumerate() {
+            sbb.set_tiles(*pos, &self.letter(index));
+        }
+    }
+
+    pub(crate) fn text_draw(&self, console: &ConsoleState, ctrl: &mut video::Control<mode::Text>) {
+        let mut sbb = ctrl.basic_sbb(INITIALS_SBB);
+        if self.changed {
+            self.draw_letters(&mut sbb);
+        }
+        self.cursor.draw(console, &mut sbb);
+    }
+
+    fn select(&mut self, current: usize, console: &ConsoleState) {
+        self.current = current;
+        self.cursor
+            .update(self.letter_pos[current] - Pos::x(1), console);
+    }
+
+    pub(crate) fn logic(&mut self, console: &mut ConsoleState) -> Transition {
+        self.changed = false;
+        self.cursor.clear_previous();
+        if self.controls.just_pressed(console.input, Action::Confirm) {
+            if self.current + 1 == INITIALS_LEN {
+                self.save();
+                return Transition::HighScores;
+            }
+            self.select(self.current + 1, console);
+        } else if self.controls.just_pressed(console.input, Action::Back) {
+            self.select(self.current.saturating_sub(1), console);
+        } else if let Some(dir) = console.input.repeated_direction() {
+            let letter = self.letters[self.current];
+            match dir {
+                Dir::Up | Dir::Down => {
+                    self.letters[self.current] = match (dir, letter) {
+                        (Dir::Down, 0) => LAST_LETTER,
+                        (Dir::Down, _) => letter - 1,
+                        (_, LAST_LETTER) => 0,
+                        (_, _) => letter + 1,
+                    };
+                    self.changed = true;
+                }
+                Dir::Left => self.select(self.current.saturating_sub(1), console),
+                Dir::Right => self.select((self.current + 1).min(INITIALS_LEN - 1), console),
+            }
+        }
+        Transition::Stay
+    }
+
+    /// Save the score with the initials entered so far.
+    ///
+    /// Also called on soft reset, so that the score isn't lost.
+    pub(crate) fn save(&mut self) {
+        let initials = self
+            .letters
+            .map(|letter| ALPHABET.as_bytes()[usize::from(letter)]);
+        let entry = Entry { score: self.score, initials };
+        match self.table.insert(entry) {
+            Some(rank) => hal::info!("New high score, rank {}", rank + 1),
+            None => hal::error!("The score doesn't make the high score table"),
+        }
+        highscore::save(&self.table);
+    }
+}
@@ -2,20 +2,31 @@
 mod cycle;
 
 use const_default::ConstDefault;
+use enumflags2::BitFlags;
 use gbassets::Image;
 use hal::{
     exec::ConsoleState,
-    input::{Dir, Key},
+    input::{
+        sequence::{Matcher, Sequence},
+        Dir, Key, KeyGroup,
+    },
     video::{
-        self, mode,
-        tile::{drawable::Windowed, layer, map::Rect, sbb},
-        Pos,
+        self, colmod, mode,
+        tile::{cbb, drawable::Windowed, layer, map::Rect, sbb},
+        Layer, Pos,
     },
 };
 
 use crate::{
     assets,
-    game::{cursor::Cursor, state::Transition, Ship},
+    game::{
+        action::{Action, Controls, DEFAULT_CONTROLS},
+        cursor::Cursor,
+        highscore::{self, TABLE_HEIGHT, TABLE_WIDTH},
+        space::{Cheats, DEFAULT_CHEATS},
+        state::Transition,
+        Ship,
+    },
     layout,
 };
 
@@ -23,12 +34,28 @@ use super::blink::Blink;
 
 const MAIN_MENU_SBB: sbb::Slot = sbb::Slot::_16;
 const SHIP_SELECT_SBB: sbb::Slot = sbb::Slot::_17;
-pub(crate) const TITLE_SCREEN_SBB: sbb::Slot = sbb::Slot::_15;
+const TITLE_SCREEN_SBB: sbb::Slot = sbb::Slot::_15;
+const HIGH_SCORES_SBB: sbb::Slot = sbb::Slot::_18;
 const PRESS_START: &str = "Press A";
 const DESCR_WIDTH: u16 = 21;
 
 const PRESS_START_BLINK_RATE: usize = 1 << 6;
 
+const UP: KeyGroup = KeyGroup::of(&[Key::Dpad(Dir::Up)]);
+const DOWN: KeyGroup = KeyGroup::of(&[Key::Dpad(Dir::Down)]);
+const LEFT: KeyGroup = KeyGroup::of(&[Key::Dpad(Dir::Left)]);
+const RIGHT: KeyGroup = KeyGroup::of(&[Key::Dpad(Dir::Right)]);
+const B: KeyGroup = KeyGroup::of(&[Key::B]);
+const A: KeyGroup = KeyGroup::of(&[Key::A]);
+const KONAMI_CODE: [KeyGroup; 10] = [UP, UP, DOWN, DOWN, LEFT, RIGHT, LEFT, RIGHT, B, A];
+/// Frames allowed between two keys of a title screen code.
+const CODE_TIMEOUT: u16 = 40;
+/// Codes toggling cheats on the title screen.
+const CHEAT_CODES: [(Cheats, Sequence); 1] = [(
+    Cheats::PowerupSpawn,
+    Sequence::new(&KONAMI_CODE, CODE_TIMEOUT),
+)];
+
 impl Ship {
     const fn image(self) -> Image {
         use assets::menu::player_ships;
@@ -38,7 +65,7 @@ impl Ship {
             Self::Paladin => player_ships::paladin,
         }
     }
-    const fn name(self) -> &'static str {
+    pub(super) const fn name(self) -> &'static str {
         match self {
             Self::Blank => "Blank",
             Self::Spear => "Spear",
@@ -65,6 +92,7 @@ crate::cycling_enum! {
     pub(crate) enum MainEntry {
         Start,
         ShipSelect,
+        HighScores,
     }
 }
 impl MainEntry {
@@ -80,9 +108,15 @@ pub(crate) enum Submenu {
     Title,
     Main(MainEntry),
     ShipSelect { highlight: Ship },
+    HighScores,
 }
 pub(crate) struct Mainmenu {
     pub(crate) selected_ship: Ship,
+    /// Cheats to enable in the game, toggled with title screen codes.
+    pub(crate) cheats: BitFlags<Cheats>,
+    cheat_codes: Matcher<Cheats, 1>,
+    /// Key bindings, used in the menu and passed to the game.
+    pub(crate) controls: Controls,
     pub(crate) menu: Submenu,
     pub(crate) data: MainMenuData,
     just_new_screen: bool,
@@ -91,6 +125,9 @@ pub(crate) struct Mainmenu {
 impl ConstDefault for Mainmenu {
     const DEFAULT: Self = Self {
         selected_ship: Ship::Blank,
+        cheats: DEFAULT_CHEATS,
+        cheat_codes: Matcher::new(CHEAT_CODES),
+        controls: DEFAULT_CONTROLS,
         menu: Submenu::Title,
         data: ConstDefault::DEFAULT,
         just_new_screen: true,
@@ -98,11 +135,24 @@ impl ConstDefault for Mainmenu {
     };
 }
 impl Mainmenu {
+    /// The menu showing the high score table.
+    pub(crate) const HIGH_SCORES: Self = Self { menu: Submenu::HighScores, ..Self::DEFAULT };
+
+    /// Load the menu tiles and draw all menu screens in video memory.
+    pub(crate) fn setup_video(&mut self, ctrl: &mut video::Control<mode::Text>) {
+        setup_menu_video(ctrl, TITLE_SCREEN_SBB);
+        init_menu(&mut self.data, ctrl);
+        let mut sbb = ctrl.basic_sbb(HIGH_SCORES_SBB);
+        highscore::draw_table(&highscore::load(), self.data.high_score_table, &mut sbb);
+        self.just_new_screen = true;
+    }
+
     pub(crate) fn draw_new_screen(&self, ctrl: &mut video::Control<mode::Text>) {
         let menu_slot = match self.menu {
             Submenu::Title => TITLE_SCREEN_SBB,
             Submenu::Main { .. } => MAIN_MENU_SBB,
             Submenu::ShipSelect { .. } => SHIP_SELECT_SBB,
+            Submenu::HighScores => HIGH_SCORES_SBB,
         };
         ctrl.layer(layer::text::Slot::_0).set_sbb(menu_slot);
     }
@@ -110,6 +160,7 @@ impl Mainmenu {
     pub(crate) fn text_draw(&self, console: &ConsoleState, ctrl: &mut video::Control<mode::Text>) {
         match &self.menu {
             Submenu::Title => self.data.draw_title_screen(console, ctrl),
+            Submenu::HighScores => {}
             Submenu::ShipSelect { .. } => self
                 .cursor
                 .draw(console, &mut ctrl.basic_sbb(SHIP_SELECT_SBB)),
@@ -126,9 +177,20 @@ impl Mainmenu {
     pub(crate) fn logic(&mut self, console: &mut ConsoleState) -> Transition {
         self.just_new_screen = false;
         self.cursor.clear_previous();
-        if console.input.just_pressed(Key::A) {
+        if let Submenu::Title = self.menu {
+            if let Some(cheat) = self.cheat_codes.update(console.input) {
+                self.cheats.toggle(cheat);
+                hal::info!("Toggled cheat {cheat:?}, active cheats: {:?}", self.cheats);
+                // Codes may end with the Confirm key, don't leave the title screen.
+                return Transition::Stay;
+            }
+        }
+        if self.controls.just_pressed(console.input, Action::Confirm) {
             match self.menu {
                 Submenu::Title => {
+                    if let Some(seed) = console.seed_from_entropy() {
+                        hal::debug!("Seeded the rng with {seed:#x}");
+                    }
                     self.just_new_screen = true;
                     self.menu = Submenu::Main(MainEntry::Start);
                     let cursor_pos = self.data.menu_select.of(MainEntry::Start) - Pos::x(2);
@@ -144,29 +206,33 @@ impl Mainmenu {
                     self.selected_ship = highlight;
                     self.just_new_screen = true;
                 }
+                Submenu::Main(MainEntry::HighScores) => {
+                    self.just_new_screen = true;
+                    self.menu = Submenu::HighScores;
+                }
+                Submenu::HighScores => self.back_to_main(MainEntry::HighScores, console),
                 Submenu::Main(MainEntry::Start) => {
                     return Transition::EnterGame;
                 }
             }
-        } else if console.input.just_pressed(Key::B) {
-            if let Submenu::ShipSelect { .. } = self.menu {
-                self.just_new_screen = true;
-                self.menu = Submenu::Main(MainEntry::ShipSelect);
-                let ship_pos = self.data.menu_select.of(MainEntry::ShipSelect);
-                self.cursor.update(ship_pos - Pos::x(2), console);
+        } else if self.controls.just_pressed(console.input, Action::Back) {
+            match self.menu {
+                Submenu::ShipSelect { .. } => self.back_to_main(MainEntry::ShipSelect, console),
+                Submenu::HighScores => self.back_to_main(MainEntry::HighScores, console),
+                Submenu::Title | Submenu::Main(_) => {}
             }
         } else {
             match &mut self.menu {
-                Submenu::Title => {}
+                Submenu::Title | Submenu::HighScores => {}
                 Submenu::Main(entry) => {
-                    if let Some(dir) = console.input.just_direction() {
+                    if let Some(dir) = console.input.repeated_direction() {
                         *entry = entry.go(dir);
                         let cursor_pos = self.data.menu_select.of(*entry) - Pos::x(2);
                         self.cursor.update(cursor_pos, console);
                     }
                 }
                 Submenu::ShipSelect { highlight } => {
-                    if let Some(dir) = console.input.just_direction() {
+                    if let Some(dir) = console.input.repeated_direction() {
                         *highlight = highlight.go(dir);
                         let cursor_pos = self.data.ship_menu.of(*highlight) - Pos::x(1);
                         self.cursor.update(cursor_pos, console);
@@ -176,6 +242,24 @@ impl Mainmenu {
         }
         Transition::Stay
     }
+
+    fn back_to_main(&mut self, entry: MainEntry, console: &ConsoleState) {
+        self.just_new_screen = true;
+        self.menu = Submenu::Main(entry);
+        let cursor_pos = self.data.menu_select.of(entry) - Pos::x(2);
+        self.cursor.update(cursor_pos, console);
+    }
+}
+
+/// Load the menu tiles and palette, and show `sbb` on the text layer.
+pub(super) fn setup_menu_video(ctrl: &mut video::Control<mode::Text>, sbb: sbb::Slot) {
+    ctrl.reset_display_control();
+    ctrl.load_tileset(cbb::Slot::_0, &assets::menu::set);
+    ctrl.load_palette(assets::menu::palette.get());
+    ctrl.enable_layer(Layer::<mode::Text>::_0);
+    let mut layer = ctrl.layer(layer::text::Slot::_0);
+    layer.set_color_mode::<colmod::Bit8>();
+    layer.set_sbb(sbb);
 }
 
 /// Positions of various elements in the ship selection screen.
@@ -211,12 +295,14 @@ impl ShipMenuPos {
 struct MenuSelectPos {
     start_game: Pos,
     ships: Pos,
+    high_scores: Pos,
 }
 impl MenuSelectPos {
     const fn of(self, entry: MainEntry) -> Pos {
         match entry {
             MainEntry::Start => self.start_game,
             MainEntry::ShipSelect => self.ships,
+            MainEntry::HighScores => self.high_scores,
         }
     }
 }
@@ -225,6 +311,7 @@ pub(crate) struct MainMenuData {
     menu_select: MenuSelectPos,
     ship_menu: ShipMenuPos,
     press_start: Pos,
+    high_score_table: Pos,
 }
 impl MainMenuData {
     fn draw_title_screen(&self, console: &ConsoleState, video: &mut video::Control<mode::Text>) {
@@ -233,13 +320,27 @@ impl MainMenuData {
         blink.draw(self.press_start, console, &mut sbb);
     }
 }
-pub(crate) fn init_menu(data: &mut MainMenuData, ctrl: &mut video::Control<mode::Text>) {
+fn init_menu(data: &mut MainMenuData, ctrl: &mut video::Control<mode::Text>) {
     let MainMenuData {
-        menu_select: MenuSelectPos { start_game, ships },
+        menu_select: MenuSelectPos { start_game, ships, high_scores },
         ship_menu: ShipMenuPos { paladin, spear, blank, image, descr, name },
         press_start,
+        high_score_table,
     } = data;
 
+    layout! {
+        #[sbb(ctrl.basic_sbb(HIGH_SCORES_SBB))]
+        horizontal(
+            space(1),
+            vertical(
+                space(2),
+                horizontal(space(9), text("High Scores")),
+                space(2),
+                rect(high_score_table, TABLE_WIDTH x TABLE_HEIGHT),
+            ),
+        )
+    };
+
     layout! {
         #[sbb(ctrl.basic_sbb(SHIP_SELECT_SBB))]
         horizontal(
@@ -279,6 +380,8 @@ pub(crate) fn init_menu(data: &mut MainMenuData, ctrl: &mut video::Control<mode:
                 select(start_game, "Start Game!!"),
                 space(2),
                 select(ships, "Ship Select"),
+                space(2),
+                select(high_scores, "High Scores"),
             ),
         )
     };
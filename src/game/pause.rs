@@ -0,0 +1,82 @@
+//! The pause screen, on top of [`super::Space`].
+
+use core::mem;
+
+use const_default::ConstDefault;
+use hal::{
+    exec::ConsoleState,
+    input::{Key, KeyGroup},
+    video::{
+        self, colmod, mode,
+        tile::{cbb, drawable::EmptyRect, layer::MixedSlot, map::Rect, sbb},
+        Layer, Pos, Priority,
+    },
+};
+
+use super::{
+    action::{Action, Controls},
+    highscore::Score,
+    state::Transition,
+};
+
+/// Keys to wake up the console after sleeping.
+const WAKE_KEYS: KeyGroup = KeyGroup::of(&[Key::L, Key::R, Key::Select]);
+/// The overlay map, in the last CBB, which [`super::Space`] doesn't use.
+const PAUSE_SBB: sbb::Slot = sbb::Slot::new(24);
+const OVERLAY: &str = "       PAUSED
+
+Start   Resume
+Select  Sleep
+         L+R+Select to wake
+B       Give up";
+
+pub(crate) struct Pause {
+    controls: Controls,
+    /// The score of the paused game, kept if the player gives up.
+    score: Score,
+}
+impl Pause {
+    pub(crate) fn new(controls: Controls, score: Score) -> Self {
+        hal::info!("Paused, press Back to give up the game");
+        Self { controls, score }
+    }
+    pub(crate) const fn controls(&self) -> Controls {
+        self.controls
+    }
+    pub(crate) const fn score(&self) -> Score {
+        self.score
+    }
+    /// Draw the overlay on a text layer, using the in-game UI tiles loaded
+    /// by [`super::Space::setup_video`].
+    ///
+    /// The stars and objects stay visible under it, the planet layer doesn't
+    /// exist in mixed mode and is hidden until the game resumes.
+    pub(crate) fn setup_video(ctrl: &mut video::Control<mode::Mixed>) {
+        let screen = Rect { width: 30, height: 20 };
+        let mut sbb = ctrl.basic_text_sbb(PAUSE_SBB);
+        sbb.clear_tiles(Pos::DEFAULT, &EmptyRect(screen));
+        sbb.set_tiles(Pos { x: 3, y: 6 }, &OVERLAY);
+
+        let mut layer = ctrl.text_layer(MixedSlot::_0);
+        layer.set_color_mode::<colmod::Bit8>();
+        layer.set_cbb(cbb::Slot::_1);
+        layer.set_sbb(PAUSE_SBB);
+        layer.set_priority(Priority::_0);
+        mem::drop(layer);
+        ctrl.enable_layer(Layer::<mode::Mixed>::_0);
+    }
+    pub(crate) fn logic(&mut self, console: &mut ConsoleState) -> Transition {
+        if console.input.just_pressed(Key::Select) {
+            hal::info!("Going to sleep, press L+R+Select to wake up");
+            console.sleep(WAKE_KEYS);
+            Transition::Stay
+        } else if self.controls.just_pressed(console.input, Action::Pause) {
+            Transition::Resume
+        } else if self.controls.just_pressed(console.input, Action::Back) {
+            hal::info!("Gave up with {} points", self.score.points);
+            Transition::GameOver
+        } else {
+            Transition::Stay
+        }
+    }
+}
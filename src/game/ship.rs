@@ -1,14 +1,14 @@
 // TODO: rename this to "player.rs"
 use hal::{
     exec::ConsoleState,
-    input::Key,
-    video::{self, mode, object, palette},
+    video::{self, command, mode, object, palette},
 };
 
 use crate::collide::{Collide, Shape};
 use crate::game::space::Bullet;
 use crate::{assets::players, game::Posi};
 
+use super::action::{Action, Controls};
 use super::space::items;
 
 const INITIAL_PLAYER_POS: Posi = Posi::new(4, 52);
@@ -137,12 +137,19 @@ impl Player {
             slot,
         }
     }
-    pub(crate) fn update(&mut self, console: &mut ConsoleState) -> Option<Bullet> {
+    pub(super) const fn into_slot(self) -> object::Slot {
+        self.slot
+    }
+    pub(crate) fn update(
+        &mut self,
+        console: &mut ConsoleState,
+        controls: &Controls,
+    ) -> Option<Bullet> {
         let input = console.input;
         let frame = console.frame;
         let momentum: Posi = input.current().into();
         self.pos += momentum;
-        if input.pressed(Key::A) && self.next_fire_frame < frame {
+        if controls.pressed(input, Action::Fire) && self.next_fire_frame < frame {
             let slot = console.reserve_object()?;
             self.next_fire_frame = frame + self.weapon.cooldown();
 
@@ -152,10 +159,9 @@ impl Player {
         }
     }
 
-    pub(crate) fn draw(&self, ctrl: &mut video::Control<mode::Affine>) {
-        let mut player = ctrl.object(&self.slot);
+    pub(crate) fn draw(&self, commands: &mut command::Buffer) {
         if let Ok(pos) = self.pos.try_into() {
-            player.set_pos(pos);
+            commands.object(&self.slot, command::Object::Pos(pos));
         }
     }
     pub(super) fn init_video(
@@ -3,7 +3,6 @@ use core::mem;
 use arrayvec::ArrayVec;
 use const_default::ConstDefault;
 use enumflags2::{bitflags, BitFlags};
-use utils::{Bitset128, Bitset8};
 
 mod background;
 mod bullet;
@@ -11,33 +10,76 @@ mod enemy;
 pub(super) mod items;
 
 use hal::{
+    audio,
     exec::ConsoleState,
+    input::{
+        sequence::{Matcher, Sequence},
+        Key, KeyGroup,
+    },
     video::{
-        self, colmod, mode, object, object::sprite, tile::cbb, tile::layer::affine,
-        tile::map::AffineSize, Layer, Priority,
+        self, colmod, command, mode, object, object::sprite, scanline, tile::cbb,
+        tile::layer::affine, tile::map::AffineSize, Layer, Mode, Priority,
     },
 };
 
-use super::{ship::Weapon, state::Transition, Player, Posi, Ship, PLANET_SBB, STAR_SBB};
+use super::{
+    action::{Action, Controls},
+    highscore::Score,
+    ship::Weapon,
+    state::Transition,
+    Player, Posi, Ship, PLANET_SBB, STAR_SBB,
+};
 use crate::assets;
 pub(crate) use bullet::Bullet;
 pub(super) use items::Item;
 
 const MAX_BULLETS: usize = 88;
 const MAX_ITEMS: usize = 5;
+/// Video commands queued by `setup_video` when spawning a bullet or an item.
+const SPAWN_COMMANDS: usize = 4;
+/// The most video commands [`Space::update`] queues in a frame: one per
+/// bullet and item, a bullet and an item spawn, the background offset and
+/// the player position.
+const MAX_FRAME_COMMANDS: usize = MAX_BULLETS + MAX_ITEMS + 2 * SPAWN_COMMANDS + 2;
+const _: () = assert!(
+    MAX_FRAME_COMMANDS <= command::CAPACITY,
+    "Space could overflow the video command buffer",
+);
+const STARS_EFFECT: scanline::Slot = scanline::Slot::_0;
+/// Scroll speed of each row of 8 lines of the star background,
+/// in 1/256th of pixel per frame.
+const STAR_ROW_SPEEDS: [usize; 5] = [2, 4, 1, 3, 6];
+/// Frames played before reaching the next stage.
+///
+/// There are no levels yet, so the stage is how many minutes were survived,
+/// plus one. It makes surviving worth more points over time, and tells apart
+/// games in the high score table.
+const STAGE_FRAMES: u32 = 60 * 60;
+/// Every this many frames, surviving earns as many points as the stage.
+const SURVIVAL_PERIOD: u32 = 1 << 4;
+/// Points earned picking up an item.
+const ITEM_POINTS: u32 = 100;
 
+/// Gameplay cheats, toggled with the [`CHEAT_CODES`] in game, or with
+/// the Konami code on the title screen.
 #[bitflags]
 #[repr(u8)]
-#[derive(Copy, Clone)]
-enum Cheats {
+#[derive(Copy, Clone, Debug)]
+pub(crate) enum Cheats {
     PowerupSpawn,
 }
 
 #[cfg(feature = "cheat-powerups")]
-const DEFAULT_CHEATS: BitFlags<Cheats> = enumflags2::make_bitflags!(Cheats::{PowerupSpawn});
+pub(crate) const DEFAULT_CHEATS: BitFlags<Cheats> =
+    enumflags2::make_bitflags!(Cheats::{PowerupSpawn});
 
 #[cfg(not(feature = "cheat-powerups"))]
-const DEFAULT_CHEATS: BitFlags<Cheats> = BitFlags::EMPTY;
+pub(crate) const DEFAULT_CHEATS: BitFlags<Cheats> = BitFlags::EMPTY;
+
+const POWERUP_CHORD: KeyGroup = KeyGroup::of(&[Key::L, Key::R, Key::Select]);
+/// Key chords toggling cheats while playing.
+const CHEAT_CODES: [(Cheats, Sequence); 1] =
+    [(Cheats::PowerupSpawn, Sequence::new(&[POWERUP_CHORD], 0))];
 
 pub(crate) struct Space {
     player: Player,
@@ -47,21 +89,84 @@ pub(crate) struct Space {
     bullet_sprites: sprite::SheetSlot<14>,
     item_sprites: sprite::SheetSlot<7>,
     ship: Ship,
-    new_bullets: Bitset128,
-    new_items: Bitset8,
     cheats: BitFlags<Cheats>,
+    cheat_codes: Matcher<Cheats, 1>,
+    controls: Controls,
+    score: Score,
+    /// Frames played, not counting the pause screen.
+    frames_played: u32,
 }
 
 impl Space {
+    // allow: GBA's usize is 32, and rust reference says casting from uX to iX is
+    // a no-op. I just can't be harsed to explictly handle it.
+    #[allow(
+        clippy::cast_possible_truncation,
+        clippy::cast_possible_wrap,
+        clippy::cast_sign_loss
+    )]
     pub(crate) fn update(&mut self, console: &mut ConsoleState) -> Transition {
-        self.bullets.iter_mut().for_each(|bullet| {
-            bullet.update(console.frame);
-        });
-        self.items.iter_mut().for_each(|item| {
-            item.update(&mut self.player);
-        });
+        if self.controls.just_pressed(console.input, Action::Pause) {
+            return Transition::Pause;
+        }
+        let frame = console.frame;
+        if let Some(cheat) = self.cheat_codes.update(console.input) {
+            self.cheats.toggle(cheat);
+            hal::info!("Toggled cheat {cheat:?}, active cheats: {:?}", self.cheats);
+        }
+        self.frames_played += 1;
+        if self.frames_played % STAGE_FRAMES == 0 {
+            self.score.stage = self.score.stage.saturating_add(1);
+            hal::info!("Reached stage {}", self.score.stage);
+        }
+        if self.frames_played % SURVIVAL_PERIOD == 0 {
+            self.score.add(u32::from(self.score.stage));
+        }
+        let bullets_start = console.profiler.start();
+        self.bullets = self
+            .bullets
+            .drain(..)
+            .filter_map(|mut bullet| {
+                bullet.update(frame);
+                if bullet.should_die(frame) {
+                    let slot = bullet.into_slot();
+                    console
+                        .commands
+                        .object(&slot, command::Object::Visible(false));
+                    console.free_object(slot);
+                    None
+                } else {
+                    bullet.draw(&mut console.commands);
+                    Some(bullet)
+                }
+            })
+            .collect();
+        console.profiler.record("bullets", bullets_start);
+        self.items = self
+            .items
+            .drain(..)
+            .filter_map(|mut item| {
+                if item.update(&mut self.player) {
+                    self.score.add(ITEM_POINTS);
+                    console
+                        .audio
+                        .play(assets::sfx::pickup, audio::Params::DEFAULT);
+                }
+                if item.should_die() {
+                    let slot = item.into_slot();
+                    console
+                        .commands
+                        .object(&slot, command::Object::Visible(false));
+                    console.free_object(slot);
+                    None
+                } else {
+                    item.draw(&mut console.commands);
+                    Some(item)
+                }
+            })
+            .collect();
         if self.cheats.contains(Cheats::PowerupSpawn) {
-            let mut random = console.rng.u64();
+            let mut random = console.rng.gameplay.u64();
             let should_spawn = (random & 127) == 0;
             if should_spawn {
                 random >>= 7;
@@ -80,89 +185,43 @@ impl Space {
 
                 if let Some(item_slot) = console.reserve_object() {
                     let new_item = Item::new(item_slot, position, kind);
-                    hal::info!("Spawning a new item: {new_item:?}");
-                    match self.items.try_push(new_item) {
-                        Ok(()) => {
-                            self.new_items.reserve((self.items.len() - 1) as u32);
-                        }
-                        Err(_) => {
-                            hal::error!("Couldn't spawn an item, too many already on screen!");
-                        }
+                    hal::debug!("Spawning a new item: {new_item:?}");
+                    if self.items.is_full() {
+                        hal::error!("Couldn't spawn an item, too many already on screen!");
+                        console.free_object(new_item.into_slot());
+                    } else {
+                        new_item.setup_video(&self.item_sprites, &mut console.commands);
+                        self.items.push(new_item);
                     }
                 }
             }
         }
-        if let Some(new_bullet) = self.player.update(console) {
-            match self.bullets.try_push(new_bullet) {
-                Ok(()) => {
-                    self.new_bullets.reserve((self.bullets.len() - 1) as u32);
-                }
-                Err(_) => {
-                    hal::error!("Couldn't spawn a bullet, too many already on screen!");
-                }
+        if let Some(new_bullet) = self.player.update(console, &self.controls) {
+            console.psg.play(assets::sfx::shoot);
+            if self.bullets.is_full() {
+                hal::error!("Couldn't spawn a bullet, too many already on screen!");
+                console.free_object(new_bullet.into_slot());
+            } else {
+                new_bullet.setup_video(&self.bullet_sprites, &mut console.commands);
+                self.bullets.push(new_bullet);
+            }
+        }
+        if let Some(mut stars) = console.scanline.get_mut(STARS_EFFECT) {
+            for (line, offset) in stars.values_mut().iter_mut().enumerate() {
+                let speed = STAR_ROW_SPEEDS[(line / 8) % STAR_ROW_SPEEDS.len()];
+                *offset = frame.wrapping_mul(speed) as u16;
             }
         }
+        let commands = &mut console.commands;
+        commands.affine_offset(affine::Slot::_3, (frame as i32) * 5, 0);
+        self.player.draw(commands);
         Transition::Stay
     }
 
-    // allow: GBA's usize is 32, and rust reference says casting from uX to iX is
-    // a no-op. I just can't be harsed to explictly handle it.
-    #[allow(clippy::cast_possible_truncation, clippy::cast_possible_wrap)]
-    pub(crate) fn affine_draw(
-        &mut self,
-        console: &mut ConsoleState,
-        ctrl: &mut video::Control<mode::Affine>,
-    ) {
-        self.bullets = self
-            .bullets
-            .drain(..)
-            .enumerate()
-            .filter_map(|(i, bullet)| match () {
-                () if bullet.should_die(console.frame) => {
-                    let slot = bullet.into_slot();
-                    ctrl.object(&slot).set_visible(false);
-                    console.free_object(slot);
-                    None
-                }
-                () if !self.new_bullets.free(i as u32) => {
-                    bullet.setup_video(&self.bullet_sprites, ctrl);
-                    Some(bullet)
-                }
-                () => Some(bullet),
-            })
-            .collect();
-        self.items = self
-            .items
-            .drain(..)
-            .enumerate()
-            .filter_map(|(i, item)| match () {
-                () if item.should_die() => {
-                    let slot = item.into_slot();
-                    ctrl.object(&slot).set_visible(false);
-                    console.free_object(slot);
-                    None
-                }
-                () if !self.new_items.free(i as u32) => {
-                    item.setup_video(&self.item_sprites, ctrl);
-                    Some(item)
-                }
-                () => Some(item),
-            })
-            .collect();
-        let mut layer = ctrl.layer(affine::Slot::_2);
-        layer.set_x_offset((console.frame as i32) * 2);
-        mem::drop(layer);
-
-        let mut layer = ctrl.layer(affine::Slot::_3);
-        layer.set_x_offset((console.frame as i32) * 5);
-        mem::drop(layer);
-
-        self.player.draw(ctrl);
-        self.bullets.iter().for_each(|bullet| bullet.draw(ctrl));
-        self.items.iter().for_each(|item| item.draw(ctrl));
-    }
     pub(crate) const fn start(
         selected_ship: Ship,
+        cheats: BitFlags<Cheats>,
+        controls: Controls,
         player_slot: object::Slot,
         bullet_sprites: sprite::SheetSlot<14>,
         item_sprites: sprite::SheetSlot<7>,
@@ -170,15 +229,23 @@ impl Space {
         Self {
             player: Player::new(player_slot, selected_ship),
             bullets: ArrayVec::new_const(),
-            new_bullets: Bitset128::DEFAULT,
             items: ArrayVec::new_const(),
-            new_items: Bitset8::DEFAULT,
             bullet_sprites,
             item_sprites,
             ship: selected_ship,
-            cheats: DEFAULT_CHEATS,
+            cheats,
+            cheat_codes: Matcher::new(CHEAT_CODES),
+            controls,
+            score: Score::new(selected_ship),
+            frames_played: 0,
         }
     }
+    pub(crate) const fn controls(&self) -> Controls {
+        self.controls
+    }
+    pub(crate) const fn score(&self) -> Score {
+        self.score
+    }
     pub(crate) fn setup_video(
         &self,
         ctrl: &mut video::Control<mode::Affine>,
@@ -206,8 +273,12 @@ impl Space {
         let ship = self.ship.asset();
         self.player.init_video(ctrl, console, &ship);
 
-        let rng = &mut console.rng;
+        let rng = &mut console.rng.cosmetic;
         background::generate_stars(rng, ctrl.sbb(STAR_SBB, background_size));
+        let stars_scroll = scanline::Target::AffineXOffset(affine::Slot::_2);
+        console
+            .scanline
+            .set(STARS_EFFECT, scanline::Effect::new(stars_scroll));
 
         let mut layer = ctrl.layer(affine::Slot::_3);
         layer.set_overflow(true);
@@ -218,4 +289,23 @@ impl Space {
         mem::drop(layer);
         background::generate_planets(rng, ctrl.sbb(PLANET_SBB, AffineSize::Base));
     }
+    /// Free all object slots and sprites used by the game.
+    pub(crate) fn teardown<M: Mode>(
+        self,
+        ctrl: &mut video::Control<M>,
+        console: &mut ConsoleState,
+    ) {
+        for bullet in self.bullets {
+            console.free_object(bullet.into_slot());
+        }
+        for item in self.items {
+            console.free_object(item.into_slot());
+        }
+        console.free_object(self.player.into_slot());
+        ctrl.unload_sprite_sheet(console, &assets::space::bullets::tiles);
+        ctrl.unload_sprite_sheet(console, &assets::space::items);
+        ctrl.unload_sprite(console, &self.ship.asset().sprite);
+        ctrl.reset_objects();
+        console.scanline.remove(STARS_EFFECT);
+    }
 }
@@ -4,6 +4,7 @@ use hal::{exec::Rng, video::tile::sbb, video::Pos};
 use crate::assets::space;
 
 const PLANET_COUNT: usize = 3;
+const _: () = assert!(space::star_count.is_power_of_two(), "Would bias star tiles");
 
 // algorithm: knowing we have a region.surface_size() tiles to fill, we
 // place N tiles on it, tiles are taken from three different sets:
@@ -22,6 +23,7 @@ pub(crate) fn generate_stars(rng: &mut Rng, mut sbb: sbb::AffineHandle) {
             .take(region.width() as usize)
             .map(|rand| {
                 // unwrap: never fails because % 16 will always be within range of u8
+                // Not biased, and not a division, since star_count is a power of two.
                 let tile: u8 = (rand % space::star_count).try_into().unwrap();
                 // True 1 time out of 16
                 let should_show = rand & 0b1111_0000 == 0b1111_0000;
@@ -55,3 +57,40 @@ pub(crate) fn generate_planets(rng: &mut Rng, mut sbb: sbb::AffineHandle) {
         sbb.set_tiles(Pos { x, y }, &&image);
     }
 }
+
+#[cfg(test)]
+mod test {
+    use core::mem;
+
+    use hal::{
+        exec::Rng,
+        host::Console,
+        video::{
+            self, colmod, mode,
+            tile::{cbb, layer::affine, map::AffineSize},
+            Layer,
+        },
+    };
+
+    use crate::{assets::space, game::STAR_SBB, test::golden};
+
+    #[test]
+    fn stars() {
+        let mut console = Console::new();
+        // SAFETY: only Control in this test.
+        let ctrl = unsafe { video::Control::<mode::Text>::init() };
+        let mut ctrl = ctrl.enter_mode::<mode::Affine>();
+        ctrl.enable_layer(Layer::<mode::Affine>::_2);
+        ctrl.load_palette(space::background_pal.get());
+        ctrl.load_tileset(cbb::Slot::_0, &space::background);
+        let mut layer = ctrl.layer(affine::Slot::_2);
+        layer.set_sbb(STAR_SBB);
+        layer.set_color_mode::<colmod::Bit8>();
+        layer.set_size(AffineSize::Double);
+        mem::drop(layer);
+
+        let mut rng = Rng::new(0xDEAD_BEEF);
+        super::generate_stars(&mut rng, ctrl.sbb(STAR_SBB, AffineSize::Double));
+        console.render().assert_golden(golden("stars"));
+    }
+}
@@ -1,5 +1,5 @@
 use const_default::ConstDefault;
-use hal::video::{self, mode, object, object::sprite, palette};
+use hal::video::{command, object, object::sprite, palette};
 
 use crate::assets::space::bullets::Bullets;
 use crate::collide::{Collide, Shape};
@@ -32,7 +32,7 @@ impl Bullet {
         mut velocity: Posi,
         slot: object::Slot,
     ) -> Self {
-        hal::info!("Player: {player:?} is spawning a bullet");
+        hal::debug!("Player: {player:?} is spawning a bullet");
         velocity += Posi::x(1);
         let fix = velocity == Posi::DEFAULT;
         let velocity = if fix { Posi::x(1) } else { velocity };
@@ -68,24 +68,24 @@ impl Bullet {
         !SCREEN_AREA.overlaps(self)
     }
 
-    pub(crate) fn draw(&self, ctrl: &mut video::Control<mode::Affine>) {
-        let mut ctrl = ctrl.object(&self.slot);
+    pub(crate) fn draw(&self, commands: &mut command::Buffer) {
         if let Ok(pos) = self.pos.try_into() {
-            ctrl.set_pos(pos);
+            commands.object(&self.slot, command::Object::Pos(pos));
         }
     }
     pub(crate) fn setup_video(
         &self,
         sheet: &sprite::SheetSlot<14>,
-        ctrl: &mut video::Control<mode::Affine>,
+        commands: &mut command::Buffer,
     ) {
-        let mut ctrl = ctrl.object(&self.slot);
-        if let Ok(pos) = self.pos.try_into() {
-            ctrl.set_pos(pos);
-        }
-        ctrl.set_sprite(sheet.get(Bullets::from(self.kind) as u16));
-        ctrl.set_palette_mode(palette::Type::Full);
-        ctrl.set_visible(true);
+        self.draw(commands);
+        let sprite = sheet.get(Bullets::from(self.kind) as u16);
+        commands.object(&self.slot, command::Object::Sprite(sprite));
+        commands.object(
+            &self.slot,
+            command::Object::PaletteMode(palette::Type::Full),
+        );
+        commands.object(&self.slot, command::Object::Visible(true));
     }
 }
 struct Damage(u8);
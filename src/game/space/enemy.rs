@@ -2,7 +2,12 @@ use hal::exec::Rng;
 
 use crate::assets::space::Ships;
 
+/// How many kinds of enemy ships there are, see [`Ships::try_from_u8`].
+const ENEMY_KINDS: u32 = 15;
+
 fn random_enemy(rng: &mut Rng) -> Ships {
-    let random = (rng.u64() % 16) as u8;
+    // allow: `ENEMY_KINDS` fits in a `u8`.
+    #[allow(clippy::cast_possible_truncation)]
+    let random = rng.below(ENEMY_KINDS) as u8;
     Ships::try_from_u8(random).unwrap_or(Ships::SmallGreen1)
 }
@@ -1,7 +1,7 @@
 //! Items that allow player to heal and change weapon.
 
 use hal::info;
-use hal::video::{self, mode, object, object::sprite, palette};
+use hal::video::{command, object, object::sprite, palette};
 
 use crate::collide::{Collide, Shape};
 use crate::game::{
@@ -49,35 +49,34 @@ impl Item {
     pub(crate) const fn into_slot(self) -> object::Slot {
         self.slot
     }
-    pub(crate) fn draw(&self, ctrl: &mut video::Control<mode::Affine>) {
-        let mut ctrl = ctrl.object(&self.slot);
+    pub(crate) fn draw(&self, commands: &mut command::Buffer) {
         if let Ok(pos) = self.pos.try_into() {
-            ctrl.set_pos(pos);
+            commands.object(&self.slot, command::Object::Pos(pos));
         }
     }
-    pub(crate) fn setup_video(
-        &self,
-        sheet: &sprite::SheetSlot<7>,
-        ctrl: &mut video::Control<mode::Affine>,
-    ) {
-        let mut ctrl = ctrl.object(&self.slot);
-        if let Ok(pos) = self.pos.try_into() {
-            ctrl.set_pos(pos);
-        }
-        ctrl.set_sprite(sheet.get(self.kind.sprite_sheet_entry()));
-        ctrl.set_palette_mode(palette::Type::Full);
-        ctrl.set_visible(true);
+    pub(crate) fn setup_video(&self, sheet: &sprite::SheetSlot<7>, commands: &mut command::Buffer) {
+        self.draw(commands);
+        let sprite = sheet.get(self.kind.sprite_sheet_entry());
+        commands.object(&self.slot, command::Object::Sprite(sprite));
+        commands.object(
+            &self.slot,
+            command::Object::PaletteMode(palette::Type::Full),
+        );
+        commands.object(&self.slot, command::Object::Visible(true));
     }
     // NOTE: this `Item::update` may also update the player, I know this sucks
     // but hell, unless going full ECS, I don't see an alternative to this wonky
     // sharing of responsabilities
-    pub(crate) fn update(&mut self, player: &mut Player) {
+    /// Returns whether the player picked up the item.
+    pub(crate) fn update(&mut self, player: &mut Player) -> bool {
         self.pos.x -= 1;
-        if player.overlaps(self) {
+        let picked_up = player.overlaps(self);
+        if picked_up {
             info!("player picked up item: {self:?}");
             self.should_die = true;
             player.pick_up(self.kind);
         }
+        picked_up
     }
     pub(crate) const fn should_die(&self) -> bool {
         self.pos.x < 0 || self.should_die
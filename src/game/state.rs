@@ -2,6 +2,14 @@
 pub(crate) enum Transition {
     Stay,
     EnterGame,
+    /// Pause the game, see [`super::pause::Pause`].
+    Pause,
+    /// Leave the pause screen.
+    Resume,
+    /// Give up the game from the pause screen, see [`super::highscore`].
+    GameOver,
+    /// Initials were entered, show the high score table.
+    HighScores,
 }
 
 // TODO: probably
@@ -1,6 +1,4 @@
-// TODO: Design a generic hierarchical state machine to build the game around them
-#![no_std]
-#![no_main]
+#![cfg_attr(not(test), no_std, no_main)]
 #![warn(clippy::pedantic, clippy::nursery)]
 #![allow(clippy::redundant_pub_crate, clippy::match_bool)]
 #![feature(const_mut_refs, const_replace)]
@@ -11,105 +9,255 @@ mod game;
 mod text;
 
 use const_default::ConstDefault;
-use hal::exec::{full_game, panic_handler, EnterMode, GameState, GameStateEnterMode};
+use enumflags2::BitFlags;
+use hal::exec::{
+    panic_handler,
+    scene::{Entry, Scene, Stack, Transition},
+};
 use hal::{
-    exec::ConsoleState,
-    video::{
-        self, colmod, mode,
-        tile::{cbb, layer},
-        Layer,
-    },
+    exec::{ConsoleState, Runner},
+    log::Level,
+    video::{self, mode, Mode},
 };
 
-use game::{
-    mainmenu::{Mainmenu, TITLE_SCREEN_SBB},
-    state::Transition,
-};
+use game::{action::Controls, highscore, mainmenu::Mainmenu, space::Cheats, state, Ship};
 
+#[cfg(not(test))]
 #[panic_handler]
 fn panic(info: &core::panic::PanicInfo) -> ! {
     panic_handler(info)
 }
 
+/// Maximum depth of the [`Screen`] stack, plus one for [`Transition::Replace`].
+const MAX_SCREENS: usize = 3;
+
 // TODO: devildahu logo + rust logo
 // allow: We assume there is exactly a single instance of `Screen` avaiable
 // at the same time.
 #[allow(clippy::large_enum_variant)]
 enum Screen {
     Mainmenu(Mainmenu),
+    /// The game was just started with given ship, cheats and controls, waiting
+    /// for the next vblank to load sprites and become a [`Screen::Space`].
+    NewGame(Ship, BitFlags<Cheats>, Controls),
     Space(game::Space),
+    /// The game is paused, on top of a [`Screen::Space`], drawing an overlay
+    /// in mixed mode.
+    Pause(game::Pause),
+    /// The game ended with a high score, the player enters their initials.
+    Initials(game::Initials),
 }
-struct State {
-    screen: Screen,
+impl Screen {
+    /// The screen after giving up the game from `pause`.
+    fn game_over(pause: &game::Pause) -> Self {
+        let table = highscore::load();
+        match game::Initials::new(pause.score(), table, pause.controls()) {
+            Some(initials) => Self::Initials(initials),
+            None => Self::Mainmenu(Mainmenu::DEFAULT),
+        }
+    }
 }
-impl GameState for State {
-    fn logic(&mut self, console: &mut ConsoleState) -> Option<GameStateEnterMode<Self>> {
-        match &mut self.screen {
-            Screen::Mainmenu(mainmenu) => {
-                let result = mainmenu.logic(console);
-                if Transition::EnterGame == result {
-                    return Some(EnterMode::Affine(|ctrl, state, console| {
-                        let ship = match &state.screen {
-                            Screen::Mainmenu(mainmenu) => mainmenu.selected_ship,
-                            Screen::Space(_) => return,
-                        };
-                        // TODO: unwrap
-                        let slot = match console.reserve_object() {
-                            Some(slot) => slot,
-                            None => return,
-                        };
-                        let bullets = ctrl
-                            .load_sprite_sheet(console, &assets::space::bullets::tiles)
-                            .unwrap();
-                        let items = ctrl
-                            .load_sprite_sheet(console, &assets::space::items)
-                            .unwrap();
-                        state.screen =
-                            Screen::Space(game::Space::start(ship, slot, bullets, items));
-                        if let Self { screen: Screen::Space(space) } = state {
-                            space.setup_video(ctrl, console);
-                        }
-                    }));
-                };
+impl Scene for Screen {
+    fn mode(&self) -> mode::Type {
+        match self {
+            Self::Mainmenu(_) | Self::Initials(_) => mode::Type::Text,
+            Self::Pause(_) => mode::Type::Mixed,
+            Self::NewGame(..) | Self::Space(_) => mode::Type::Affine,
+        }
+    }
+
+    fn logic(&mut self, console: &mut ConsoleState) -> Transition<Self> {
+        #[cfg(feature = "profile")]
+        console.every(0, 1 << 7, |console| console.profiler.log());
+        match self {
+            Self::Mainmenu(mainmenu) => match mainmenu.logic(console) {
+                state::Transition::EnterGame => {
+                    let Mainmenu { selected_ship, cheats, controls, .. } = *mainmenu;
+                    Transition::Replace(Self::NewGame(selected_ship, cheats, controls))
+                }
+                _ => Transition::Stay,
+            },
+            Self::NewGame(..) => Transition::Stay,
+            Self::Space(space) => match space.update(console) {
+                state::Transition::Pause => {
+                    let pause = game::Pause::new(space.controls(), space.score());
+                    Transition::Push(Self::Pause(pause))
+                }
+                _ => Transition::Stay,
+            },
+            Self::Pause(pause) => match pause.logic(console) {
+                state::Transition::Resume => Transition::Pop,
+                state::Transition::GameOver => Transition::Restart(Self::game_over(pause)),
+                _ => Transition::Stay,
+            },
+            Self::Initials(initials) => match initials.logic(console) {
+                state::Transition::HighScores => {
+                    Transition::Restart(Self::Mainmenu(Mainmenu::HIGH_SCORES))
+                }
+                _ => Transition::Stay,
+            },
+        }
+    }
+
+    fn soft_reset(&mut self, _: &mut ConsoleState) -> Option<Transition<Self>> {
+        match self {
+            Self::Mainmenu(_) => None,
+            // Keep the high score, with the initials entered so far.
+            Self::Initials(initials) => {
+                initials.save();
+                Some(Transition::Restart(Self::Mainmenu(Mainmenu::DEFAULT)))
             }
-            Screen::Space(space) => {
-                space.update(console);
+            // Return to the title screen, rather than resetting the console.
+            Self::NewGame(..) | Self::Space(_) | Self::Pause(_) => {
+                Some(Transition::Restart(Self::Mainmenu(Mainmenu::DEFAULT)))
             }
         }
-        None
     }
 
-    fn text_draw(&mut self, console: &mut ConsoleState, ctrl: &mut video::Control<mode::Text>) {
-        if let Screen::Mainmenu(mainmenu) = &self.screen {
-            mainmenu.text_draw(console, ctrl);
+    fn text_enter(
+        &mut self,
+        _: Entry,
+        console: &mut ConsoleState,
+        ctrl: &mut video::Control<mode::Text>,
+    ) {
+        match self {
+            Self::Mainmenu(mainmenu) => mainmenu.setup_video(ctrl),
+            Self::Initials(initials) => initials.setup_video(console, ctrl),
+            Self::NewGame(..) | Self::Space(_) | Self::Pause(_) => {}
+        }
+    }
+
+    fn mixed_enter(
+        &mut self,
+        _: Entry,
+        _: &mut ConsoleState,
+        ctrl: &mut video::Control<mode::Mixed>,
+    ) {
+        match self {
+            Self::Pause(_) => game::Pause::setup_video(ctrl),
+            Self::Mainmenu(_) | Self::NewGame(..) | Self::Space(_) | Self::Initials(_) => {}
+        }
+    }
+
+    fn affine_enter(
+        &mut self,
+        _: Entry,
+        console: &mut ConsoleState,
+        ctrl: &mut video::Control<mode::Affine>,
+    ) {
+        let (ship, cheats, controls) = match self {
+            Self::NewGame(ship, cheats, controls) => (*ship, *cheats, *controls),
+            Self::Mainmenu(_) | Self::Space(_) | Self::Pause(_) | Self::Initials(_) => return,
+        };
+        // No other scene holds objects when a game starts, so this only fails on a bug.
+        let slot = console
+            .reserve_object()
+            .expect("No free object slot for the player ship");
+        let bullets = ctrl
+            .load_sprite_sheet(console, &assets::space::bullets::tiles)
+            .unwrap();
+        let items = ctrl
+            .load_sprite_sheet(console, &assets::space::items)
+            .unwrap();
+        let space = game::Space::start(ship, cheats, controls, slot, bullets, items);
+        space.setup_video(ctrl, console);
+        *self = Self::Space(space);
+    }
+
+    fn exit<M: Mode>(self, console: &mut ConsoleState, ctrl: &mut video::Control<M>) {
+        if let Self::Space(space) = self {
+            space.teardown(ctrl, console);
         }
     }
-    fn affine_draw(&mut self, console: &mut ConsoleState, ctrl: &mut video::Control<mode::Affine>) {
-        if let Screen::Space(space) = &mut self.screen {
-            space.affine_draw(console, ctrl);
+
+    fn text_draw(&mut self, console: &mut ConsoleState, ctrl: &mut video::Control<mode::Text>) {
+        match self {
+            Self::Mainmenu(mainmenu) => mainmenu.text_draw(console, ctrl),
+            Self::Initials(initials) => initials.text_draw(console, ctrl),
+            Self::NewGame(..) | Self::Space(_) | Self::Pause(_) => {}
         }
     }
 }
 
-#[no_mangle]
+#[cfg_attr(not(test), no_mangle)]
 pub fn main() -> ! {
-    // SAFETY: I, Nicola Papale, solemnly promise that I will not
-    // call video::Control::init or full_game until this video_control
-    // instance is dropped.
-    let mut video_control = unsafe { video::Control::<mode::Text>::init() };
-    video_control.reset_display_control();
-    video_control.load_tileset(cbb::Slot::_0, &assets::menu::set);
-    video_control.load_palette(assets::menu::palette.get());
-    video_control.enable_layer(Layer::<mode::Text>::_0);
     hal::warn!("babbooon metal world");
+    // Bullets and items spawn every few frames, don't flood the log with them.
+    hal::log::set_module_level(concat!(module_path!(), "::game::space"), Some(Level::Info));
+    let screens = Stack::<Screen, MAX_SCREENS>::new(Screen::Mainmenu(Mainmenu::DEFAULT));
+    // SAFETY: I, Nicola Papale, solemnly promise that I will not
+    // call video::Control::init while the game runs.
+    let mut runner = unsafe { Runner::new(screens) };
+    #[cfg(feature = "profile")]
     {
-        let mut layer = video_control.layer(layer::text::Slot::_0);
-        layer.set_color_mode::<colmod::Bit8>();
-        layer.set_sbb(TITLE_SCREEN_SBB);
+        let enabled = runner.console_mut().enable_profiler();
+        hal::error!((!enabled) "Couldn't enable the profiler, timers 2 and 3 are in use");
+    }
+    let audio = runner.console_mut().enable_audio();
+    hal::error!((!audio) "Couldn't enable audio, timer 0 is in use");
+    runner.console_mut().psg.enable();
+    // Same as `full_game`, with the console set up first.
+    loop {
+        runner.step();
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use const_default::ConstDefault;
+    use hal::{
+        exec::{reset, GameState, Runner},
+        host::Console,
+        input::{Dir, Key},
+    };
+
+    use super::{Mainmenu, Screen, Stack, MAX_SCREENS};
+    use crate::game::{action::DEFAULT_CONTROLS, highscore, Initials, Ship};
+
+    pub(crate) fn golden(name: &str) -> String {
+        format!("{}/golden/{name}.png", env!("CARGO_MANIFEST_DIR"))
+    }
+
+    fn tap(console: &mut Console, runner: &mut Runner<impl GameState>, key: Key) {
+        console.press(key);
+        runner.step();
+        console.release_all();
+        runner.step();
+    }
+
+    #[test]
+    fn mainmenu_screens() {
+        let mut console = Console::new();
+        let screens = Stack::<Screen, MAX_SCREENS>::new(Screen::Mainmenu(Mainmenu::DEFAULT));
+        // SAFETY: only Runner in this test, and the console is alive.
+        let mut runner = unsafe { Runner::new(screens) };
+        runner.step();
+        console.render().assert_golden(golden("mainmenu_title"));
+
+        tap(&mut console, &mut runner, Key::A);
+        console.render().assert_golden(golden("mainmenu_main"));
+
+        tap(&mut console, &mut runner, Key::Dpad(Dir::Down));
+        tap(&mut console, &mut runner, Key::A);
+        console
+            .render()
+            .assert_golden(golden("mainmenu_ship_select"));
+    }
+
+    #[test]
+    fn soft_reset_keeps_high_score() {
+        let mut console = Console::new();
+        let mut score = highscore::Score::new(Ship::Spear);
+        score.points = 50;
+        let initials = Initials::new(score, highscore::load(), DEFAULT_CONTROLS).unwrap();
+        let screens = Stack::<Screen, MAX_SCREENS>::new(Screen::Initials(initials));
+        // SAFETY: only Runner in this test, and the console is alive.
+        let mut runner = unsafe { Runner::new(screens) };
+        runner.step();
+        console.press(reset::STANDARD_KEYS);
+        runner.step();
+        assert!(matches!(runner.state().top(), Some(Screen::Mainmenu(_))));
+        // Ties rank after the saved score.
+        assert_eq!(highscore::load().rank(50), Some(1));
     }
-    let mut mainmenu = Mainmenu::DEFAULT;
-    game::mainmenu::init_menu(&mut mainmenu.data, &mut video_control);
-    let state = State { screen: Screen::Mainmenu(mainmenu) };
-    // TODO move logic from top to here
-    unsafe { full_game(state) };
 }
@@ -0,0 +1,344 @@
+//! GBA Direct Memory Access (DMA) transfers, see [`Channel`].
+//!
+//! Only available with the `dma` feature, and only makes sense when running
+//! on a GBA, since it writes to the GBA DMA registers. With the `host`
+//! feature, [`Timing::Immediate`] transfers are done in software instead,
+//! for testing on a regular computer with simulated GBA memory.
+//!
+//! The GBA has four DMA channels, with decreasing priority. DMA transfers
+//! halt the CPU until they complete, and are much faster than a CPU copy loop.
+//!
+//! | Channel | Typical use | Max count |
+//! |:-------:|:-----------:|:---------:|
+//! | [`Channel::_0`] | Time critical (HBlank) transfers, can't read the cartridge | `0x4000` |
+//! | [`Channel::_1`], [`Channel::_2`] | Direct Sound FIFO | `0x4000` |
+//! | [`Channel::_3`] | General purpose copies | `0x1_0000` |
+//!
+//! The [`copy_words`], [`copy_halfwords`], [`fill_words`] and [`fill_halfwords`]
+//! functions use [`Channel::_3`] with [`Timing::Immediate`].
+//! Avoid using them in interrupt handlers, since an interrupt may happen while
+//! [`Channel::_3`] registers are being set up for another transfer.
+use core::mem;
+
+use voladdress::{Safe, VolAddress};
+
+const DMA_ADDR_USIZE: usize = 0x0400_00B0;
+const CHANNEL_STRIDE: usize = 12;
+/// Max units per DMA3 transfer, a count of `0` means `0x1_0000`.
+const MAX_DMA3_COUNT: usize = 0x1_0000;
+
+/// A DMA channel.
+///
+/// See [module-level doc](self) for the specifics of each channel.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+#[repr(usize)]
+pub enum Channel {
+    /// Highest priority channel.
+    _0 = 0,
+    /// Usually used for Direct Sound FIFO A.
+    _1 = 1,
+    /// Usually used for Direct Sound FIFO B.
+    _2 = 2,
+    /// General purpose channel.
+    _3 = 3,
+}
+
+/// When a DMA transfer starts.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+#[repr(u16)]
+pub enum Timing {
+    /// Start right away.
+    Immediate = 0,
+    /// Start at the beginning of vblank.
+    VBlank = 1,
+    /// Start at the beginning of each hblank.
+    HBlank = 2,
+    /// Channel-specific: sound FIFO for channels 1 and 2, video capture for 3.
+    Special = 3,
+}
+
+/// How an address changes after each unit transferred.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+#[repr(u16)]
+pub enum Step {
+    /// Go to next unit.
+    Increment = 0,
+    /// Go to previous unit.
+    Decrement = 1,
+    /// Stay at the same address, useful for filling or writing to a register.
+    Fixed = 2,
+    /// Increment, but reset to the initial address when the transfer repeats.
+    ///
+    /// Only valid for the destination address.
+    IncrementReload = 3,
+}
+
+/// Size of a single transferred unit.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+#[repr(u16)]
+pub enum Unit {
+    /// 16 bits.
+    HalfWord = 0,
+    /// 32 bits.
+    Word = 1,
+}
+
+/// A DMA control register value, describing a transfer.
+///
+/// Built with [`Control::new`] and the `with_*` methods.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct Control(u16);
+impl Control {
+    const ENABLE: u16 = 1 << 15;
+
+    /// An enabled, non-repeating transfer of `unit`s starting at `timing`,
+    /// incrementing both source and destination addresses.
+    #[must_use]
+    pub const fn new(unit: Unit, timing: Timing) -> Self {
+        Self(Self::ENABLE | (unit as u16) << 10 | (timing as u16) << 12)
+    }
+    /// Set how the destination address changes after each unit.
+    #[must_use]
+    pub const fn with_dest(self, step: Step) -> Self {
+        Self(self.0 & !(0b11 << 5) | (step as u16) << 5)
+    }
+    /// Set how the source address changes after each unit.
+    ///
+    /// [`Step::IncrementReload`] is invalid here, and treated as [`Step::Increment`].
+    #[must_use]
+    pub const fn with_source(self, step: Step) -> Self {
+        let step = match step {
+            Step::IncrementReload => Step::Increment,
+            step => step,
+        };
+        Self(self.0 & !(0b11 << 7) | (step as u16) << 7)
+    }
+    /// Set whether to repeat the transfer at each `timing` event.
+    ///
+    /// Repeating transfers only stop when [`Channel::stop`] is called.
+    #[must_use]
+    pub const fn with_repeat(self, repeat: bool) -> Self {
+        Self(self.0 & !(1 << 9) | (repeat as u16) << 9)
+    }
+    /// Set whether to request an interrupt when the transfer completes.
+    #[must_use]
+    pub const fn with_irq(self, irq: bool) -> Self {
+        Self(self.0 & !(1 << 14) | (irq as u16) << 14)
+    }
+    /// The raw register value.
+    #[must_use]
+    pub const fn bits(self) -> u16 {
+        self.0
+    }
+}
+
+impl Channel {
+    const fn base(self) -> usize {
+        DMA_ADDR_USIZE + self as usize * CHANNEL_STRIDE
+    }
+    const fn source_register(self) -> VolAddress<usize, (), Safe> {
+        // SAFETY: DMAxSAD is a write-only 32 bits register.
+        unsafe { VolAddress::new(self.base()) }
+    }
+    const fn dest_register(self) -> VolAddress<usize, (), Safe> {
+        // SAFETY: DMAxDAD is a write-only 32 bits register.
+        unsafe { VolAddress::new(self.base() + 4) }
+    }
+    const fn count_register(self) -> VolAddress<u16, (), Safe> {
+        // SAFETY: DMAxCNT_L is a write-only 16 bits register.
+        unsafe { VolAddress::new(self.base() + 8) }
+    }
+    const fn control_register(self) -> VolAddress<u16, Safe, Safe> {
+        // SAFETY: DMAxCNT_H is a read/write 16 bits register.
+        unsafe { VolAddress::new(self.base() + 10) }
+    }
+
+    /// Start a transfer of `count` units from `source` to `dest`.
+    ///
+    /// With [`Timing::Immediate`], the CPU is halted until the transfer
+    /// completes. Otherwise, the transfer starts at the next `timing` event.
+    ///
+    /// A `count` of `0` means the maximum count of this channel.
+    ///
+    /// # Safety
+    ///
+    /// - `source` must be valid for reads and `dest` for writes of `count` units,
+    ///   accounting for their respective [`Step`].
+    /// - Both must be aligned to the size of the [`Unit`].
+    /// - [`Channel::_0`] can't read from the cartridge.
+    /// - The memory must stay valid for as long as the transfer is active,
+    ///   which is forever for repeating transfers, unless [`Channel::stop`] is
+    ///   called.
+    pub unsafe fn start(self, source: usize, dest: usize, count: u16, control: Control) {
+        self.stop();
+        self.source_register().write(source);
+        self.dest_register().write(dest);
+        self.count_register().write(count);
+        self.control_register().write(control.bits());
+        #[cfg(feature = "host")]
+        if control.bits() & (0b11 << 12) == (Timing::Immediate as u16) << 12 {
+            // SAFETY: upheld by function safety invariants.
+            unsafe { host_transfer(self, source, dest, count, control) };
+            self.stop();
+        }
+    }
+    /// Stop the current transfer on this channel, if any.
+    pub fn stop(self) {
+        self.control_register().write(0);
+    }
+    /// Whether a transfer is active on this channel.
+    ///
+    /// Repeating transfers are always active until stopped.
+    #[must_use]
+    pub fn is_active(self) -> bool {
+        self.control_register().read() & Control::ENABLE != 0
+    }
+}
+
+/// Do the transfer `Channel::start` would, in software.
+///
+/// # Safety
+///
+/// See [`Channel::start`].
+#[cfg(feature = "host")]
+unsafe fn host_transfer(
+    channel: Channel,
+    source: usize,
+    dest: usize,
+    count: u16,
+    control: Control,
+) {
+    let bits = control.bits();
+    let unit_size = match bits & (1 << 10) {
+        0 => mem::size_of::<u16>(),
+        _ => mem::size_of::<u32>(),
+    };
+    let count = match (count, channel) {
+        (0, Channel::_3) => MAX_DMA3_COUNT,
+        (0, _) => 0x4000,
+        (count, _) => usize::from(count),
+    };
+    let step = |offset: u16| match (bits >> offset) & 0b11 {
+        1 => unit_size.wrapping_neg(),
+        2 => 0,
+        _ => unit_size,
+    };
+    let (source_step, dest_step) = (step(7), step(5));
+    let (mut source, mut dest) = (source, dest);
+    for _ in 0..count {
+        // SAFETY: upheld by function safety invariants.
+        unsafe {
+            if unit_size == mem::size_of::<u32>() {
+                let value = (source as *const u32).read_volatile();
+                (dest as *mut u32).write_volatile(value);
+            } else {
+                let value = (source as *const u16).read_volatile();
+                (dest as *mut u16).write_volatile(value);
+            }
+        }
+        source = source.wrapping_add(source_step);
+        dest = dest.wrapping_add(dest_step);
+    }
+}
+
+/// Transfer `count` units with DMA3, splitting in as many transfers as needed.
+///
+/// # Safety
+///
+/// See [`Channel::start`].
+unsafe fn dma3(source: usize, dest: usize, count: usize, control: Control) {
+    let unit_size = match control.bits() & (1 << 10) {
+        0 => mem::size_of::<u16>(),
+        _ => mem::size_of::<u32>(),
+    };
+    let fixed_source = control.bits() & (0b11 << 7) == (Step::Fixed as u16) << 7;
+    let mut done = 0;
+    while done < count {
+        let chunk = (count - done).min(MAX_DMA3_COUNT);
+        let source = if fixed_source {
+            source
+        } else {
+            source + done * unit_size
+        };
+        // allow: chunk is at most 0x1_0000, which truncates to 0, meaning 0x1_0000.
+        #[allow(clippy::cast_possible_truncation)]
+        let chunk_count = chunk as u16;
+        // SAFETY: upheld by function safety invariants.
+        unsafe { Channel::_3.start(source, dest + done * unit_size, chunk_count, control) };
+        done += chunk;
+    }
+}
+
+/// Copy `count` words from `source` to `dest` using DMA3.
+///
+/// # Safety
+///
+/// `source` must be valid for reads and `dest` for writes of `count` words,
+/// both must be 4 bytes aligned.
+pub unsafe fn copy_words(source: *const u32, dest: *mut u32, count: usize) {
+    let control = Control::new(Unit::Word, Timing::Immediate);
+    // SAFETY: upheld by function safety invariants.
+    unsafe { dma3(source as usize, dest as usize, count, control) };
+}
+
+/// Copy `count` half words from `source` to `dest` using DMA3.
+///
+/// # Safety
+///
+/// `source` must be valid for reads and `dest` for writes of `count` half words,
+/// both must be 2 bytes aligned.
+pub unsafe fn copy_halfwords(source: *const u16, dest: *mut u16, count: usize) {
+    let control = Control::new(Unit::HalfWord, Timing::Immediate);
+    // SAFETY: upheld by function safety invariants.
+    unsafe { dma3(source as usize, dest as usize, count, control) };
+}
+
+/// Write `value` to `count` words starting at `dest` using DMA3.
+///
+/// # Safety
+///
+/// `dest` must be valid for writes of `count` words, and 4 bytes aligned.
+pub unsafe fn fill_words(value: u32, dest: *mut u32, count: usize) {
+    let control = Control::new(Unit::Word, Timing::Immediate).with_source(Step::Fixed);
+    let source: *const u32 = &value;
+    // SAFETY: `value` lives until the end of the function, and immediate
+    // transfers complete before `dma3` returns.
+    unsafe { dma3(source as usize, dest as usize, count, control) };
+}
+
+/// Write `value` to `count` half words starting at `dest` using DMA3.
+///
+/// # Safety
+///
+/// `dest` must be valid for writes of `count` half words, and 2 bytes aligned.
+pub unsafe fn fill_halfwords(value: u16, dest: *mut u16, count: usize) {
+    let control = Control::new(Unit::HalfWord, Timing::Immediate).with_source(Step::Fixed);
+    let source: *const u16 = &value;
+    // SAFETY: `value` lives until the end of the function, and immediate
+    // transfers complete before `dma3` returns.
+    unsafe { dma3(source as usize, dest as usize, count, control) };
+}
+
+/// Copy `bytes` bytes from `source` to `dest` with DMA3, using the largest
+/// unit allowed by the alignment of `source`, `dest` and `bytes`.
+///
+/// Returns `false` without copying anything if the copy can't be done with
+/// DMA (not even 2 bytes aligned).
+///
+/// # Safety
+///
+/// `source` must be valid for reads and `dest` for writes of `bytes` bytes.
+pub(crate) unsafe fn copy_bytes(source: usize, dest: usize, bytes: usize) -> bool {
+    let alignment = source | dest | bytes;
+    if alignment % 4 == 0 {
+        // SAFETY: upheld by function safety invariants, and checked alignment.
+        unsafe { copy_words(source as *const u32, dest as *mut u32, bytes / 4) };
+        true
+    } else if alignment % 2 == 0 {
+        // SAFETY: upheld by function safety invariants, and checked alignment.
+        unsafe { copy_halfwords(source as *const u16, dest as *mut u16, bytes / 2) };
+        true
+    } else {
+        false
+    }
+}
@@ -1,4 +1,6 @@
 //! Extensions to the [`voladdress`] crate, including [`VolMatrix`] and [`VolMemcopy`].
+//!
+//! With the `dma` feature, also includes the GBA-specific `dma` module.
 #![no_std]
 #![warn(clippy::pedantic, clippy::nursery)]
 #![forbid(missing_docs)]
@@ -10,6 +12,8 @@
 #![cfg_attr(feature = "nightly", allow(incomplete_features))]
 #![cfg_attr(feature = "nightly", feature(generic_const_exprs))]
 
+#[cfg(feature = "dma")]
+pub mod dma;
 mod volmatrix;
 mod volmemcopy;
 
@@ -1,10 +1,17 @@
+#[cfg(feature = "dma")]
+use core::mem;
+
 use voladdress::{Safe, VolBlock};
 
+/// Transfers smaller than this are not worth setting up a DMA transfer.
+#[cfg(feature = "dma")]
+const DMA_MIN_BYTES: usize = 64;
+
 /// Extension trait to [`VolBlock`] for bulk volatile load/store.
 ///
-/// Currently just a very basic call to `ptr::write_volatile`, but later may
-/// use optimized intrisics (currently want to get it working, AND need to
-/// define myself the optimized intrisics, so it's not ready tomorrow)
+/// With the `dma` feature, transfers of at least 64 bytes with 2 or 4 bytes
+/// alignment use [`crate::dma`]. Otherwise, this is a loop of volatile
+/// reads or writes.
 ///
 /// [`VolMatrix`]: crate::VolMatrix
 pub trait VolMemcopy<T>: Sized {
@@ -37,6 +44,18 @@ pub trait VolMemcopy<T>: Sized {
 }
 impl<T: Copy, const C: usize> VolMemcopy<T> for VolBlock<T, Safe, Safe, C> {
     fn write_slice_at_offset(self, offset: usize, slice: &[T]) {
+        #[cfg(feature = "dma")]
+        if let Some(store) = self.get(offset) {
+            let bytes = mem::size_of_val(slice).min((C - offset) * mem::size_of::<T>());
+            // SAFETY: `bytes` fits both in `slice` and the store after `offset`.
+            let copied = bytes >= DMA_MIN_BYTES
+                && unsafe {
+                    crate::dma::copy_bytes(slice.as_ptr() as usize, store.as_usize(), bytes)
+                };
+            if copied {
+                return;
+            }
+        }
         for (i, elem) in slice.iter().enumerate() {
             if let Some(addr) = self.get(i + offset) {
                 addr.write(*elem);
@@ -51,6 +70,18 @@ impl<T: Copy, const C: usize> VolMemcopy<T> for VolBlock<T, Safe, Safe, C> {
         // iter.for_each(|(addr, value)| addr.write(*value));
     }
     fn read_offset_into_slice(self, offset: usize, slice: &mut [T]) {
+        #[cfg(feature = "dma")]
+        if let Some(store) = self.get(offset) {
+            let bytes = mem::size_of_val(slice).min((C - offset) * mem::size_of::<T>());
+            // SAFETY: `bytes` fits both in `slice` and the store after `offset`.
+            let copied = bytes >= DMA_MIN_BYTES
+                && unsafe {
+                    crate::dma::copy_bytes(store.as_usize(), slice.as_mut_ptr() as usize, bytes)
+                };
+            if copied {
+                return;
+            }
+        }
         let iter = self.iter().skip(offset).zip(slice.iter_mut());
         iter.for_each(|(addr, value)| *value = addr.read());
     }